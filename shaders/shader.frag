@@ -1,7 +1,9 @@
 #version 450
 
+layout (location = 0) in vec4 frag_color;
+
 layout (location = 0) out vec4 Color;
 
 void main() {
-    Color = vec4(1.0, 0.0, 0.0, 1.0);
+    Color = frag_color;
 }
@@ -1,6 +1,13 @@
 #version 450
 
+layout (location = 0) in vec3 position;
+layout (location = 1) in vec3 normal;
+layout (location = 2) in vec2 uv;
+layout (location = 3) in vec4 color;
+
+layout (location = 0) out vec4 frag_color;
+
 void main() {
-  gl_PointSize = 2.0;
-  gl_Position = vec4(0.0, 0.0, 0.0, 1.0);
+  gl_Position = vec4(position, 1.0);
+  frag_color = color;
 }
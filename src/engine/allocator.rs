@@ -0,0 +1,1049 @@
+use std::{fmt, ptr, slice};
+
+use ash::vk;
+
+use super::error::{EngineError, VkResultExt};
+
+/// size of the `vk::DeviceMemory` blocks resources are sub-allocated from, smaller heaps get
+/// an eighth of their size instead
+pub const DEFAULT_BLOCK_SIZE: u64 = 64 * 1024 * 1024;
+
+/// where the memory of a resource should live, decides the memory type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryLocation {
+    /// device local, the cpu can't see it (images, vertex buffers filled through staging)
+    GpuOnly,
+    /// host visible and coherent, mapped for the whole lifetime (staging and uniform buffers)
+    CpuToGpu,
+    /// like `CpuToGpu`, but preferably cached so reading it back is fast
+    GpuToCpu,
+}
+
+impl MemoryLocation {
+    fn required_flags(self) -> vk::MemoryPropertyFlags {
+        match self {
+            MemoryLocation::GpuOnly => vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            MemoryLocation::CpuToGpu | MemoryLocation::GpuToCpu => {
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT
+            }
+        }
+    }
+
+    fn preferred_flags(self) -> vk::MemoryPropertyFlags {
+        match self {
+            MemoryLocation::GpuToCpu => vk::MemoryPropertyFlags::HOST_CACHED,
+            _ => vk::MemoryPropertyFlags::empty(),
+        }
+    }
+
+    fn unwanted_flags(self) -> vk::MemoryPropertyFlags {
+        match self {
+            // leave the (often tiny) host visible device memory to the resources that need it
+            MemoryLocation::GpuOnly => vk::MemoryPropertyFlags::HOST_VISIBLE,
+            _ => vk::MemoryPropertyFlags::empty(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocationStrategy {
+    /// best fit from a list of free ranges, freed memory can be reused right away
+    FreeList,
+    /// bumps an offset, a block is only reused once everything in it was freed. cheap for
+    /// resources that are created and destroyed together
+    Linear,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AllocationDesc {
+    pub requirements: vk::MemoryRequirements,
+    pub location: MemoryLocation,
+    pub strategy: AllocationStrategy,
+    /// buffers and `LINEAR` images. linear and optimal resources sharing a block have to be
+    /// `bufferImageGranularity` apart
+    pub linear: bool,
+}
+
+impl AllocationDesc {
+    pub fn buffer(requirements: vk::MemoryRequirements, location: MemoryLocation) -> Self {
+        AllocationDesc {
+            requirements,
+            location,
+            strategy: AllocationStrategy::FreeList,
+            linear: true,
+        }
+    }
+
+    /// for `OPTIMAL` images, use `buffer` for `LINEAR` ones
+    pub fn image(requirements: vk::MemoryRequirements, location: MemoryLocation) -> Self {
+        AllocationDesc {
+            linear: false,
+            ..AllocationDesc::buffer(requirements, location)
+        }
+    }
+
+    pub fn strategy(mut self, strategy: AllocationStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+}
+
+/// a range of a `vk::DeviceMemory` block. it has to be given back with `Allocator::free`,
+/// dropping it only leaks the range until the allocator is cleaned up. the default value is a
+/// null allocation, which `free` ignores
+#[derive(Debug)]
+pub struct Allocation {
+    memory: vk::DeviceMemory,
+    offset: u64,
+    size: u64,
+    memory_type_index: u32,
+    block_index: usize,
+    // null unless the memory is host visible
+    mapped_ptr: *mut u8,
+}
+
+impl Default for Allocation {
+    fn default() -> Self {
+        Allocation {
+            memory: vk::DeviceMemory::null(),
+            offset: 0,
+            size: 0,
+            memory_type_index: 0,
+            block_index: 0,
+            mapped_ptr: ptr::null_mut(),
+        }
+    }
+}
+
+impl Allocation {
+    pub fn memory(&self) -> vk::DeviceMemory {
+        self.memory
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn memory_type_index(&self) -> u32 {
+        self.memory_type_index
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.memory == vk::DeviceMemory::null()
+    }
+
+    /// host visible memory stays mapped, so this is `None` only for `GpuOnly` allocations
+    pub fn mapped_slice(&self) -> Option<&[u8]> {
+        if self.mapped_ptr.is_null() {
+            return None;
+        }
+        Some(unsafe { slice::from_raw_parts(self.mapped_ptr, self.size as usize) })
+    }
+
+    pub fn mapped_slice_mut(&mut self) -> Option<&mut [u8]> {
+        if self.mapped_ptr.is_null() {
+            return None;
+        }
+        Some(unsafe { slice::from_raw_parts_mut(self.mapped_ptr, self.size as usize) })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MemoryStats {
+    /// `vk::DeviceMemory` objects
+    pub block_count: u32,
+    pub allocation_count: u32,
+    /// size of all blocks
+    pub reserved_bytes: u64,
+    /// size of all allocations, without alignment padding
+    pub used_bytes: u64,
+}
+
+impl MemoryStats {
+    fn add(&mut self, other: &MemoryStats) {
+        self.block_count += other.block_count;
+        self.allocation_count += other.allocation_count;
+        self.reserved_bytes += other.reserved_bytes;
+        self.used_bytes += other.used_bytes;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MemoryTypeStats {
+    pub memory_type_index: u32,
+    pub property_flags: vk::MemoryPropertyFlags,
+    pub stats: MemoryStats,
+}
+
+#[derive(Debug, Clone)]
+pub struct AllocatorStats {
+    /// only the memory types that have blocks
+    pub memory_types: Vec<MemoryTypeStats>,
+    pub total: MemoryStats,
+    /// `maxMemoryAllocationCount`, the limit for `total.block_count`
+    pub max_block_count: u32,
+}
+
+impl fmt::Display for AllocatorStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} bytes used by {} allocations in {}/{} blocks",
+            self.total.used_bytes,
+            self.total.reserved_bytes,
+            self.total.allocation_count,
+            self.total.block_count,
+            self.max_block_count
+        )?;
+        for memory_type in &self.memory_types {
+            write!(
+                f,
+                "\n  type {} ({:?}): {} of {} bytes, {} allocations in {} blocks",
+                memory_type.memory_type_index,
+                memory_type.property_flags,
+                memory_type.stats.used_bytes,
+                memory_type.stats.reserved_bytes,
+                memory_type.stats.allocation_count,
+                memory_type.stats.block_count
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// hands out ranges of a few large `vk::DeviceMemory` blocks per memory type, instead of one
+/// allocation per resource (`maxMemoryAllocationCount` can be as low as 4096). allocations
+/// bigger than half a block get a block of their own
+pub struct Allocator {
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    buffer_image_granularity: u64,
+    max_block_count: u32,
+    block_count: u32,
+    // indexed by memory type
+    pools: Vec<MemoryPool>,
+}
+
+impl Allocator {
+    pub fn init(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> Allocator {
+        let memory_properties =
+            unsafe { instance.get_physical_device_memory_properties(physical_device) };
+        let limits = unsafe { instance.get_physical_device_properties(physical_device) }.limits;
+
+        let pools = memory_properties.memory_types[..memory_properties.memory_type_count as usize]
+            .iter()
+            .enumerate()
+            .map(|(index, memory_type)| {
+                let heap_size =
+                    memory_properties.memory_heaps[memory_type.heap_index as usize].size;
+                MemoryPool {
+                    memory_type_index: index as u32,
+                    property_flags: memory_type.property_flags,
+                    block_size: block_size(heap_size),
+                    blocks: Vec::new(),
+                }
+            })
+            .collect();
+
+        Allocator {
+            memory_properties,
+            buffer_image_granularity: limits.buffer_image_granularity.max(1),
+            max_block_count: limits.max_memory_allocation_count,
+            block_count: 0,
+            pools,
+        }
+    }
+
+    pub fn memory_properties(&self) -> &vk::PhysicalDeviceMemoryProperties {
+        &self.memory_properties
+    }
+
+    pub fn allocate(
+        &mut self,
+        logical_device: &ash::Device,
+        desc: &AllocationDesc,
+    ) -> Result<Allocation, EngineError> {
+        let candidates = self.memory_type_candidates(desc);
+        if candidates.is_empty() {
+            return Err(EngineError::NoSuitableMemoryType(
+                desc.location.required_flags(),
+            ));
+        }
+
+        // a full heap isn't fatal as long as another memory type fits
+        let mut out_of_memory = None;
+        for memory_type_index in candidates {
+            match self.allocate_from_pool(logical_device, memory_type_index, desc) {
+                Err(
+                    err @ EngineError::Vulkan {
+                        result: vk::Result::ERROR_OUT_OF_DEVICE_MEMORY,
+                        ..
+                    },
+                ) => out_of_memory = Some(err),
+                result => return result,
+            }
+        }
+        Err(out_of_memory.expect("there was at least one candidate"))
+    }
+
+    pub fn free(&mut self, logical_device: &ash::Device, allocation: Allocation) {
+        if allocation.is_null() {
+            return;
+        }
+        let pool = &mut self.pools[allocation.memory_type_index as usize];
+        let block_count = pool.blocks.iter().flatten().count();
+        let slot = &mut pool.blocks[allocation.block_index];
+        let block = slot.as_mut().expect("allocation belongs to a freed block");
+        block.free(allocation.offset, allocation.size);
+
+        // keep one empty block around, so a single resource being recreated doesn't allocate
+        // and free a whole block every time
+        if block.is_empty() && (block.dedicated() || block_count > 1) {
+            let block = slot.take().expect("checked above");
+            unsafe { logical_device.free_memory(block.memory, None) };
+            self.block_count -= 1;
+        }
+    }
+
+    pub fn create_buffer(
+        &mut self,
+        logical_device: &ash::Device,
+        buffer_info: &vk::BufferCreateInfo,
+        location: MemoryLocation,
+    ) -> Result<(vk::Buffer, Allocation), EngineError> {
+        let buffer =
+            unsafe { logical_device.create_buffer(buffer_info, None) }.call("create_buffer")?;
+        let requirements = unsafe { logical_device.get_buffer_memory_requirements(buffer) };
+        let allocation = match self.allocate(
+            logical_device,
+            &AllocationDesc::buffer(requirements, location),
+        ) {
+            Ok(allocation) => allocation,
+            Err(err) => {
+                unsafe { logical_device.destroy_buffer(buffer, None) };
+                return Err(err);
+            }
+        };
+        if let Err(err) = unsafe {
+            logical_device.bind_buffer_memory(buffer, allocation.memory, allocation.offset)
+        }
+        .call("bind_buffer_memory")
+        {
+            unsafe { logical_device.destroy_buffer(buffer, None) };
+            self.free(logical_device, allocation);
+            return Err(err);
+        }
+        Ok((buffer, allocation))
+    }
+
+    pub fn create_image(
+        &mut self,
+        logical_device: &ash::Device,
+        image_info: &vk::ImageCreateInfo,
+        location: MemoryLocation,
+    ) -> Result<(vk::Image, Allocation), EngineError> {
+        let image =
+            unsafe { logical_device.create_image(image_info, None) }.call("create_image")?;
+        let requirements = unsafe { logical_device.get_image_memory_requirements(image) };
+        let desc = match image_info.tiling {
+            vk::ImageTiling::LINEAR => AllocationDesc::buffer(requirements, location),
+            _ => AllocationDesc::image(requirements, location),
+        };
+        let allocation = match self.allocate(logical_device, &desc) {
+            Ok(allocation) => allocation,
+            Err(err) => {
+                unsafe { logical_device.destroy_image(image, None) };
+                return Err(err);
+            }
+        };
+        if let Err(err) =
+            unsafe { logical_device.bind_image_memory(image, allocation.memory, allocation.offset) }
+                .call("bind_image_memory")
+        {
+            unsafe { logical_device.destroy_image(image, None) };
+            self.free(logical_device, allocation);
+            return Err(err);
+        }
+        Ok((image, allocation))
+    }
+
+    /// # Safety
+    /// the gpu must be done with the buffer
+    pub unsafe fn destroy_buffer(
+        &mut self,
+        logical_device: &ash::Device,
+        buffer: vk::Buffer,
+        allocation: Allocation,
+    ) {
+        logical_device.destroy_buffer(buffer, None);
+        self.free(logical_device, allocation);
+    }
+
+    /// # Safety
+    /// the gpu must be done with the image
+    pub unsafe fn destroy_image(
+        &mut self,
+        logical_device: &ash::Device,
+        image: vk::Image,
+        allocation: Allocation,
+    ) {
+        logical_device.destroy_image(image, None);
+        self.free(logical_device, allocation);
+    }
+
+    pub fn stats(&self) -> AllocatorStats {
+        let mut total = MemoryStats::default();
+        let mut memory_types = Vec::new();
+        for pool in &self.pools {
+            let mut stats = MemoryStats::default();
+            for block in pool.blocks.iter().flatten() {
+                stats.add(&block.stats());
+            }
+            if stats.block_count > 0 {
+                total.add(&stats);
+                memory_types.push(MemoryTypeStats {
+                    memory_type_index: pool.memory_type_index,
+                    property_flags: pool.property_flags,
+                    stats,
+                });
+            }
+        }
+        AllocatorStats {
+            memory_types,
+            total,
+            max_block_count: self.max_block_count,
+        }
+    }
+
+    pub fn report(&self) {
+        println!("[Allocator] {}", self.stats());
+    }
+
+    /// frees every block, allocations that weren't given back are gone as well
+    ///
+    /// # Safety
+    /// nothing may use memory from this allocator anymore
+    pub unsafe fn cleanup(&mut self, logical_device: &ash::Device) {
+        for pool in &mut self.pools {
+            for block in pool.blocks.drain(..).flatten() {
+                logical_device.free_memory(block.memory, None);
+            }
+        }
+        self.block_count = 0;
+    }
+
+    /// memory types allowed by the requirements that have the required flags, best first
+    fn memory_type_candidates(&self, desc: &AllocationDesc) -> Vec<u32> {
+        let required = desc.location.required_flags();
+        let preferred = desc.location.preferred_flags();
+        let unwanted = desc.location.unwanted_flags();
+        // these need device features the engine doesn't enable
+        let unsupported =
+            vk::MemoryPropertyFlags::PROTECTED | vk::MemoryPropertyFlags::DEVICE_COHERENT_AMD;
+
+        let mut candidates: Vec<(u32, i32)> = self
+            .pools
+            .iter()
+            .filter(|pool| {
+                desc.requirements.memory_type_bits & (1 << pool.memory_type_index) != 0
+                    && pool.property_flags.contains(required)
+                    && !pool.property_flags.intersects(unsupported)
+            })
+            .map(|pool| {
+                let score = (pool.property_flags & preferred).as_raw().count_ones() as i32
+                    - (pool.property_flags & unwanted).as_raw().count_ones() as i32;
+                (pool.memory_type_index, score)
+            })
+            .collect();
+        // stable, so equally good types keep the order the driver lists them in
+        candidates.sort_by_key(|&(_, score)| -score);
+        candidates.into_iter().map(|(index, _)| index).collect()
+    }
+
+    fn allocate_from_pool(
+        &mut self,
+        logical_device: &ash::Device,
+        memory_type_index: u32,
+        desc: &AllocationDesc,
+    ) -> Result<Allocation, EngineError> {
+        let granularity = self.buffer_image_granularity;
+        let pool = &mut self.pools[memory_type_index as usize];
+        let size = desc.requirements.size;
+        let alignment = desc.requirements.alignment.max(1);
+        let kind = if desc.linear {
+            ChunkKind::Linear
+        } else {
+            ChunkKind::Optimal
+        };
+
+        let dedicated = pool.needs_dedicated(size);
+        if !dedicated {
+            for (block_index, block) in pool.blocks.iter_mut().enumerate() {
+                let Some(block) = block else { continue };
+                if block.strategy != Some(desc.strategy) {
+                    continue;
+                }
+                if let Some(offset) = block.allocate(size, alignment, kind, granularity) {
+                    return Ok(block.allocation(memory_type_index, block_index, offset, size));
+                }
+            }
+        }
+
+        if self.block_count >= self.max_block_count {
+            return Err(EngineError::vulkan(
+                "allocate_memory",
+                vk::Result::ERROR_TOO_MANY_OBJECTS,
+            ));
+        }
+        let mut block = if dedicated {
+            MemoryBlock::init(logical_device, memory_type_index, pool, size, None)?
+        } else {
+            MemoryBlock::init(
+                logical_device,
+                memory_type_index,
+                pool,
+                pool.block_size,
+                Some(desc.strategy),
+            )?
+        };
+        self.block_count += 1;
+
+        let offset = block
+            .allocate(size, alignment, kind, granularity)
+            .expect("a new block fits the allocation");
+        let block_index = match pool.blocks.iter().position(Option::is_none) {
+            Some(index) => index,
+            None => {
+                pool.blocks.push(None);
+                pool.blocks.len() - 1
+            }
+        };
+        let allocation = block.allocation(memory_type_index, block_index, offset, size);
+        pool.blocks[block_index] = Some(block);
+        Ok(allocation)
+    }
+}
+
+fn block_size(heap_size: u64) -> u64 {
+    if heap_size <= 1024 * 1024 * 1024 {
+        heap_size / 8
+    } else {
+        DEFAULT_BLOCK_SIZE
+    }
+}
+
+struct MemoryPool {
+    memory_type_index: u32,
+    property_flags: vk::MemoryPropertyFlags,
+    block_size: u64,
+    // freed blocks leave a hole, so the block index of live allocations stays valid
+    blocks: Vec<Option<MemoryBlock>>,
+}
+
+impl MemoryPool {
+    fn needs_dedicated(&self, size: u64) -> bool {
+        size > self.block_size / 2
+    }
+}
+
+struct MemoryBlock {
+    memory: vk::DeviceMemory,
+    size: u64,
+    mapped_ptr: *mut u8,
+    /// `None` for a dedicated block
+    strategy: Option<AllocationStrategy>,
+    ranges: BlockRanges,
+}
+
+enum BlockRanges {
+    FreeList(FreeList),
+    Linear(Linear),
+    Dedicated { used: bool },
+}
+
+impl MemoryBlock {
+    fn init(
+        logical_device: &ash::Device,
+        memory_type_index: u32,
+        pool: &MemoryPool,
+        size: u64,
+        strategy: Option<AllocationStrategy>,
+    ) -> Result<MemoryBlock, EngineError> {
+        let allocate_info = vk::MemoryAllocateInfo::builder()
+            .allocation_size(size)
+            .memory_type_index(memory_type_index);
+        let memory = unsafe { logical_device.allocate_memory(&allocate_info, None) }
+            .call("allocate_memory")?;
+
+        let mapped_ptr = if pool
+            .property_flags
+            .contains(vk::MemoryPropertyFlags::HOST_VISIBLE)
+        {
+            match unsafe {
+                logical_device.map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty())
+            }
+            .call("map_memory")
+            {
+                Ok(ptr) => ptr as *mut u8,
+                Err(err) => {
+                    unsafe { logical_device.free_memory(memory, None) };
+                    return Err(err);
+                }
+            }
+        } else {
+            ptr::null_mut()
+        };
+
+        let ranges = match strategy {
+            Some(AllocationStrategy::FreeList) => BlockRanges::FreeList(FreeList::new(size)),
+            Some(AllocationStrategy::Linear) => BlockRanges::Linear(Linear::default()),
+            None => BlockRanges::Dedicated { used: false },
+        };
+        Ok(MemoryBlock {
+            memory,
+            size,
+            mapped_ptr,
+            strategy,
+            ranges,
+        })
+    }
+
+    fn dedicated(&self) -> bool {
+        self.strategy.is_none()
+    }
+
+    fn allocate(
+        &mut self,
+        size: u64,
+        alignment: u64,
+        kind: ChunkKind,
+        granularity: u64,
+    ) -> Option<u64> {
+        match &mut self.ranges {
+            BlockRanges::FreeList(free_list) => {
+                free_list.allocate(size, alignment, kind, granularity)
+            }
+            BlockRanges::Linear(linear) => {
+                linear.allocate(self.size, size, alignment, kind, granularity)
+            }
+            BlockRanges::Dedicated { used } => {
+                if *used {
+                    return None;
+                }
+                *used = true;
+                Some(0)
+            }
+        }
+    }
+
+    fn free(&mut self, offset: u64, size: u64) {
+        match &mut self.ranges {
+            BlockRanges::FreeList(free_list) => free_list.free(offset),
+            BlockRanges::Linear(linear) => linear.free(size),
+            BlockRanges::Dedicated { used } => *used = false,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.stats().allocation_count == 0
+    }
+
+    fn allocation(
+        &self,
+        memory_type_index: u32,
+        block_index: usize,
+        offset: u64,
+        size: u64,
+    ) -> Allocation {
+        Allocation {
+            memory: self.memory,
+            offset,
+            size,
+            memory_type_index,
+            block_index,
+            mapped_ptr: if self.mapped_ptr.is_null() {
+                ptr::null_mut()
+            } else {
+                unsafe { self.mapped_ptr.add(offset as usize) }
+            },
+        }
+    }
+
+    fn stats(&self) -> MemoryStats {
+        let (allocation_count, used_bytes) = match &self.ranges {
+            BlockRanges::FreeList(free_list) => free_list.used(),
+            BlockRanges::Linear(linear) => (linear.count, linear.used),
+            BlockRanges::Dedicated { used: true } => (1, self.size),
+            BlockRanges::Dedicated { used: false } => (0, 0),
+        };
+        MemoryStats {
+            block_count: 1,
+            allocation_count,
+            reserved_bytes: self.size,
+            used_bytes,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChunkKind {
+    Free,
+    Linear,
+    Optimal,
+}
+
+impl ChunkKind {
+    /// linear and optimal resources may not share a `bufferImageGranularity` page
+    fn conflicts(self, other: ChunkKind) -> bool {
+        self != ChunkKind::Free && other != ChunkKind::Free && self != other
+    }
+}
+
+fn align_up(value: u64, alignment: u64) -> u64 {
+    value.div_ceil(alignment) * alignment
+}
+
+/// whether the byte at `end` (inclusive) and the byte at `start` lie on the same page
+fn same_page(end: u64, start: u64, page_size: u64) -> bool {
+    page_size > 1 && end / page_size == start / page_size
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Chunk {
+    offset: u64,
+    size: u64,
+    kind: ChunkKind,
+}
+
+impl Chunk {
+    fn end(&self) -> u64 {
+        self.offset + self.size
+    }
+}
+
+/// the whole block as a sorted list of used and free chunks, neighbouring free chunks are
+/// merged on free
+struct FreeList {
+    chunks: Vec<Chunk>,
+}
+
+impl FreeList {
+    fn new(size: u64) -> FreeList {
+        FreeList {
+            chunks: vec![Chunk {
+                offset: 0,
+                size,
+                kind: ChunkKind::Free,
+            }],
+        }
+    }
+
+    fn allocate(
+        &mut self,
+        size: u64,
+        alignment: u64,
+        kind: ChunkKind,
+        granularity: u64,
+    ) -> Option<u64> {
+        // the smallest free chunk that fits, as (chunk index, aligned offset)
+        let mut best: Option<(usize, u64)> = None;
+        for (index, chunk) in self.chunks.iter().enumerate() {
+            if chunk.kind != ChunkKind::Free || chunk.size < size {
+                continue;
+            }
+
+            let mut offset = align_up(chunk.offset, alignment);
+            if let Some(previous) = index.checked_sub(1).map(|i| self.chunks[i]) {
+                if previous.kind.conflicts(kind)
+                    && same_page(previous.end() - 1, offset, granularity)
+                {
+                    offset = align_up(offset, granularity);
+                }
+            }
+            let end = offset + size;
+            if end > chunk.end() {
+                continue;
+            }
+            if let Some(next) = self.chunks.get(index + 1) {
+                if next.kind.conflicts(kind) && same_page(end - 1, next.offset, granularity) {
+                    continue;
+                }
+            }
+
+            match best {
+                Some((best_index, _)) if self.chunks[best_index].size <= chunk.size => {}
+                _ => best = Some((index, offset)),
+            }
+        }
+
+        let (index, offset) = best?;
+        let chunk = self.chunks[index];
+        let end = offset + size;
+        let mut replacement = Vec::with_capacity(3);
+        if offset > chunk.offset {
+            replacement.push(Chunk {
+                offset: chunk.offset,
+                size: offset - chunk.offset,
+                kind: ChunkKind::Free,
+            });
+        }
+        replacement.push(Chunk { offset, size, kind });
+        if end < chunk.end() {
+            replacement.push(Chunk {
+                offset: end,
+                size: chunk.end() - end,
+                kind: ChunkKind::Free,
+            });
+        }
+        self.chunks.splice(index..=index, replacement);
+        Some(offset)
+    }
+
+    fn free(&mut self, offset: u64) {
+        let mut index = self
+            .chunks
+            .iter()
+            .position(|chunk| chunk.offset == offset && chunk.kind != ChunkKind::Free)
+            .expect("freed range was allocated from this block");
+        self.chunks[index].kind = ChunkKind::Free;
+
+        if self
+            .chunks
+            .get(index + 1)
+            .is_some_and(|next| next.kind == ChunkKind::Free)
+        {
+            let next = self.chunks.remove(index + 1);
+            self.chunks[index].size += next.size;
+        }
+        if index > 0 && self.chunks[index - 1].kind == ChunkKind::Free {
+            let chunk = self.chunks.remove(index);
+            index -= 1;
+            self.chunks[index].size += chunk.size;
+        }
+    }
+
+    fn used(&self) -> (u32, u64) {
+        self.chunks
+            .iter()
+            .filter(|chunk| chunk.kind != ChunkKind::Free)
+            .fold((0, 0), |(count, bytes), chunk| {
+                (count + 1, bytes + chunk.size)
+            })
+    }
+}
+
+/// a bump allocator, the offset goes back to 0 once every allocation was freed
+#[derive(Default)]
+struct Linear {
+    offset: u64,
+    last_kind: Option<ChunkKind>,
+    count: u32,
+    used: u64,
+}
+
+impl Linear {
+    fn allocate(
+        &mut self,
+        block_size: u64,
+        size: u64,
+        alignment: u64,
+        kind: ChunkKind,
+        granularity: u64,
+    ) -> Option<u64> {
+        let mut offset = align_up(self.offset, alignment);
+        if let Some(last_kind) = self.last_kind {
+            if last_kind.conflicts(kind) && same_page(self.offset - 1, offset, granularity) {
+                offset = align_up(offset, granularity);
+            }
+        }
+        if offset + size > block_size {
+            return None;
+        }
+        self.offset = offset + size;
+        self.last_kind = Some(kind);
+        self.count += 1;
+        self.used += size;
+        Some(offset)
+    }
+
+    fn free(&mut self, size: u64) {
+        self.count -= 1;
+        self.used -= size;
+        if self.count == 0 {
+            *self = Linear::default();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRANULARITY: u64 = 1024;
+
+    fn chunks(free_list: &FreeList) -> Vec<(u64, u64, ChunkKind)> {
+        free_list
+            .chunks
+            .iter()
+            .map(|chunk| (chunk.offset, chunk.size, chunk.kind))
+            .collect()
+    }
+
+    #[test]
+    fn free_list_aligns_and_splits() {
+        let mut free_list = FreeList::new(4096);
+
+        assert_eq!(free_list.allocate(100, 1, ChunkKind::Linear, 1), Some(0));
+        assert_eq!(free_list.allocate(50, 256, ChunkKind::Linear, 1), Some(256));
+        assert_eq!(
+            chunks(&free_list),
+            [
+                (0, 100, ChunkKind::Linear),
+                (100, 156, ChunkKind::Free),
+                (256, 50, ChunkKind::Linear),
+                (306, 3790, ChunkKind::Free),
+            ]
+        );
+        // the gap left by the alignment is the best fit
+        assert_eq!(free_list.allocate(100, 4, ChunkKind::Linear, 1), Some(100));
+        assert_eq!(free_list.used(), (3, 250));
+        assert_eq!(free_list.allocate(4000, 1, ChunkKind::Linear, 1), None);
+    }
+
+    #[test]
+    fn free_list_picks_the_smallest_fitting_chunk() {
+        let mut free_list = FreeList::new(1000);
+        let offsets: Vec<u64> = [100, 10, 300, 10, 50]
+            .iter()
+            .map(|&size| free_list.allocate(size, 1, ChunkKind::Linear, 1).unwrap())
+            .collect();
+        assert_eq!(offsets, [0, 100, 110, 410, 420]);
+        free_list.free(0);
+        free_list.free(110);
+
+        assert_eq!(free_list.allocate(80, 1, ChunkKind::Linear, 1), Some(0));
+        assert_eq!(free_list.allocate(200, 1, ChunkKind::Linear, 1), Some(110));
+    }
+
+    #[test]
+    fn free_list_coalesces_on_free() {
+        let mut free_list = FreeList::new(1000);
+        let a = free_list.allocate(100, 1, ChunkKind::Linear, 1).unwrap();
+        let b = free_list.allocate(100, 1, ChunkKind::Linear, 1).unwrap();
+        let c = free_list.allocate(100, 1, ChunkKind::Linear, 1).unwrap();
+
+        free_list.free(a);
+        free_list.free(c);
+        // c merged with the free tail, a stays on its own
+        assert_eq!(
+            chunks(&free_list),
+            [
+                (0, 100, ChunkKind::Free),
+                (100, 100, ChunkKind::Linear),
+                (200, 800, ChunkKind::Free),
+            ]
+        );
+        free_list.free(b);
+        assert_eq!(chunks(&free_list), [(0, 1000, ChunkKind::Free)]);
+        assert_eq!(free_list.used(), (0, 0));
+    }
+
+    #[test]
+    fn free_list_keeps_linear_and_optimal_off_the_same_page() {
+        let mut free_list = FreeList::new(8192);
+        assert_eq!(
+            free_list.allocate(100, 16, ChunkKind::Linear, GRANULARITY),
+            Some(0)
+        );
+        assert_eq!(
+            free_list.allocate(100, 16, ChunkKind::Optimal, GRANULARITY),
+            Some(GRANULARITY)
+        );
+        // the same kind can share the page
+        assert_eq!(
+            free_list.allocate(100, 16, ChunkKind::Optimal, GRANULARITY),
+            Some(GRANULARITY + 112)
+        );
+    }
+
+    #[test]
+    fn free_list_skips_gaps_on_the_page_of_a_conflicting_chunk() {
+        let mut free_list = FreeList::new(8192);
+        let first = free_list
+            .allocate(100, 16, ChunkKind::Optimal, GRANULARITY)
+            .unwrap();
+        assert_eq!(
+            free_list.allocate(100, 16, ChunkKind::Optimal, GRANULARITY),
+            Some(112)
+        );
+        free_list.free(first);
+
+        // the freed gap shares a page with the optimal chunk after it
+        assert_eq!(
+            free_list.allocate(50, 16, ChunkKind::Linear, GRANULARITY),
+            Some(GRANULARITY)
+        );
+        assert_eq!(free_list.allocate(50, 16, ChunkKind::Linear, 1), Some(0));
+    }
+
+    #[test]
+    fn linear_bumps_pads_and_resets() {
+        let mut linear = Linear::default();
+        assert_eq!(
+            linear.allocate(4096, 100, 16, ChunkKind::Linear, GRANULARITY),
+            Some(0)
+        );
+        assert_eq!(
+            linear.allocate(4096, 100, 16, ChunkKind::Linear, GRANULARITY),
+            Some(112)
+        );
+        assert_eq!(
+            linear.allocate(4096, 100, 16, ChunkKind::Optimal, GRANULARITY),
+            Some(GRANULARITY)
+        );
+        assert_eq!(
+            linear.allocate(4096, 3000, 16, ChunkKind::Optimal, GRANULARITY),
+            None
+        );
+        assert_eq!((linear.count, linear.used), (3, 300));
+
+        linear.free(100);
+        linear.free(100);
+        assert_eq!(linear.offset, GRANULARITY + 100);
+        linear.free(100);
+        assert_eq!(
+            linear.allocate(4096, 3000, 16, ChunkKind::Optimal, GRANULARITY),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn pages_and_alignment() {
+        assert_eq!(align_up(0, 256), 0);
+        assert_eq!(align_up(1, 256), 256);
+        assert_eq!(align_up(256, 256), 256);
+        assert!(same_page(1023, 1000, 1024));
+        assert!(!same_page(1023, 1024, 1024));
+        // a granularity of 1 never forces padding
+        assert!(!same_page(5, 5, 1));
+    }
+
+    #[test]
+    fn big_allocations_get_a_dedicated_block() {
+        let pool = MemoryPool {
+            memory_type_index: 0,
+            property_flags: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            block_size: block_size(8 * 1024 * 1024 * 1024),
+            blocks: Vec::new(),
+        };
+        assert_eq!(pool.block_size, DEFAULT_BLOCK_SIZE);
+        assert!(!pool.needs_dedicated(DEFAULT_BLOCK_SIZE / 2));
+        assert!(pool.needs_dedicated(DEFAULT_BLOCK_SIZE / 2 + 1));
+
+        // small heaps get smaller blocks
+        assert_eq!(block_size(256 * 1024 * 1024), 32 * 1024 * 1024);
+    }
+}
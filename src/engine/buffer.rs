@@ -0,0 +1,134 @@
+use std::{mem, slice};
+
+use ash::vk;
+
+use super::{
+    allocator::{Allocation, Allocator, MemoryLocation},
+    error::EngineError,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferUsage {
+    Vertex,
+    Index,
+    Uniform,
+    Storage,
+    /// source of a copy, only used for staging
+    Staging,
+}
+
+impl BufferUsage {
+    pub fn flags(self) -> vk::BufferUsageFlags {
+        match self {
+            BufferUsage::Vertex => vk::BufferUsageFlags::VERTEX_BUFFER,
+            BufferUsage::Index => vk::BufferUsageFlags::INDEX_BUFFER,
+            BufferUsage::Uniform => vk::BufferUsageFlags::UNIFORM_BUFFER,
+            BufferUsage::Storage => vk::BufferUsageFlags::STORAGE_BUFFER,
+            BufferUsage::Staging => vk::BufferUsageFlags::TRANSFER_SRC,
+        }
+    }
+
+    /// the stages that read the buffer, the second half of the barrier after an upload
+    pub fn dst_stage(self) -> vk::PipelineStageFlags {
+        match self {
+            BufferUsage::Vertex | BufferUsage::Index => vk::PipelineStageFlags::VERTEX_INPUT,
+            BufferUsage::Uniform => {
+                vk::PipelineStageFlags::VERTEX_SHADER | vk::PipelineStageFlags::FRAGMENT_SHADER
+            }
+            BufferUsage::Storage => {
+                vk::PipelineStageFlags::VERTEX_SHADER
+                    | vk::PipelineStageFlags::FRAGMENT_SHADER
+                    | vk::PipelineStageFlags::COMPUTE_SHADER
+            }
+            BufferUsage::Staging => vk::PipelineStageFlags::TRANSFER,
+        }
+    }
+
+    pub fn dst_access(self) -> vk::AccessFlags {
+        match self {
+            BufferUsage::Vertex => vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+            BufferUsage::Index => vk::AccessFlags::INDEX_READ,
+            BufferUsage::Uniform => vk::AccessFlags::UNIFORM_READ,
+            BufferUsage::Storage => vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+            BufferUsage::Staging => vk::AccessFlags::TRANSFER_READ,
+        }
+    }
+}
+
+/// a `vk::Buffer` with its memory. `GpuOnly` buffers are filled through `Uploader`, host
+/// visible ones with `write`
+pub struct Buffer {
+    pub buffer: vk::Buffer,
+    pub allocation: Allocation,
+    pub size: u64,
+    pub usage: BufferUsage,
+}
+
+impl Buffer {
+    pub fn init(
+        logical_device: &ash::Device,
+        allocator: &mut Allocator,
+        size: u64,
+        usage: BufferUsage,
+        location: MemoryLocation,
+    ) -> Result<Buffer, EngineError> {
+        let mut usage_flags = usage.flags();
+        if location == MemoryLocation::GpuOnly {
+            usage_flags |= vk::BufferUsageFlags::TRANSFER_DST;
+        }
+        let buffer_info = vk::BufferCreateInfo::builder()
+            .size(size)
+            .usage(usage_flags)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let (buffer, allocation) =
+            allocator.create_buffer(logical_device, &buffer_info, location)?;
+        Ok(Buffer {
+            buffer,
+            allocation,
+            size,
+            usage,
+        })
+    }
+
+    /// copies `data` to `offset` bytes into a host visible buffer
+    pub fn write<T: Pod>(&mut self, offset: u64, data: &[T]) {
+        let bytes = as_bytes(data);
+        let start = offset as usize;
+        let mapped = self
+            .allocation
+            .mapped_slice_mut()
+            .expect("only host visible buffers can be written directly");
+        mapped[start..start + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// # Safety
+    /// the gpu must be done with the buffer
+    pub unsafe fn cleanup(&mut self, logical_device: &ash::Device, allocator: &mut Allocator) {
+        allocator.destroy_buffer(logical_device, self.buffer, mem::take(&mut self.allocation));
+        self.buffer = vk::Buffer::null();
+    }
+}
+
+/// plain data like vertices, indices or uniform structs, which can be copied to the gpu byte
+/// for byte
+///
+/// # Safety
+/// every byte of the type has to be initialized, so it can't contain padding. a `#[repr(C)]`
+/// struct of `Pod` fields that line up without gaps qualifies
+pub unsafe trait Pod: Copy + 'static {}
+
+macro_rules! impl_pod {
+    ($($ty:ty),*) => {
+        $(unsafe impl Pod for $ty {})*
+    };
+}
+
+impl_pod!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+/// the raw bytes of `data`
+pub fn as_bytes<T: Pod>(data: &[T]) -> &[u8] {
+    // Pod types have no padding, so every byte is initialized
+    unsafe { slice::from_raw_parts(data.as_ptr() as *const u8, mem::size_of_val(data)) }
+}
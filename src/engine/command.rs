@@ -0,0 +1,43 @@
+use ash::vk;
+
+use super::{
+    error::{EngineError, VkResultExt},
+    queue::QueueFamilies,
+};
+
+/// the pool the per-frame command buffers come from
+pub fn init_command_pool(
+    logical_device: &ash::Device,
+    queue_families: &QueueFamilies,
+) -> Result<vk::CommandPool, EngineError> {
+    create_command_pool(
+        logical_device,
+        queue_families.graphics()?,
+        vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+    )
+}
+
+pub fn create_command_pool(
+    logical_device: &ash::Device,
+    queue_family_index: u32,
+    flags: vk::CommandPoolCreateFlags,
+) -> Result<vk::CommandPool, EngineError> {
+    let command_pool_info = vk::CommandPoolCreateInfo::builder()
+        .queue_family_index(queue_family_index)
+        .flags(flags);
+    unsafe { logical_device.create_command_pool(&command_pool_info, None) }
+        .call("create_command_pool")
+}
+
+pub fn allocate_command_buffers(
+    logical_device: &ash::Device,
+    command_pool: vk::CommandPool,
+    amount: u32,
+) -> Result<Vec<vk::CommandBuffer>, EngineError> {
+    let command_buffer_info = vk::CommandBufferAllocateInfo::builder()
+        .command_pool(command_pool)
+        .level(vk::CommandBufferLevel::PRIMARY)
+        .command_buffer_count(amount);
+    unsafe { logical_device.allocate_command_buffers(&command_buffer_info) }
+        .call("allocate_command_buffers")
+}
@@ -0,0 +1,279 @@
+use std::{ffi, path::PathBuf};
+
+use ash::vk;
+
+use super::{
+    device::DeviceOverride,
+    error::EngineError,
+    frame::DEFAULT_FRAMES_IN_FLIGHT,
+    swapchain::{PresentPolicy, SwapChainConfig},
+};
+
+pub const VALIDATION_LAYER: &str = "VK_LAYER_KHRONOS_validation";
+
+/// everything `GameEngine::init` and `HeadlessEngine::init` need to know up front, set up like
+/// the ash builders:
+///
+/// ```ignore
+/// let config = EngineConfig::default()
+///     .application_name("my game")
+///     .validation(false)
+///     .frames_in_flight(3);
+/// ```
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    pub application_name: String,
+    pub application_version: u32,
+    /// the vulkan version the instance is created with, raised to 1.1 if lower (see
+    /// `instance_api_version`)
+    pub api_version: u32,
+    /// enables `VK_LAYER_KHRONOS_validation` and a debug messenger, both are optional so the
+    /// engine still starts on machines without the layer installed
+    pub validation: bool,
+    // init fails if any of these is missing
+    pub layers: Vec<String>,
+    pub instance_extensions: Vec<String>,
+    pub device_extensions: Vec<String>,
+    // these are dropped with a warning if missing
+    pub optional_layers: Vec<String>,
+    pub optional_instance_extensions: Vec<String>,
+    pub optional_device_extensions: Vec<String>,
+    pub debug_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    pub debug_message_types: vk::DebugUtilsMessageTypeFlagsEXT,
+    pub frames_in_flight: usize,
+    /// bytes of uniform data every frame in flight gets for itself, see
+    /// `GameEngine::frame_uniforms`
+    pub frame_uniform_size: u64,
+    /// takes precedence over the `LEARNING_ASH_DEVICE` environment variable
+    pub device_override: Option<DeviceOverride>,
+    /// can be changed later with `GameEngine::set_present_policy`
+    pub present_policy: PresentPolicy,
+    /// clamped to the limits of the surface
+    pub swapchain_image_count: u32,
+    /// tried in order, before falling back to 8-bit srgb
+    pub surface_formats: Vec<vk::SurfaceFormatKHR>,
+    /// where shaders are loaded from at runtime
+    pub shader_dir: PathBuf,
+    /// rebuild pipelines when their shaders change on disk
+    pub hot_reload: bool,
+    /// adds a depth attachment to the render pass
+    pub depth_buffer: bool,
+    /// picks a depth format with a stencil aspect, only used with `depth_buffer`
+    pub stencil: bool,
+    /// samples per pixel, clamped to what the device supports. 1 turns msaa off, can be
+    /// changed later with `EngineCore::set_msaa`
+    pub msaa_samples: u32,
+    /// see `PipelineBuilder::min_sample_shading`, ignored without the `sample_rate_shading`
+    /// feature or msaa
+    pub sample_shading: Option<f32>,
+    /// renders with `cmd_begin_rendering` (vulkan 1.3 or `VK_KHR_dynamic_rendering`) instead of
+    /// render passes and framebuffers, if the device supports it
+    pub dynamic_rendering: bool,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        EngineConfig {
+            application_name: "hi :)".to_string(),
+            application_version: vk::make_api_version(0, 1, 0, 0),
+            api_version: vk::API_VERSION_1_1,
+            validation: true,
+            layers: Vec::new(),
+            instance_extensions: Vec::new(),
+            device_extensions: Vec::new(),
+            optional_layers: Vec::new(),
+            optional_instance_extensions: Vec::new(),
+            optional_device_extensions: Vec::new(),
+            debug_severity: vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
+                | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR
+                | vk::DebugUtilsMessageSeverityFlagsEXT::INFO
+                | vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE,
+            debug_message_types: vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+                | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
+                | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
+            frames_in_flight: DEFAULT_FRAMES_IN_FLIGHT,
+            frame_uniform_size: 0,
+            device_override: None,
+            present_policy: PresentPolicy::default(),
+            swapchain_image_count: SwapChainConfig::default().image_count,
+            surface_formats: Vec::new(),
+            shader_dir: PathBuf::from("shaders"),
+            hot_reload: true,
+            depth_buffer: true,
+            stencil: false,
+            msaa_samples: 1,
+            sample_shading: None,
+            dynamic_rendering: true,
+        }
+    }
+}
+
+impl EngineConfig {
+    pub fn application_name(mut self, name: impl Into<String>) -> Self {
+        self.application_name = name.into();
+        self
+    }
+
+    pub fn application_version(mut self, major: u32, minor: u32, patch: u32) -> Self {
+        self.application_version = vk::make_api_version(0, major, minor, patch);
+        self
+    }
+
+    /// versions below 1.1 are raised to 1.1, device selection relies on it
+    pub fn api_version(mut self, major: u32, minor: u32) -> Self {
+        self.api_version = vk::make_api_version(0, major, minor, 0).max(vk::API_VERSION_1_1);
+        self
+    }
+
+    pub fn validation(mut self, enabled: bool) -> Self {
+        self.validation = enabled;
+        self
+    }
+
+    pub fn layer(mut self, name: impl Into<String>) -> Self {
+        self.layers.push(name.into());
+        self
+    }
+
+    pub fn instance_extension(mut self, name: impl Into<String>) -> Self {
+        self.instance_extensions.push(name.into());
+        self
+    }
+
+    pub fn device_extension(mut self, name: impl Into<String>) -> Self {
+        self.device_extensions.push(name.into());
+        self
+    }
+
+    pub fn optional_layer(mut self, name: impl Into<String>) -> Self {
+        self.optional_layers.push(name.into());
+        self
+    }
+
+    pub fn optional_instance_extension(mut self, name: impl Into<String>) -> Self {
+        self.optional_instance_extensions.push(name.into());
+        self
+    }
+
+    pub fn optional_device_extension(mut self, name: impl Into<String>) -> Self {
+        self.optional_device_extensions.push(name.into());
+        self
+    }
+
+    pub fn debug_severity(mut self, severity: vk::DebugUtilsMessageSeverityFlagsEXT) -> Self {
+        self.debug_severity = severity;
+        self
+    }
+
+    pub fn debug_message_types(mut self, message_types: vk::DebugUtilsMessageTypeFlagsEXT) -> Self {
+        self.debug_message_types = message_types;
+        self
+    }
+
+    pub fn frames_in_flight(mut self, frames_in_flight: usize) -> Self {
+        self.frames_in_flight = frames_in_flight.max(1);
+        self
+    }
+
+    pub fn frame_uniform_size(mut self, size: u64) -> Self {
+        self.frame_uniform_size = size;
+        self
+    }
+
+    pub fn device_override(mut self, device_override: DeviceOverride) -> Self {
+        self.device_override = Some(device_override);
+        self
+    }
+
+    pub fn present_policy(mut self, present_policy: PresentPolicy) -> Self {
+        self.present_policy = present_policy;
+        self
+    }
+
+    pub fn swapchain_image_count(mut self, image_count: u32) -> Self {
+        self.swapchain_image_count = image_count;
+        self
+    }
+
+    /// adds a preferred surface format, e.g. `B8G8R8A8_UNORM` to do gamma correction in the
+    /// shaders
+    pub fn surface_format(mut self, format: vk::Format, color_space: vk::ColorSpaceKHR) -> Self {
+        self.surface_formats.push(vk::SurfaceFormatKHR {
+            format,
+            color_space,
+        });
+        self
+    }
+
+    pub fn shader_dir(mut self, shader_dir: impl Into<PathBuf>) -> Self {
+        self.shader_dir = shader_dir.into();
+        self
+    }
+
+    pub fn hot_reload(mut self, enabled: bool) -> Self {
+        self.hot_reload = enabled;
+        self
+    }
+
+    pub fn depth_buffer(mut self, enabled: bool) -> Self {
+        self.depth_buffer = enabled;
+        self
+    }
+
+    pub fn stencil(mut self, enabled: bool) -> Self {
+        self.stencil = enabled;
+        self
+    }
+
+    pub fn msaa(mut self, samples: u32) -> Self {
+        self.msaa_samples = samples;
+        self
+    }
+
+    pub fn sample_shading(mut self, min_sample_shading: Option<f32>) -> Self {
+        self.sample_shading = min_sample_shading;
+        self
+    }
+
+    pub fn dynamic_rendering(mut self, enabled: bool) -> Self {
+        self.dynamic_rendering = enabled;
+        self
+    }
+
+    /// the validation layer (if enabled) followed by the optional layers
+    pub fn all_optional_layers(&self) -> Vec<String> {
+        let mut layers = Vec::new();
+        if self.validation {
+            layers.push(VALIDATION_LAYER.to_string());
+        }
+        layers.extend(self.optional_layers.iter().cloned());
+        layers
+    }
+
+    /// `api_version`, but at least 1.1. device selection and feature queries use
+    /// `vkGetPhysicalDeviceProperties2` and `vkGetPhysicalDeviceFeatures2`, which need it
+    pub fn instance_api_version(&self) -> u32 {
+        self.api_version.max(vk::API_VERSION_1_1)
+    }
+
+    pub fn swapchain_config(&self) -> SwapChainConfig {
+        SwapChainConfig {
+            present_policy: self.present_policy,
+            image_count: self.swapchain_image_count,
+            surface_formats: self.surface_formats.clone(),
+        }
+    }
+
+    pub fn device_override_or_env(&self) -> Option<DeviceOverride> {
+        self.device_override
+            .clone()
+            .or_else(DeviceOverride::from_env)
+    }
+}
+
+pub fn to_cstrings(names: &[String]) -> Result<Vec<ffi::CString>, EngineError> {
+    Ok(names
+        .iter()
+        .map(|name| ffi::CString::new(name.as_str()))
+        .collect::<Result<_, _>>()?)
+}
@@ -0,0 +1,401 @@
+use std::path::Path;
+
+use ash::vk;
+
+use super::{
+    allocator::Allocator,
+    buffer::{as_bytes, Pod},
+    command,
+    config::EngineConfig,
+    debug::Debug,
+    default_pipeline_builder, depth,
+    descriptor::{DescriptorAllocator, DescriptorLayoutCache},
+    device::{self, DeviceRequirements},
+    dynamic_rendering::{self, DynamicRendering, DynamicRenderingSupport},
+    error::{EngineError, VkResultExt},
+    extensions::{self, EnabledExtensions},
+    init_devices_and_queues, init_physical_devices_and_properties, init_render_pass,
+    mesh::{Mesh, MeshIndex},
+    msaa,
+    pipeline::{Pipeline, PipelineBuilder},
+    queue::{QueueFamilies, Queues},
+    render_graph::RenderGraphCache,
+    shader::{self, ShaderManager},
+    surface::Surfaces,
+    texture::{self, SamplerConfig, Texture, TextureConfig},
+    upload::Uploader,
+    vertex::Vertex,
+    Draw,
+};
+
+/// what `GameEngine` and `HeadlessEngine` share: the instance and device, memory and uploads,
+/// and the pipelines, meshes and textures that get drawn. the engines only add the swapchain or
+/// offscreen target the frames end up in
+pub struct EngineCore {
+    pub entry: ash::Entry,
+    pub instance: ash::Instance,
+    pub enabled: EnabledExtensions,
+    // only there with validation enabled and debug utils available
+    pub debug: Option<Debug>,
+    pub physical_device: vk::PhysicalDevice,
+    pub physical_device_properties: vk::PhysicalDeviceProperties,
+    pub enabled_features: vk::PhysicalDeviceFeatures,
+    pub queue_families: QueueFamilies,
+    pub queues: Queues,
+    pub device: ash::Device,
+    pub allocator: Allocator,
+    pub uploader: Uploader,
+    /// `None` if the device doesn't support it or `EngineConfig::dynamic_rendering` is off
+    pub dynamic_rendering: Option<DynamicRenderingSupport>,
+    /// the format of the image the frames are rendered to
+    pub color_format: vk::Format,
+    /// the layout the render pass leaves that image in
+    pub final_layout: vk::ImageLayout,
+    /// what the pipelines are built against, null with dynamic rendering
+    pub render_pass: vk::RenderPass,
+    /// `None` without `EngineConfig::depth_buffer`
+    pub depth_format: Option<vk::Format>,
+    /// `TYPE_1` without msaa
+    pub samples: vk::SampleCountFlags,
+    /// what the pipelines are built with, `None` if unsupported
+    pub sample_shading: Option<f32>,
+    /// the depth and msaa targets and the framebuffers
+    pub render_graph: RenderGraphCache,
+    pub shaders: ShaderManager,
+    pub descriptor_layouts: DescriptorLayoutCache,
+    /// for sets that live as long as the engine
+    pub descriptors: DescriptorAllocator,
+    // `DEFAULT_PIPELINE` is always there
+    pub pipelines: Vec<Pipeline>,
+    pub draws: Vec<Draw>,
+    pub textures: Vec<Texture>,
+    // destroyed with the engine
+    pub samplers: Vec<vk::Sampler>,
+    pub command_pool: vk::CommandPool,
+}
+
+impl EngineCore {
+    /// picks a device (one that can present to `surfaces` if there are any) and sets it up.
+    /// nothing can be drawn until `init_pipelines` was called with the target's format
+    pub fn init(
+        entry: ash::Entry,
+        instance: ash::Instance,
+        mut enabled: EnabledExtensions,
+        debug: Option<Debug>,
+        surfaces: Option<&Surfaces>,
+        config: &EngineConfig,
+    ) -> Result<EngineCore, EngineError> {
+        let required_features = vk::PhysicalDeviceFeatures::default();
+        let mut required_device_extensions = Vec::new();
+        if surfaces.is_some() {
+            required_device_extensions.push(extensions::cstr_to_string(
+                ash::extensions::khr::Swapchain::name(),
+            ));
+        }
+        required_device_extensions.extend(config.device_extensions.iter().cloned());
+        let (physical_device, physical_device_properties) = init_physical_devices_and_properties(
+            &instance,
+            &DeviceRequirements {
+                surfaces,
+                extensions: &required_device_extensions,
+                features: required_features,
+            },
+            config.device_override_or_env().as_ref(),
+        )?;
+        enabled.device_extensions = extensions::resolve_device_extensions(
+            &instance,
+            physical_device,
+            &required_device_extensions,
+            &config.optional_device_extensions,
+        )?;
+        let dynamic_rendering = if config.dynamic_rendering {
+            dynamic_rendering::enable(
+                &instance,
+                physical_device,
+                config
+                    .instance_api_version()
+                    .min(physical_device_properties.api_version),
+                &mut enabled.device_extensions,
+            )?
+        } else {
+            None
+        };
+
+        let enabled_features =
+            device::enabled_features(&instance, physical_device, &required_features);
+
+        let queue_families = QueueFamilies::init(&instance, physical_device, surfaces)?;
+
+        let (logical_device, queues) = init_devices_and_queues(
+            &instance,
+            physical_device,
+            &queue_families,
+            &enabled.layers,
+            &enabled.device_extensions,
+            &enabled_features,
+            dynamic_rendering.is_some(),
+        )?;
+
+        let allocator = Allocator::init(&instance, physical_device);
+        let uploader = Uploader::init(&logical_device, &queue_families)?;
+
+        let depth_format = config
+            .depth_buffer
+            .then(|| depth::choose_depth_format(&instance, physical_device, config.stencil))
+            .transpose()?;
+        let samples = msaa::choose_sample_count(
+            config.msaa_samples,
+            &physical_device_properties.limits,
+            depth_format.is_some(),
+        );
+        let sample_shading =
+            msaa::choose_sample_shading(config.sample_shading, samples, &enabled_features);
+        let render_graph = RenderGraphCache::new(
+            dynamic_rendering
+                .map(|support| DynamicRendering::new(&instance, &logical_device, support)),
+        );
+
+        let command_pool = command::init_command_pool(&logical_device, &queue_families)?;
+
+        Ok(EngineCore {
+            entry,
+            instance,
+            enabled,
+            debug,
+            physical_device,
+            physical_device_properties,
+            enabled_features,
+            queue_families,
+            queues,
+            device: logical_device,
+            allocator,
+            uploader,
+            dynamic_rendering,
+            color_format: vk::Format::UNDEFINED,
+            final_layout: vk::ImageLayout::UNDEFINED,
+            render_pass: vk::RenderPass::null(),
+            depth_format,
+            samples,
+            sample_shading,
+            render_graph,
+            shaders: ShaderManager::new(&config.shader_dir, config.hot_reload),
+            descriptor_layouts: DescriptorLayoutCache::default(),
+            descriptors: DescriptorAllocator::default(),
+            pipelines: Vec::new(),
+            draws: Vec::new(),
+            textures: Vec::new(),
+            samplers: Vec::new(),
+            command_pool,
+        })
+    }
+
+    /// builds the render pass and `DEFAULT_PIPELINE` for a target of `color_format`, which the
+    /// render pass leaves in `final_layout`
+    pub fn init_pipelines(
+        &mut self,
+        color_format: vk::Format,
+        final_layout: vk::ImageLayout,
+    ) -> Result<(), EngineError> {
+        self.color_format = color_format;
+        self.final_layout = final_layout;
+        if self.dynamic_rendering.is_none() {
+            self.render_pass = init_render_pass(
+                &self.device,
+                color_format,
+                self.depth_format,
+                self.samples,
+                final_layout,
+            )?;
+        }
+        let builder = default_pipeline_builder(&mut self.shaders);
+        self.add_pipeline(builder)?;
+        Ok(())
+    }
+
+    /// builds a pipeline for the engine's render pass, returns its index in `pipelines`. the
+    /// sample count, sample shading and attachment formats are taken from the engine
+    pub fn add_pipeline(&mut self, builder: PipelineBuilder) -> Result<usize, EngineError> {
+        let builder = builder
+            .max_push_constants_size(
+                self.physical_device_properties
+                    .limits
+                    .max_push_constants_size,
+            )
+            .samples(self.samples)
+            .sample_shading(self.sample_shading)
+            .rendering_formats(vec![self.color_format], self.depth_format);
+        self.pipelines.push(builder.build(
+            &self.device,
+            &mut self.descriptor_layouts,
+            self.render_pass,
+            0,
+        )?);
+        Ok(self.pipelines.len() - 1)
+    }
+
+    /// `samples` is clamped like `EngineConfig::msaa_samples`, the count that is actually used
+    /// ends up in `self.samples`. rebuilds the render pass and every pipeline
+    pub fn set_msaa(
+        &mut self,
+        samples: u32,
+        sample_shading: Option<f32>,
+    ) -> Result<(), EngineError> {
+        let samples = msaa::choose_sample_count(
+            samples,
+            &self.physical_device_properties.limits,
+            self.depth_format.is_some(),
+        );
+        let sample_shading =
+            msaa::choose_sample_shading(sample_shading, samples, &self.enabled_features);
+        if samples == self.samples && sample_shading == self.sample_shading {
+            return Ok(());
+        }
+
+        unsafe {
+            self.device.device_wait_idle().call("device_wait_idle")?;
+            self.render_graph.cleanup(&self.device, &mut self.allocator);
+        }
+        self.samples = samples;
+        self.sample_shading = sample_shading;
+        self.rebuild_render_pass()
+    }
+
+    /// pipelines are only compatible with render passes (or dynamic rendering) of the same
+    /// format, so this rebuilds the render pass and every pipeline. the device has to be idle
+    pub fn set_color_format(&mut self, color_format: vk::Format) -> Result<(), EngineError> {
+        if color_format == self.color_format {
+            return Ok(());
+        }
+        self.color_format = color_format;
+        self.rebuild_render_pass()
+    }
+
+    /// rebuilds the render pass and every pipeline, e.g. for a new color format or sample count.
+    /// the device has to be idle
+    fn rebuild_render_pass(&mut self) -> Result<(), EngineError> {
+        if self.dynamic_rendering.is_none() {
+            unsafe { self.device.destroy_render_pass(self.render_pass, None) };
+            self.render_pass = init_render_pass(
+                &self.device,
+                self.color_format,
+                self.depth_format,
+                self.samples,
+                self.final_layout,
+            )?;
+        }
+        for pipeline in &mut self.pipelines {
+            pipeline.builder.samples = self.samples;
+            pipeline.builder.min_sample_shading = self.sample_shading;
+            pipeline.builder.color_formats = vec![self.color_format];
+            unsafe {
+                pipeline.rebuild(&self.device, &mut self.descriptor_layouts, self.render_pass)?
+            };
+        }
+        Ok(())
+    }
+
+    /// rebuilds the pipelines whose shaders changed on disk, called before every frame
+    pub fn reload_shaders(&mut self) -> Result<(), EngineError> {
+        shader::reload_pipelines(
+            &self.device,
+            &mut self.shaders,
+            &mut self.descriptor_layouts,
+            &mut self.pipelines,
+        )
+    }
+
+    /// uploads a mesh that is drawn every frame with the given pipeline, returns its index in
+    /// `draws`
+    pub fn upload_mesh<V: Vertex, I: MeshIndex>(
+        &mut self,
+        pipeline: usize,
+        vertices: &[V],
+        indices: &[I],
+    ) -> Result<usize, EngineError> {
+        let mesh = Mesh::upload(
+            &self.device,
+            &mut self.allocator,
+            &mut self.uploader,
+            &self.queues,
+            vertices,
+            indices,
+        )?;
+        self.draws.push(Draw {
+            pipeline,
+            mesh,
+            push_constants: Vec::new(),
+        });
+        Ok(self.draws.len() - 1)
+    }
+
+    /// loads a png, jpeg or tga file, returns its index in `textures`
+    pub fn load_texture<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        config: &TextureConfig,
+    ) -> Result<usize, EngineError> {
+        let texture = Texture::load(
+            &self.instance,
+            self.physical_device,
+            &self.device,
+            &mut self.allocator,
+            &mut self.uploader,
+            &self.queues,
+            path,
+            config,
+        )?;
+        self.textures.push(texture);
+        Ok(self.textures.len() - 1)
+    }
+
+    /// anisotropy is clamped to what the device supports
+    pub fn create_sampler(&mut self, config: &SamplerConfig) -> Result<vk::Sampler, EngineError> {
+        let sampler = texture::create_sampler(
+            &self.device,
+            config,
+            &self.physical_device_properties.limits,
+            &self.enabled_features,
+        )?;
+        self.samplers.push(sampler);
+        Ok(sampler)
+    }
+
+    /// pushed at offset 0 before the draw is recorded, e.g. its model matrix or tint
+    pub fn set_push_constants<T: Pod>(&mut self, draw: usize, data: &T) -> Result<(), EngineError> {
+        let draw = &mut self.draws[draw];
+        let bytes = as_bytes(std::slice::from_ref(data));
+        self.pipelines[draw.pipeline].push_constant_stages(0, bytes.len() as u32)?;
+        draw.push_constants = bytes.to_vec();
+        Ok(())
+    }
+}
+
+impl Drop for EngineCore {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = self.device.device_wait_idle();
+            self.device.destroy_command_pool(self.command_pool, None);
+            for draw in &mut self.draws {
+                draw.mesh.cleanup(&self.device, &mut self.allocator);
+            }
+            for pipeline in &mut self.pipelines {
+                pipeline.cleanup(&self.device);
+            }
+            for texture in &mut self.textures {
+                texture.cleanup(&self.device, &mut self.allocator);
+            }
+            for &sampler in &self.samplers {
+                self.device.destroy_sampler(sampler, None);
+            }
+            self.descriptors.cleanup(&self.device);
+            self.descriptor_layouts.cleanup(&self.device);
+            self.device.destroy_render_pass(self.render_pass, None);
+            self.render_graph.cleanup(&self.device, &mut self.allocator);
+            self.uploader.cleanup(&self.device, &mut self.allocator);
+            self.allocator.cleanup(&self.device);
+            self.device.destroy_device(None);
+            drop(self.debug.take());
+            self.instance.destroy_instance(None);
+        };
+    }
+}
@@ -2,6 +2,13 @@ use std::ffi;
 
 use ash::vk;
 
+use super::{
+    config::EngineConfig,
+    error::{EngineError, VkResultExt},
+};
+
+/// # Safety
+/// only meant to be called by the validation layers, `p_callback_data` has to be valid
 pub unsafe extern "system" fn vulkan_debug_utils_callback(
     message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
     message_type: vk::DebugUtilsMessageTypeFlagsEXT,
@@ -20,28 +27,26 @@ pub struct Debug {
     pub messenger: vk::DebugUtilsMessengerEXT,
 }
 
+/// also chained into the instance create info, so instance creation itself gets reported
+pub fn messenger_create_info(config: &EngineConfig) -> vk::DebugUtilsMessengerCreateInfoEXT {
+    vk::DebugUtilsMessengerCreateInfoEXT::builder()
+        .message_severity(config.debug_severity)
+        .message_type(config.debug_message_types)
+        .pfn_user_callback(Some(vulkan_debug_utils_callback))
+        .build()
+}
+
 impl Debug {
-    pub fn init(entry: &ash::Entry, instance: &ash::Instance) -> Result<Debug, vk::Result> {
-        use vk::DebugUtilsMessageSeverityFlagsEXT as SeverityFlags;
-        use vk::DebugUtilsMessageTypeFlagsEXT as TypeFlags;
-        let mut debug_create_info = vk::DebugUtilsMessengerCreateInfoEXT::builder()
-            .message_severity(
-                SeverityFlags::WARNING
-                    | SeverityFlags::ERROR
-                    | SeverityFlags::INFO
-                    | SeverityFlags::VERBOSE,
-            )
-            .message_type(TypeFlags::VALIDATION | TypeFlags::GENERAL | TypeFlags::PERFORMANCE)
-            .pfn_user_callback(Some(vulkan_debug_utils_callback));
+    pub fn init(
+        entry: &ash::Entry,
+        instance: &ash::Instance,
+        config: &EngineConfig,
+    ) -> Result<Debug, EngineError> {
+        let debug_create_info = messenger_create_info(config);
 
         let loader = ash::extensions::ext::DebugUtils::new(entry, instance);
-        let messenger = unsafe {
-            loader
-                .create_debug_utils_messenger(&debug_create_info, None)
-                .unwrap()
-        };
-
-
+        let messenger = unsafe { loader.create_debug_utils_messenger(&debug_create_info, None) }
+            .call("create_debug_utils_messenger")?;
 
         Ok(Debug { loader, messenger })
     }
@@ -0,0 +1,72 @@
+use ash::vk;
+
+use super::error::EngineError;
+
+/// tried in order, `D32_SFLOAT` has the best precision but no stencil
+pub const DEPTH_FORMATS: &[vk::Format] = &[
+    vk::Format::D32_SFLOAT,
+    vk::Format::D32_SFLOAT_S8_UINT,
+    vk::Format::D24_UNORM_S8_UINT,
+];
+
+/// the formats with a stencil aspect, vulkan guarantees one of them is supported
+pub const DEPTH_STENCIL_FORMATS: &[vk::Format] = &[
+    vk::Format::D32_SFLOAT_S8_UINT,
+    vk::Format::D24_UNORM_S8_UINT,
+];
+
+/// the first of `DEPTH_FORMATS` (or `DEPTH_STENCIL_FORMATS` with `stencil`) that can be a depth
+/// attachment with optimal tiling
+pub fn choose_depth_format(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    stencil: bool,
+) -> Result<vk::Format, EngineError> {
+    let candidates = if stencil {
+        DEPTH_STENCIL_FORMATS
+    } else {
+        DEPTH_FORMATS
+    };
+    candidates
+        .iter()
+        .copied()
+        .find(|&format| {
+            let properties =
+                unsafe { instance.get_physical_device_format_properties(physical_device, format) };
+            properties
+                .optimal_tiling_features
+                .contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+        })
+        .ok_or(EngineError::NoSuitableDepthFormat)
+}
+
+pub fn is_depth_format(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::D16_UNORM
+            | vk::Format::X8_D24_UNORM_PACK32
+            | vk::Format::D32_SFLOAT
+            | vk::Format::S8_UINT
+            | vk::Format::D16_UNORM_S8_UINT
+            | vk::Format::D24_UNORM_S8_UINT
+            | vk::Format::D32_SFLOAT_S8_UINT
+    )
+}
+
+pub fn has_stencil(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::S8_UINT
+            | vk::Format::D16_UNORM_S8_UINT
+            | vk::Format::D24_UNORM_S8_UINT
+            | vk::Format::D32_SFLOAT_S8_UINT
+    )
+}
+
+pub fn aspect_mask(format: vk::Format) -> vk::ImageAspectFlags {
+    if has_stencil(format) {
+        vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+    } else {
+        vk::ImageAspectFlags::DEPTH
+    }
+}
@@ -0,0 +1,402 @@
+use std::collections::HashMap;
+
+use ash::vk;
+
+use super::{
+    buffer::Buffer,
+    error::{EngineError, VkResultExt},
+};
+
+/// descriptors of each type per set in a new pool, the mix a typical set uses
+pub const DEFAULT_POOL_SIZES: &[(vk::DescriptorType, f32)] = &[
+    (vk::DescriptorType::SAMPLER, 0.5),
+    (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 4.0),
+    (vk::DescriptorType::SAMPLED_IMAGE, 4.0),
+    (vk::DescriptorType::STORAGE_IMAGE, 1.0),
+    (vk::DescriptorType::UNIFORM_TEXEL_BUFFER, 1.0),
+    (vk::DescriptorType::STORAGE_TEXEL_BUFFER, 1.0),
+    (vk::DescriptorType::UNIFORM_BUFFER, 2.0),
+    (vk::DescriptorType::STORAGE_BUFFER, 2.0),
+    (vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC, 1.0),
+    (vk::DescriptorType::STORAGE_BUFFER_DYNAMIC, 1.0),
+    (vk::DescriptorType::INPUT_ATTACHMENT, 0.5),
+];
+
+pub const INITIAL_SETS_PER_POOL: u32 = 64;
+/// pools grow by half each time one runs out, up to this many sets
+pub const MAX_SETS_PER_POOL: u32 = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct BindingKey {
+    binding: u32,
+    descriptor_type: vk::DescriptorType,
+    count: u32,
+    stages: vk::ShaderStageFlags,
+}
+
+/// hands out one `DescriptorSetLayout` per distinct set of bindings, so pipelines declaring
+/// the same set share its layout (and its descriptor sets). the layouts live until `cleanup`
+#[derive(Default)]
+pub struct DescriptorLayoutCache {
+    layouts: HashMap<Vec<BindingKey>, vk::DescriptorSetLayout>,
+}
+
+impl DescriptorLayoutCache {
+    /// the order of `bindings` doesn't matter, immutable samplers aren't supported
+    pub fn get(
+        &mut self,
+        logical_device: &ash::Device,
+        bindings: &[vk::DescriptorSetLayoutBinding],
+    ) -> Result<vk::DescriptorSetLayout, EngineError> {
+        let mut key: Vec<BindingKey> = bindings
+            .iter()
+            .map(|binding| BindingKey {
+                binding: binding.binding,
+                descriptor_type: binding.descriptor_type,
+                count: binding.descriptor_count,
+                stages: binding.stage_flags,
+            })
+            .collect();
+        key.sort_by_key(|binding| binding.binding);
+        if let Some(&layout) = self.layouts.get(&key) {
+            return Ok(layout);
+        }
+
+        let sorted_bindings: Vec<vk::DescriptorSetLayoutBinding> = key
+            .iter()
+            .map(|binding| {
+                vk::DescriptorSetLayoutBinding::builder()
+                    .binding(binding.binding)
+                    .descriptor_type(binding.descriptor_type)
+                    .descriptor_count(binding.count)
+                    .stage_flags(binding.stages)
+                    .build()
+            })
+            .collect();
+        let layout_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&sorted_bindings);
+        let layout = unsafe { logical_device.create_descriptor_set_layout(&layout_info, None) }
+            .call("create_descriptor_set_layout")?;
+        self.layouts.insert(key, layout);
+        Ok(layout)
+    }
+
+    pub fn len(&self) -> usize {
+        self.layouts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layouts.is_empty()
+    }
+
+    /// # Safety
+    /// no pipeline layout or descriptor set created from the layouts may still be in use
+    pub unsafe fn cleanup(&mut self, logical_device: &ash::Device) {
+        for (_, layout) in self.layouts.drain() {
+            logical_device.destroy_descriptor_set_layout(layout, None);
+        }
+    }
+}
+
+/// allocates descriptor sets from a list of pools, creating a bigger pool whenever the current
+/// one runs out. sets aren't freed one by one, `reset` recycles all of them at once, so the
+/// per-frame allocators are reset once the frame's fence signalled and a long-lived allocator
+/// is never reset
+pub struct DescriptorAllocator {
+    pool_sizes: Vec<(vk::DescriptorType, f32)>,
+    sets_per_pool: u32,
+    // the last one is allocated from
+    used_pools: Vec<vk::DescriptorPool>,
+    free_pools: Vec<vk::DescriptorPool>,
+}
+
+impl Default for DescriptorAllocator {
+    fn default() -> Self {
+        DescriptorAllocator::new(DEFAULT_POOL_SIZES)
+    }
+}
+
+impl DescriptorAllocator {
+    /// pools are only created on the first allocation
+    pub fn new(pool_sizes: &[(vk::DescriptorType, f32)]) -> DescriptorAllocator {
+        DescriptorAllocator {
+            pool_sizes: pool_sizes.to_vec(),
+            sets_per_pool: INITIAL_SETS_PER_POOL,
+            used_pools: Vec::new(),
+            free_pools: Vec::new(),
+        }
+    }
+
+    pub fn allocate(
+        &mut self,
+        logical_device: &ash::Device,
+        layout: vk::DescriptorSetLayout,
+    ) -> Result<vk::DescriptorSet, EngineError> {
+        let pool = match self.used_pools.last() {
+            Some(&pool) => pool,
+            None => self.next_pool(logical_device)?,
+        };
+        match allocate_set(logical_device, pool, layout) {
+            Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL) => {
+                // the pool is full, a fresh one has room unless the layout itself is too big
+                let pool = self.next_pool(logical_device)?;
+                allocate_set(logical_device, pool, layout).call("allocate_descriptor_sets")
+            }
+            result => result.call("allocate_descriptor_sets"),
+        }
+    }
+
+    fn next_pool(
+        &mut self,
+        logical_device: &ash::Device,
+    ) -> Result<vk::DescriptorPool, EngineError> {
+        let pool = match self.free_pools.pop() {
+            Some(pool) => pool,
+            None => {
+                let pool = create_pool(logical_device, self.sets_per_pool, &self.pool_sizes)?;
+                self.sets_per_pool =
+                    (self.sets_per_pool + self.sets_per_pool / 2).min(MAX_SETS_PER_POOL);
+                pool
+            }
+        };
+        self.used_pools.push(pool);
+        Ok(pool)
+    }
+
+    /// frees every set allocated so far, keeping the pools for reuse
+    ///
+    /// # Safety
+    /// the gpu must be done with all sets from this allocator
+    pub unsafe fn reset(&mut self, logical_device: &ash::Device) -> Result<(), EngineError> {
+        for &pool in &self.used_pools {
+            logical_device
+                .reset_descriptor_pool(pool, vk::DescriptorPoolResetFlags::empty())
+                .call("reset_descriptor_pool")?;
+        }
+        self.free_pools.append(&mut self.used_pools);
+        Ok(())
+    }
+
+    pub fn pool_count(&self) -> usize {
+        self.used_pools.len() + self.free_pools.len()
+    }
+
+    /// # Safety
+    /// the gpu must be done with all sets from this allocator
+    pub unsafe fn cleanup(&mut self, logical_device: &ash::Device) {
+        for pool in self.used_pools.drain(..).chain(self.free_pools.drain(..)) {
+            logical_device.destroy_descriptor_pool(pool, None);
+        }
+    }
+}
+
+fn allocate_set(
+    logical_device: &ash::Device,
+    pool: vk::DescriptorPool,
+    layout: vk::DescriptorSetLayout,
+) -> Result<vk::DescriptorSet, vk::Result> {
+    let layouts = [layout];
+    let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+        .descriptor_pool(pool)
+        .set_layouts(&layouts);
+    unsafe { logical_device.allocate_descriptor_sets(&allocate_info) }.map(|sets| sets[0])
+}
+
+fn create_pool(
+    logical_device: &ash::Device,
+    max_sets: u32,
+    pool_sizes: &[(vk::DescriptorType, f32)],
+) -> Result<vk::DescriptorPool, EngineError> {
+    let sizes: Vec<vk::DescriptorPoolSize> = pool_sizes
+        .iter()
+        .map(|&(ty, per_set)| vk::DescriptorPoolSize {
+            ty,
+            descriptor_count: ((per_set * max_sets as f32) as u32).max(1),
+        })
+        .collect();
+    let pool_info = vk::DescriptorPoolCreateInfo::builder()
+        .max_sets(max_sets)
+        .pool_sizes(&sizes);
+    unsafe { logical_device.create_descriptor_pool(&pool_info, None) }
+        .call("create_descriptor_pool")
+}
+
+enum WriteInfo {
+    Buffers(usize),
+    Images(usize),
+}
+
+struct PendingWrite {
+    binding: u32,
+    array_element: u32,
+    descriptor_type: vk::DescriptorType,
+    count: u32,
+    info: WriteInfo,
+}
+
+/// collects descriptor writes and applies them to a set in one `update_descriptor_sets`:
+///
+/// ```ignore
+/// DescriptorWriter::default()
+///     .uniform_buffer(0, &frame_uniforms)
+///     .combined_image_sampler(1, texture_view, sampler)
+///     .update(&device, set);
+/// ```
+#[derive(Default)]
+pub struct DescriptorWriter {
+    buffer_infos: Vec<vk::DescriptorBufferInfo>,
+    image_infos: Vec<vk::DescriptorImageInfo>,
+    writes: Vec<PendingWrite>,
+}
+
+impl DescriptorWriter {
+    /// the whole buffer as a `UNIFORM_BUFFER`
+    pub fn uniform_buffer(self, binding: u32, buffer: &Buffer) -> Self {
+        self.buffer(
+            binding,
+            vk::DescriptorType::UNIFORM_BUFFER,
+            buffer.buffer,
+            0,
+            vk::WHOLE_SIZE,
+        )
+    }
+
+    /// the whole buffer as a `STORAGE_BUFFER`
+    pub fn storage_buffer(self, binding: u32, buffer: &Buffer) -> Self {
+        self.buffer(
+            binding,
+            vk::DescriptorType::STORAGE_BUFFER,
+            buffer.buffer,
+            0,
+            vk::WHOLE_SIZE,
+        )
+    }
+
+    pub fn buffer(
+        self,
+        binding: u32,
+        descriptor_type: vk::DescriptorType,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        range: vk::DeviceSize,
+    ) -> Self {
+        self.buffers(
+            binding,
+            0,
+            descriptor_type,
+            &[vk::DescriptorBufferInfo {
+                buffer,
+                offset,
+                range,
+            }],
+        )
+    }
+
+    /// consecutive elements of an array binding, starting at `array_element`
+    pub fn buffers(
+        mut self,
+        binding: u32,
+        array_element: u32,
+        descriptor_type: vk::DescriptorType,
+        infos: &[vk::DescriptorBufferInfo],
+    ) -> Self {
+        self.writes.push(PendingWrite {
+            binding,
+            array_element,
+            descriptor_type,
+            count: infos.len() as u32,
+            info: WriteInfo::Buffers(self.buffer_infos.len()),
+        });
+        self.buffer_infos.extend_from_slice(infos);
+        self
+    }
+
+    /// an image in `SHADER_READ_ONLY_OPTIMAL` sampled with `sampler`
+    pub fn combined_image_sampler(
+        self,
+        binding: u32,
+        image_view: vk::ImageView,
+        sampler: vk::Sampler,
+    ) -> Self {
+        self.image(
+            binding,
+            vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            image_view,
+            sampler,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        )
+    }
+
+    pub fn sampler(self, binding: u32, sampler: vk::Sampler) -> Self {
+        self.image(
+            binding,
+            vk::DescriptorType::SAMPLER,
+            vk::ImageView::null(),
+            sampler,
+            vk::ImageLayout::UNDEFINED,
+        )
+    }
+
+    pub fn image(
+        self,
+        binding: u32,
+        descriptor_type: vk::DescriptorType,
+        image_view: vk::ImageView,
+        sampler: vk::Sampler,
+        image_layout: vk::ImageLayout,
+    ) -> Self {
+        self.images(
+            binding,
+            0,
+            descriptor_type,
+            &[vk::DescriptorImageInfo {
+                sampler,
+                image_view,
+                image_layout,
+            }],
+        )
+    }
+
+    /// consecutive elements of an array binding, starting at `array_element`
+    pub fn images(
+        mut self,
+        binding: u32,
+        array_element: u32,
+        descriptor_type: vk::DescriptorType,
+        infos: &[vk::DescriptorImageInfo],
+    ) -> Self {
+        self.writes.push(PendingWrite {
+            binding,
+            array_element,
+            descriptor_type,
+            count: infos.len() as u32,
+            info: WriteInfo::Images(self.image_infos.len()),
+        });
+        self.image_infos.extend_from_slice(infos);
+        self
+    }
+
+    /// the set must not be in use by a command buffer that is still executing
+    pub fn update(&self, logical_device: &ash::Device, set: vk::DescriptorSet) {
+        let writes: Vec<vk::WriteDescriptorSet> = self
+            .writes
+            .iter()
+            .map(|write| {
+                let builder = vk::WriteDescriptorSet::builder()
+                    .dst_set(set)
+                    .dst_binding(write.binding)
+                    .dst_array_element(write.array_element)
+                    .descriptor_type(write.descriptor_type);
+                let count = write.count as usize;
+                match write.info {
+                    WriteInfo::Buffers(first) => builder
+                        .buffer_info(&self.buffer_infos[first..first + count])
+                        .build(),
+                    WriteInfo::Images(first) => builder
+                        .image_info(&self.image_infos[first..first + count])
+                        .build(),
+                }
+            })
+            .collect();
+        unsafe { logical_device.update_descriptor_sets(&writes, &[]) };
+    }
+}
@@ -0,0 +1,261 @@
+use std::{ffi, mem};
+
+use ash::vk;
+
+use super::{
+    error::{EngineError, VkResultExt},
+    extensions,
+    queue::QueueFamilies,
+    surface::Surfaces,
+};
+
+/// environment variable that forces a physical device, see `DeviceOverride::parse`
+pub const DEVICE_OVERRIDE_ENV: &str = "LEARNING_ASH_DEVICE";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceOverride {
+    /// index into `enumerate_physical_devices`
+    Index(usize),
+    /// case insensitive substring of the device name
+    Name(String),
+    Uuid([u8; vk::UUID_SIZE]),
+}
+
+impl DeviceOverride {
+    /// a plain number is an index, 32 hex digits (dashes allowed) are a uuid and anything else is
+    /// matched against the device name
+    pub fn parse(value: &str) -> DeviceOverride {
+        let value = value.trim();
+        if let Ok(index) = value.parse() {
+            return DeviceOverride::Index(index);
+        }
+        let hex: String = value.chars().filter(|&c| c != '-').collect();
+        if hex.len() == vk::UUID_SIZE * 2 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            let mut uuid = [0; vk::UUID_SIZE];
+            for (i, byte) in uuid.iter_mut().enumerate() {
+                *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap();
+            }
+            return DeviceOverride::Uuid(uuid);
+        }
+        DeviceOverride::Name(value.to_lowercase())
+    }
+
+    pub fn from_env() -> Option<DeviceOverride> {
+        std::env::var(DEVICE_OVERRIDE_ENV)
+            .ok()
+            .filter(|value| !value.trim().is_empty())
+            .map(|value| DeviceOverride::parse(&value))
+    }
+
+    fn matches(
+        &self,
+        index: usize,
+        properties: &vk::PhysicalDeviceProperties,
+        uuid: &[u8],
+    ) -> bool {
+        match self {
+            DeviceOverride::Index(wanted) => *wanted == index,
+            DeviceOverride::Name(name) => device_name(properties).to_lowercase().contains(name),
+            DeviceOverride::Uuid(wanted) => wanted == uuid,
+        }
+    }
+}
+
+/// what a physical device has to offer for the engine to be able to use it at all
+pub struct DeviceRequirements<'a> {
+    /// when set, the device needs a graphics queue that can present to it and at least one
+    /// format and present mode
+    pub surfaces: Option<&'a Surfaces>,
+    pub extensions: &'a [String],
+    pub features: vk::PhysicalDeviceFeatures,
+}
+
+pub struct DeviceCandidate {
+    pub index: usize,
+    pub physical_device: vk::PhysicalDevice,
+    pub properties: vk::PhysicalDeviceProperties,
+    pub uuid: [u8; vk::UUID_SIZE],
+    pub device_local_memory: vk::DeviceSize,
+}
+
+impl DeviceCandidate {
+    /// compared lexicographically: device type first, then memory size, then api version
+    pub fn score(&self) -> (u32, vk::DeviceSize, u32) {
+        let type_score = match self.properties.device_type {
+            vk::PhysicalDeviceType::DISCRETE_GPU => 4,
+            vk::PhysicalDeviceType::INTEGRATED_GPU => 3,
+            vk::PhysicalDeviceType::VIRTUAL_GPU => 2,
+            vk::PhysicalDeviceType::CPU => 1,
+            _ => 0,
+        };
+        (
+            type_score,
+            self.device_local_memory,
+            self.properties.api_version,
+        )
+    }
+}
+
+pub fn device_name(properties: &vk::PhysicalDeviceProperties) -> String {
+    unsafe { ffi::CStr::from_ptr(properties.device_name.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+pub fn select_physical_device(
+    instance: &ash::Instance,
+    requirements: &DeviceRequirements,
+    device_override: Option<&DeviceOverride>,
+) -> Result<(vk::PhysicalDevice, vk::PhysicalDeviceProperties), EngineError> {
+    let physical_devices =
+        unsafe { instance.enumerate_physical_devices() }.call("enumerate_physical_devices")?;
+
+    let mut candidates = Vec::with_capacity(physical_devices.len());
+    for (index, &physical_device) in physical_devices.iter().enumerate() {
+        let candidate = query_candidate(instance, index, physical_device);
+        match check_requirements(instance, physical_device, requirements)? {
+            Ok(()) => candidates.push(candidate),
+            Err(reason) => println!(
+                "[Device] skipping {} ({index}): {reason}",
+                device_name(&candidate.properties)
+            ),
+        }
+    }
+
+    if let Some(device_override) = device_override {
+        let forced = candidates
+            .iter()
+            .find(|c| device_override.matches(c.index, &c.properties, &c.uuid));
+        match forced {
+            Some(candidate) => {
+                println!(
+                    "[Device] using {} ({}), forced by {device_override:?}",
+                    device_name(&candidate.properties),
+                    candidate.index
+                );
+                return Ok((candidate.physical_device, candidate.properties));
+            }
+            None => println!(
+                "[Device] no suitable device matches {device_override:?}, picking one instead"
+            ),
+        }
+    }
+
+    let best = candidates
+        .iter()
+        .max_by_key(|candidate| candidate.score())
+        .ok_or(EngineError::NoSuitableDevice)?;
+    println!(
+        "[Device] using {} ({}, {:?})",
+        device_name(&best.properties),
+        best.index,
+        best.properties.device_type
+    );
+    Ok((best.physical_device, best.properties))
+}
+
+fn query_candidate(
+    instance: &ash::Instance,
+    index: usize,
+    physical_device: vk::PhysicalDevice,
+) -> DeviceCandidate {
+    let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+
+    // the uuid is only queryable through vulkan 1.1
+    let mut id_properties = vk::PhysicalDeviceIDProperties::default();
+    if properties.api_version >= vk::API_VERSION_1_1 {
+        let mut properties2 =
+            vk::PhysicalDeviceProperties2::builder().push_next(&mut id_properties);
+        unsafe { instance.get_physical_device_properties2(physical_device, &mut properties2) };
+    }
+
+    let memory_properties =
+        unsafe { instance.get_physical_device_memory_properties(physical_device) };
+    let device_local_memory = memory_properties.memory_heaps
+        [..memory_properties.memory_heap_count as usize]
+        .iter()
+        .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+        .map(|heap| heap.size)
+        .sum();
+
+    DeviceCandidate {
+        index,
+        physical_device,
+        properties,
+        uuid: id_properties.device_uuid,
+        device_local_memory,
+    }
+}
+
+/// the outer result is for vulkan errors while querying, the inner one says why the device
+/// doesn't fit
+fn check_requirements(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    requirements: &DeviceRequirements,
+) -> Result<Result<(), String>, EngineError> {
+    let queue_families = QueueFamilies::init(instance, physical_device, requirements.surfaces)?;
+    if queue_families.graphics_queue_index.is_none() {
+        return Ok(Err("no graphics queue family".to_string()));
+    }
+    if requirements.surfaces.is_some() && queue_families.present_queue_index.is_none() {
+        return Ok(Err("no queue family can present to the surface".to_string()));
+    }
+
+    let available_extensions = extensions::available_device_extensions(instance, physical_device)?;
+    let missing_extensions: Vec<_> = requirements
+        .extensions
+        .iter()
+        .filter(|&wanted| !available_extensions.contains(wanted))
+        .collect();
+    if !missing_extensions.is_empty() {
+        return Ok(Err(format!("missing extensions {missing_extensions:?}")));
+    }
+
+    let available_features = unsafe { instance.get_physical_device_features(physical_device) };
+    if !features_supported(&requirements.features, &available_features) {
+        return Ok(Err("missing required features".to_string()));
+    }
+
+    if let Some(surfaces) = requirements.surfaces {
+        if surfaces.get_formats(physical_device)?.is_empty()
+            || surfaces.get_present_modes(physical_device)?.is_empty()
+        {
+            return Ok(Err("no surface formats or present modes".to_string()));
+        }
+    }
+
+    Ok(Ok(()))
+}
+
+/// the required features plus the optional ones the engine uses when they're available
+pub fn enabled_features(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    required: &vk::PhysicalDeviceFeatures,
+) -> vk::PhysicalDeviceFeatures {
+    let available = unsafe { instance.get_physical_device_features(physical_device) };
+    vk::PhysicalDeviceFeatures {
+        sampler_anisotropy: required.sampler_anisotropy | available.sampler_anisotropy,
+        sample_rate_shading: required.sample_rate_shading | available.sample_rate_shading,
+        ..*required
+    }
+}
+
+/// `PhysicalDeviceFeatures` is nothing but `Bool32`s, so every feature that is requested has to
+/// be available
+fn features_supported(
+    requested: &vk::PhysicalDeviceFeatures,
+    available: &vk::PhysicalDeviceFeatures,
+) -> bool {
+    const COUNT: usize =
+        mem::size_of::<vk::PhysicalDeviceFeatures>() / mem::size_of::<vk::Bool32>();
+    let requested =
+        unsafe { std::slice::from_raw_parts(requested as *const _ as *const vk::Bool32, COUNT) };
+    let available =
+        unsafe { std::slice::from_raw_parts(available as *const _ as *const vk::Bool32, COUNT) };
+    requested
+        .iter()
+        .zip(available)
+        .all(|(&requested, &available)| requested == vk::FALSE || available == vk::TRUE)
+}
@@ -0,0 +1,130 @@
+use std::ffi;
+
+use ash::{extensions::khr, vk};
+
+use super::{error::EngineError, extensions};
+
+/// where `cmd_begin_rendering` comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DynamicRenderingSupport {
+    /// vulkan 1.3 on both the instance and the device
+    Core,
+    /// `VK_KHR_dynamic_rendering`, plus the extensions it depends on below vulkan 1.2
+    Extension,
+}
+
+impl DynamicRenderingSupport {
+    /// `version` is the api version the device is used with, the lower one of the instance and
+    /// the device. `None` if neither path is there or the feature isn't supported
+    pub fn query(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        version: u32,
+    ) -> Result<Option<DynamicRenderingSupport>, EngineError> {
+        let support = if version >= vk::API_VERSION_1_3 {
+            DynamicRenderingSupport::Core
+        } else {
+            let available = extensions::available_device_extensions(instance, physical_device)?;
+            let missing = DynamicRenderingSupport::Extension
+                .device_extensions(version)
+                .into_iter()
+                .any(|name| !available.contains(&extensions::cstr_to_string(name)));
+            if missing {
+                return Ok(None);
+            }
+            DynamicRenderingSupport::Extension
+        };
+
+        let mut dynamic_rendering = vk::PhysicalDeviceDynamicRenderingFeatures::default();
+        let mut features = vk::PhysicalDeviceFeatures2::builder().push_next(&mut dynamic_rendering);
+        unsafe { instance.get_physical_device_features2(physical_device, &mut features) };
+        Ok((dynamic_rendering.dynamic_rendering == vk::TRUE).then_some(support))
+    }
+
+    /// the device extensions that have to be enabled for it
+    pub fn device_extensions(self, version: u32) -> Vec<&'static ffi::CStr> {
+        match self {
+            DynamicRenderingSupport::Core => Vec::new(),
+            // both are core in vulkan 1.2
+            DynamicRenderingSupport::Extension if version < vk::API_VERSION_1_2 => vec![
+                khr::DynamicRendering::name(),
+                vk::KhrDepthStencilResolveFn::name(),
+                vk::KhrCreateRenderpass2Fn::name(),
+            ],
+            DynamicRenderingSupport::Extension => vec![khr::DynamicRendering::name()],
+        }
+    }
+}
+
+/// queries the support and adds the extensions it needs to `device_extensions`, `None` if the
+/// engine has to fall back to render passes
+pub fn enable(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    version: u32,
+    device_extensions: &mut Vec<String>,
+) -> Result<Option<DynamicRenderingSupport>, EngineError> {
+    let support = DynamicRenderingSupport::query(instance, physical_device, version)?;
+    match support {
+        Some(support) => {
+            for name in support.device_extensions(version) {
+                let name = extensions::cstr_to_string(name);
+                if !device_extensions.contains(&name) {
+                    device_extensions.push(name);
+                }
+            }
+            println!("[Device] using dynamic rendering ({support:?})");
+        }
+        None => println!("[Device] dynamic rendering is not supported, using render passes"),
+    }
+    Ok(support)
+}
+
+/// `cmd_begin_rendering` and `cmd_end_rendering` of the core device or the extension
+#[derive(Clone, Copy)]
+pub struct DynamicRendering {
+    begin_rendering: vk::PFN_vkCmdBeginRendering,
+    end_rendering: vk::PFN_vkCmdEndRendering,
+}
+
+impl DynamicRendering {
+    /// the device has to be created with the extensions and the `dynamic_rendering` feature
+    pub fn new(
+        instance: &ash::Instance,
+        logical_device: &ash::Device,
+        support: DynamicRenderingSupport,
+    ) -> Self {
+        match support {
+            DynamicRenderingSupport::Core => {
+                let fp = logical_device.fp_v1_3();
+                DynamicRendering {
+                    begin_rendering: fp.cmd_begin_rendering,
+                    end_rendering: fp.cmd_end_rendering,
+                }
+            }
+            DynamicRenderingSupport::Extension => {
+                let loader = khr::DynamicRendering::new(instance, logical_device);
+                DynamicRendering {
+                    begin_rendering: loader.fp().cmd_begin_rendering_khr,
+                    end_rendering: loader.fp().cmd_end_rendering_khr,
+                }
+            }
+        }
+    }
+
+    /// # Safety
+    /// `command_buffer` has to be recording, outside of a render pass
+    pub unsafe fn cmd_begin_rendering(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        rendering_info: &vk::RenderingInfo,
+    ) {
+        (self.begin_rendering)(command_buffer, rendering_info)
+    }
+
+    /// # Safety
+    /// `command_buffer` has to be inside `cmd_begin_rendering`
+    pub unsafe fn cmd_end_rendering(&self, command_buffer: vk::CommandBuffer) {
+        (self.end_rendering)(command_buffer)
+    }
+}
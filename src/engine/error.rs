@@ -0,0 +1,141 @@
+use std::{ffi, fmt, panic::Location, path::PathBuf};
+
+use ash::vk;
+
+#[derive(Debug)]
+pub enum EngineError {
+    MissingLayers(Vec<String>),
+    MissingExtensions(Vec<String>),
+    NoSuitableDevice,
+    MissingQueueFamily(&'static str),
+    NoSuitableMemoryType(vk::MemoryPropertyFlags),
+    NoSuitableDepthFormat,
+    EmptyMesh,
+    /// an offscreen target without pixels
+    EmptyExtent(vk::Extent2D),
+    Surface(String),
+    Shader {
+        path: PathBuf,
+        message: String,
+    },
+    Reflection(String),
+    PushConstants(String),
+    Texture(String),
+    RenderGraph(String),
+    Vulkan {
+        call: &'static str,
+        result: vk::Result,
+        location: &'static Location<'static>,
+    },
+    Io(std::io::Error),
+    /// a name handed to vulkan contains a nul byte
+    Nul(ffi::NulError),
+    Png(png::EncodingError),
+    Image(image::ImageError),
+}
+
+impl EngineError {
+    #[track_caller]
+    pub fn vulkan(call: &'static str, result: vk::Result) -> EngineError {
+        EngineError::Vulkan {
+            call,
+            result,
+            location: Location::caller(),
+        }
+    }
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineError::MissingLayers(layers) => write!(f, "missing layers: {layers:?}"),
+            EngineError::MissingExtensions(extensions) => {
+                write!(f, "missing extensions: {extensions:?}")
+            }
+            EngineError::NoSuitableDevice => write!(f, "no suitable physical device found"),
+            EngineError::MissingQueueFamily(family) => {
+                write!(f, "no {family} queue family found")
+            }
+            EngineError::NoSuitableMemoryType(flags) => {
+                write!(f, "no memory type with {flags:?} found")
+            }
+            EngineError::NoSuitableDepthFormat => write!(f, "no supported depth format found"),
+            EngineError::EmptyMesh => write!(f, "a mesh needs at least one vertex and index"),
+            EngineError::EmptyExtent(extent) => {
+                write!(f, "can't render to {}x{}", extent.width, extent.height)
+            }
+            EngineError::Surface(message) => write!(f, "surface error: {message}"),
+            EngineError::Shader { path, message } => {
+                write!(f, "failed to compile {}:\n{message}", path.display())
+            }
+            EngineError::Reflection(message) => write!(f, "shader reflection failed: {message}"),
+            EngineError::PushConstants(message) => write!(f, "push constants: {message}"),
+            EngineError::Texture(message) => write!(f, "texture: {message}"),
+            EngineError::RenderGraph(message) => write!(f, "render graph: {message}"),
+            EngineError::Vulkan {
+                call,
+                result,
+                location,
+            } => write!(f, "{call} failed with {result} at {location}"),
+            EngineError::Io(err) => write!(f, "io error: {err}"),
+            EngineError::Nul(err) => write!(f, "invalid name: {err}"),
+            EngineError::Png(err) => write!(f, "png error: {err}"),
+            EngineError::Image(err) => write!(f, "image error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for EngineError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EngineError::Vulkan { result, .. } => Some(result),
+            EngineError::Io(err) => Some(err),
+            EngineError::Nul(err) => Some(err),
+            EngineError::Png(err) => Some(err),
+            EngineError::Image(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for EngineError {
+    fn from(err: std::io::Error) -> Self {
+        EngineError::Io(err)
+    }
+}
+
+impl From<ffi::NulError> for EngineError {
+    fn from(err: ffi::NulError) -> Self {
+        EngineError::Nul(err)
+    }
+}
+
+impl From<png::EncodingError> for EngineError {
+    fn from(err: png::EncodingError) -> Self {
+        EngineError::Png(err)
+    }
+}
+
+impl From<image::ImageError> for EngineError {
+    fn from(err: image::ImageError) -> Self {
+        EngineError::Image(err)
+    }
+}
+
+/// attaches the name of the failed vulkan call and where it was made from, e.g.
+/// `unsafe { device.create_fence(&info, None) }.call("create_fence")?`
+pub trait VkResultExt<T> {
+    fn call(self, call: &'static str) -> Result<T, EngineError>;
+}
+
+impl<T> VkResultExt<T> for Result<T, vk::Result> {
+    #[track_caller]
+    fn call(self, call: &'static str) -> Result<T, EngineError> {
+        let location = Location::caller();
+        self.map_err(|result| EngineError::Vulkan {
+            call,
+            result,
+            location,
+        })
+    }
+}
@@ -0,0 +1,139 @@
+use std::ffi;
+
+use ash::vk;
+
+use super::error::{EngineError, VkResultExt};
+
+/// the layers and extensions that actually got enabled, after dropping unavailable optional ones
+#[derive(Debug, Clone, Default)]
+pub struct EnabledExtensions {
+    pub layers: Vec<String>,
+    pub instance_extensions: Vec<String>,
+    pub device_extensions: Vec<String>,
+}
+
+impl EnabledExtensions {
+    pub fn has_instance_extension(&self, name: &ffi::CStr) -> bool {
+        contains(&self.instance_extensions, name)
+    }
+
+    pub fn has_device_extension(&self, name: &ffi::CStr) -> bool {
+        contains(&self.device_extensions, name)
+    }
+}
+
+fn contains(names: &[String], name: &ffi::CStr) -> bool {
+    names.iter().any(|n| n.as_bytes() == name.to_bytes())
+}
+
+pub fn name_to_string(name: &[ffi::c_char]) -> String {
+    unsafe { ffi::CStr::from_ptr(name.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+pub fn cstr_to_string(name: &ffi::CStr) -> String {
+    name.to_string_lossy().into_owned()
+}
+
+pub fn available_layers(entry: &ash::Entry) -> Result<Vec<String>, EngineError> {
+    Ok(entry
+        .enumerate_instance_layer_properties()
+        .call("enumerate_instance_layer_properties")?
+        .iter()
+        .map(|layer| name_to_string(&layer.layer_name))
+        .collect())
+}
+
+/// extensions of the implementation itself plus the ones provided by the given layers
+pub fn available_instance_extensions(
+    entry: &ash::Entry,
+    layers: &[String],
+) -> Result<Vec<String>, EngineError> {
+    let mut extensions = extension_names(
+        &entry
+            .enumerate_instance_extension_properties(None)
+            .call("enumerate_instance_extension_properties")?,
+    );
+    for layer in layers {
+        let layer = ffi::CString::new(layer.as_str())?;
+        let layer_extensions = entry
+            .enumerate_instance_extension_properties(Some(&layer))
+            .call("enumerate_instance_extension_properties")?;
+        for extension in extension_names(&layer_extensions) {
+            if !extensions.contains(&extension) {
+                extensions.push(extension);
+            }
+        }
+    }
+    Ok(extensions)
+}
+
+pub fn available_device_extensions(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+) -> Result<Vec<String>, EngineError> {
+    Ok(extension_names(
+        &unsafe { instance.enumerate_device_extension_properties(physical_device) }
+            .call("enumerate_device_extension_properties")?,
+    ))
+}
+
+/// device extensions to enable on an already selected device, which has all required ones
+pub fn resolve_device_extensions(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    required: &[String],
+    optional: &[String],
+) -> Result<Vec<String>, EngineError> {
+    resolve(
+        "Device",
+        &available_device_extensions(instance, physical_device)?,
+        required,
+        optional,
+    )
+    .map_err(EngineError::MissingExtensions)
+}
+
+fn extension_names(properties: &[vk::ExtensionProperties]) -> Vec<String> {
+    properties
+        .iter()
+        .map(|extension| name_to_string(&extension.extension_name))
+        .collect()
+}
+
+/// picks the wanted names that are available. missing optional ones are dropped with a warning,
+/// missing required ones are returned as the error
+pub fn resolve(
+    kind: &str,
+    available: &[String],
+    required: &[String],
+    optional: &[String],
+) -> Result<Vec<String>, Vec<String>> {
+    let missing: Vec<String> = required
+        .iter()
+        .filter(|name| !available.contains(name))
+        .cloned()
+        .collect();
+    if !missing.is_empty() {
+        return Err(missing);
+    }
+
+    let mut enabled: Vec<String> = Vec::with_capacity(required.len() + optional.len());
+    for name in required {
+        if !enabled.contains(name) {
+            enabled.push(name.clone());
+        }
+    }
+    for name in optional {
+        if enabled.contains(name) {
+            continue;
+        }
+        if available.contains(name) {
+            enabled.push(name.clone());
+        } else {
+            println!("[{kind}] {name} is not available, continuing without it");
+        }
+    }
+    Ok(enabled)
+}
@@ -0,0 +1,80 @@
+use ash::vk;
+
+use super::{
+    allocator::{Allocator, MemoryLocation},
+    buffer::{Buffer, BufferUsage},
+    command,
+    descriptor::DescriptorAllocator,
+    error::EngineError,
+    sync::SyncObjects,
+};
+
+pub const DEFAULT_FRAMES_IN_FLIGHT: usize = 2;
+
+/// everything a single frame in flight needs for itself, so the cpu can record the next frame
+/// while the gpu is still busy with the previous ones
+pub struct FrameContext {
+    pub command_buffer: vk::CommandBuffer,
+    pub sync: SyncObjects,
+    /// reset once the frame's fence signalled, for sets that only live for one frame
+    pub descriptors: DescriptorAllocator,
+    /// host visible uniform data only this frame reads, `None` if
+    /// `EngineConfig::frame_uniform_size` is 0
+    pub uniforms: Option<Buffer>,
+}
+
+impl FrameContext {
+    pub fn init(
+        logical_device: &ash::Device,
+        allocator: &mut Allocator,
+        command_buffer: vk::CommandBuffer,
+        uniform_size: u64,
+    ) -> Result<FrameContext, EngineError> {
+        let uniforms = (uniform_size > 0)
+            .then(|| {
+                Buffer::init(
+                    logical_device,
+                    allocator,
+                    uniform_size,
+                    BufferUsage::Uniform,
+                    MemoryLocation::CpuToGpu,
+                )
+            })
+            .transpose()?;
+        Ok(FrameContext {
+            command_buffer,
+            sync: SyncObjects::init(logical_device)?,
+            descriptors: DescriptorAllocator::default(),
+            uniforms,
+        })
+    }
+
+    /// the command buffer is freed together with its pool
+    ///
+    /// # Safety
+    /// the gpu must be done with this frame
+    pub unsafe fn cleanup(&mut self, logical_device: &ash::Device, allocator: &mut Allocator) {
+        self.sync.cleanup(logical_device);
+        self.descriptors.cleanup(logical_device);
+        if let Some(uniforms) = &mut self.uniforms {
+            uniforms.cleanup(logical_device, allocator);
+        }
+    }
+}
+
+pub fn init_frames(
+    logical_device: &ash::Device,
+    allocator: &mut Allocator,
+    command_pool: vk::CommandPool,
+    frames_in_flight: usize,
+    uniform_size: u64,
+) -> Result<Vec<FrameContext>, EngineError> {
+    let command_buffers =
+        command::allocate_command_buffers(logical_device, command_pool, frames_in_flight as u32)?;
+    command_buffers
+        .into_iter()
+        .map(|command_buffer| {
+            FrameContext::init(logical_device, allocator, command_buffer, uniform_size)
+        })
+        .collect()
+}
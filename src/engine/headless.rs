@@ -0,0 +1,172 @@
+use std::path::Path;
+
+use ash::vk;
+
+use super::{
+    add_draw_pass, command,
+    config::EngineConfig,
+    core::EngineCore,
+    descriptor::DescriptorAllocator,
+    error::{EngineError, VkResultExt},
+    init_debug, init_instance,
+    offscreen::OffscreenTarget,
+    render_graph::{
+        BufferAccess, BufferState, ImageAccess, ImageDesc, ImportedBuffer, ImportedImage,
+        RenderGraph,
+    },
+};
+
+/// renders the same pipelines as `GameEngine`, but into an `OffscreenTarget` instead of a
+/// swapchain, so it works without a window or display (e.g. on lavapipe in ci)
+pub struct HeadlessEngine {
+    pub core: EngineCore,
+    pub target: OffscreenTarget,
+    /// reset after every frame
+    pub frame_descriptors: DescriptorAllocator,
+    pub command_buffer: vk::CommandBuffer,
+    pub fence: vk::Fence,
+}
+
+impl HeadlessEngine {
+    pub fn init(extent: vk::Extent2D, config: EngineConfig) -> Result<HeadlessEngine, EngineError> {
+        if extent.width == 0 || extent.height == 0 {
+            return Err(EngineError::EmptyExtent(extent));
+        }
+        let entry = ash::Entry::linked();
+        let (instance, enabled) = init_instance(&entry, &config, &[])?;
+        let debug = init_debug(&entry, &instance, &enabled, &config)?;
+        let mut core = EngineCore::init(entry, instance, enabled, debug, None, &config)?;
+        core.init_pipelines(
+            OffscreenTarget::FORMAT,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        )?;
+
+        let target = OffscreenTarget::init(&core.device, &mut core.allocator, extent)?;
+
+        let command_buffer =
+            command::allocate_command_buffers(&core.device, core.command_pool, 1)?[0];
+        let fence = unsafe {
+            core.device
+                .create_fence(&vk::FenceCreateInfo::builder(), None)
+        }
+        .call("create_fence")?;
+
+        Ok(HeadlessEngine {
+            core,
+            target,
+            frame_descriptors: DescriptorAllocator::default(),
+            command_buffer,
+            fence,
+        })
+    }
+
+    /// renders one frame and blocks until it has been read back, returning tightly packed rgba8
+    /// rows (top row first)
+    pub fn render_frame(&mut self) -> Result<Vec<u8>, EngineError> {
+        self.core.reload_shaders()?;
+
+        unsafe {
+            self.core
+                .device
+                .reset_command_buffer(self.command_buffer, vk::CommandBufferResetFlags::empty())
+        }
+        .call("reset_command_buffer")?;
+        self.record_command_buffer()?;
+        let core = &mut self.core;
+
+        let command_buffers = [self.command_buffer];
+        let submit_info = vk::SubmitInfo::builder().command_buffers(&command_buffers);
+        unsafe {
+            core.device.queue_submit(
+                core.queues.graphics_queue,
+                &[submit_info.build()],
+                self.fence,
+            )
+        }
+        .call("queue_submit")?;
+        unsafe { core.device.wait_for_fences(&[self.fence], true, u64::MAX) }
+            .call("wait_for_fences")?;
+        unsafe { core.device.reset_fences(&[self.fence]) }.call("reset_fences")?;
+        unsafe { self.frame_descriptors.reset(&core.device)? };
+        core.uploader.collect(&core.device, &mut core.allocator)?;
+
+        Ok(self.target.read_pixels())
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&mut self, path: P) -> Result<(), EngineError> {
+        let pixels = self.render_frame()?;
+
+        let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        let mut encoder =
+            png::Encoder::new(file, self.target.extent.width, self.target.extent.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_srgb(png::SrgbRenderingIntent::Perceptual);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&pixels)?;
+
+        Ok(())
+    }
+
+    fn record_command_buffer(&mut self) -> Result<(), EngineError> {
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        let core = &mut self.core;
+        unsafe {
+            core.device
+                .begin_command_buffer(self.command_buffer, &begin_info)
+        }
+        .call("begin_command_buffer")?;
+
+        let offscreen = &self.target;
+        let target_desc = ImageDesc::new(offscreen.format, offscreen.extent);
+        let mut graph = RenderGraph::default();
+        let target = graph.import_image(
+            "offscreen",
+            ImportedImage::new(offscreen.image, offscreen.image_view, target_desc),
+        );
+        let readback = graph.import_buffer(
+            "readback",
+            ImportedBuffer::new(offscreen.readback_buffer).final_state(BufferState::HOST_READ),
+        );
+        add_draw_pass(
+            &mut graph,
+            target,
+            target_desc,
+            core.depth_format,
+            core.samples,
+            &core.pipelines,
+            &core.draws,
+        );
+        graph
+            .add_pass("readback")
+            .image(target, ImageAccess::TransferSrc)
+            .buffer(readback, BufferAccess::TransferDst)
+            .record(move |ctx| {
+                offscreen.cmd_copy_to_readback(ctx.logical_device, ctx.command_buffer);
+                Ok(())
+            });
+        graph.execute(
+            &core.device,
+            &mut core.allocator,
+            &mut core.render_graph,
+            self.command_buffer,
+        )?;
+
+        unsafe { core.device.end_command_buffer(self.command_buffer) }.call("end_command_buffer")
+    }
+}
+
+impl Drop for HeadlessEngine {
+    fn drop(&mut self) {
+        let core = &mut self.core;
+        unsafe {
+            let _ = core.device.device_wait_idle();
+            core.device.destroy_fence(self.fence, None);
+            self.frame_descriptors.cleanup(&core.device);
+            // its framebuffers point at the target
+            core.render_graph.cleanup(&core.device, &mut core.allocator);
+            self.target.cleanup(&core.device, &mut core.allocator);
+        };
+    }
+}
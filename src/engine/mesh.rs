@@ -0,0 +1,134 @@
+use ash::vk;
+
+use super::{
+    allocator::Allocator,
+    buffer::{Buffer, BufferUsage, Pod},
+    error::EngineError,
+    queue::Queues,
+    upload::Uploader,
+    vertex::Vertex,
+};
+
+pub trait MeshIndex: Pod {
+    const INDEX_TYPE: vk::IndexType;
+}
+
+impl MeshIndex for u16 {
+    const INDEX_TYPE: vk::IndexType = vk::IndexType::UINT16;
+}
+
+impl MeshIndex for u32 {
+    const INDEX_TYPE: vk::IndexType = vk::IndexType::UINT32;
+}
+
+/// device local vertex and index buffers, uploaded through the transfer queue. the vertices
+/// are bound to binding 0, instance data (if any) to binding 1
+pub struct Mesh {
+    pub vertex_buffer: Buffer,
+    pub index_buffer: Buffer,
+    pub index_count: u32,
+    pub index_type: vk::IndexType,
+}
+
+impl Mesh {
+    pub fn upload<V: Vertex, I: MeshIndex>(
+        logical_device: &ash::Device,
+        allocator: &mut Allocator,
+        uploader: &mut Uploader,
+        queues: &Queues,
+        vertices: &[V],
+        indices: &[I],
+    ) -> Result<Mesh, EngineError> {
+        // vulkan doesn't allow empty buffers
+        if vertices.is_empty() || indices.is_empty() {
+            return Err(EngineError::EmptyMesh);
+        }
+        let mut vertex_buffer = uploader.upload_buffer(
+            logical_device,
+            allocator,
+            queues,
+            vertices,
+            BufferUsage::Vertex,
+        )?;
+        let index_buffer = match uploader.upload_buffer(
+            logical_device,
+            allocator,
+            queues,
+            indices,
+            BufferUsage::Index,
+        ) {
+            Ok(index_buffer) => index_buffer,
+            Err(err) => {
+                unsafe {
+                    let _ = logical_device.device_wait_idle();
+                    vertex_buffer.cleanup(logical_device, allocator);
+                }
+                return Err(err);
+            }
+        };
+        Ok(Mesh {
+            vertex_buffer,
+            index_buffer,
+            index_count: indices.len() as u32,
+            index_type: I::INDEX_TYPE,
+        })
+    }
+
+    pub fn cmd_draw(&self, logical_device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        self.cmd_bind(logical_device, command_buffer);
+        unsafe {
+            logical_device.cmd_draw_indexed(command_buffer, self.index_count, 1, 0, 0, 0);
+        }
+    }
+
+    /// draws `instance_count` copies, reading per-instance data from `instance_buffer`
+    pub fn cmd_draw_instanced(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        instance_buffer: &Buffer,
+        instance_count: u32,
+    ) {
+        self.cmd_bind(logical_device, command_buffer);
+        unsafe {
+            logical_device.cmd_bind_vertex_buffers(
+                command_buffer,
+                1,
+                &[instance_buffer.buffer],
+                &[0],
+            );
+            logical_device.cmd_draw_indexed(
+                command_buffer,
+                self.index_count,
+                instance_count,
+                0,
+                0,
+                0,
+            );
+        }
+    }
+
+    fn cmd_bind(&self, logical_device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            logical_device.cmd_bind_vertex_buffers(
+                command_buffer,
+                0,
+                &[self.vertex_buffer.buffer],
+                &[0],
+            );
+            logical_device.cmd_bind_index_buffer(
+                command_buffer,
+                self.index_buffer.buffer,
+                0,
+                self.index_type,
+            );
+        }
+    }
+
+    /// # Safety
+    /// the gpu must be done drawing the mesh
+    pub unsafe fn cleanup(&mut self, logical_device: &ash::Device, allocator: &mut Allocator) {
+        self.vertex_buffer.cleanup(logical_device, allocator);
+        self.index_buffer.cleanup(logical_device, allocator);
+    }
+}
@@ -1,170 +1,563 @@
 use std::{ffi, mem};
 
 use ash::vk;
+use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
 use vk_shader_macros::include_glsl;
 
 use self::{
-    debug::vulkan_debug_utils_callback,
+    buffer::Buffer,
+    config::EngineConfig,
+    core::EngineCore,
     debug::Debug,
+    device::{DeviceOverride, DeviceRequirements},
+    error::{EngineError, VkResultExt},
+    extensions::EnabledExtensions,
+    frame::FrameContext,
+    mesh::Mesh,
+    pipeline::{BlendMode, Pipeline, PipelineBuilder},
     queue::{QueueFamilies, Queues},
+    render_graph::{AttachmentLoad, ImageDesc, ImageId, ImageState, ImportedImage, RenderGraph},
+    shader::ShaderManager,
     surface::Surfaces,
-    swapchain::SwapChain,
+    swapchain::{PresentPolicy, SwapChain, SwapChainConfig},
+    vertex::{MeshVertex, VertexLayout},
 };
 
+pub mod allocator;
+pub mod buffer;
+pub mod command;
+pub mod config;
+pub mod core;
 pub mod debug;
+pub mod depth;
+pub mod descriptor;
 pub mod device;
+pub mod dynamic_rendering;
+pub mod error;
+pub mod extensions;
+pub mod frame;
+pub mod headless;
+pub mod mesh;
+pub mod msaa;
+pub mod offscreen;
+pub mod pipeline;
 pub mod queue;
+pub mod reflect;
+pub mod render_graph;
+pub mod shader;
 pub mod surface;
 pub mod swapchain;
+pub mod sync;
+pub mod texture;
+pub mod upload;
+pub mod vertex;
 
 pub struct GameEngine {
-    pub window: winit::window::Window,
-    pub entry: ash::Entry,
-    pub instance: ash::Instance,
-    pub debug: mem::ManuallyDrop<Debug>,
+    pub core: EngineCore,
     pub surfaces: mem::ManuallyDrop<Surfaces>,
-    pub physical_device: vk::PhysicalDevice,
-    pub physical_device_properties: vk::PhysicalDeviceProperties,
-    pub queue_families: QueueFamilies,
-    pub queues: Queues,
-    pub device: ash::Device,
     pub swapchain: SwapChain,
-    pub render_pass: vk::RenderPass,
-    pub pipeline: Pipeline,
+    pub frames: Vec<FrameContext>,
+    pub current_frame: usize,
+    // the fence of the frame that last rendered to each swapchain image
+    pub images_in_flight: Vec<vk::Fence>,
+    // size of the window in pixels, the surface might dictate a different swapchain extent
+    pub window_extent: vk::Extent2D,
+    pub swapchain_config: SwapChainConfig,
+    // set on resize or when the swapchain config changes, the swapchain is rebuilt before the
+    // next frame
+    pub swapchain_outdated: bool,
+    pub paused: bool,
 }
 
 impl GameEngine {
-    pub fn init(window: winit::window::Window) -> Result<GameEngine, Box<dyn std::error::Error>> {
+    /// works with any window that exposes its raw handles (xlib, xcb or wayland). the engine
+    /// doesn't own the window, so it has to outlive the engine
+    pub fn init<W: HasRawWindowHandle + HasRawDisplayHandle>(
+        window: &W,
+        window_extent: vk::Extent2D,
+        config: EngineConfig,
+    ) -> Result<GameEngine, EngineError> {
         let entry = ash::Entry::linked();
+        let surface_extensions = surface::required_extensions(window.raw_display_handle())?;
+        let (instance, enabled) = init_instance(&entry, &config, &surface_extensions)?;
+        let debug = init_debug(&entry, &instance, &enabled, &config)?;
+        let surfaces = Surfaces::init(window, &entry, &instance)?;
+        let mut core = EngineCore::init(entry, instance, enabled, debug, Some(&surfaces), &config)?;
 
-        let layer_names = vec!["VK_LAYER_KHRONOS_validation"];
-        let instance = init_instance(&entry, &layer_names).unwrap();
-        let debug = Debug::init(&entry, &instance).unwrap();
-        let surfaces = Surfaces::init(&window, &entry, &instance).unwrap();
-
-        let (physical_device, physical_device_properties) =
-            init_physical_devices_and_properties(&instance).unwrap();
-
-        let queue_families = QueueFamilies::init(&instance, physical_device, &surfaces).unwrap();
-
-        let (logical_device, queues) =
-            init_devices_and_queues(&instance, physical_device, &queue_families, &layer_names)
-                .unwrap();
-
+        let swapchain_config = config.swapchain_config();
         let swapchain = SwapChain::init(
-            &instance,
-            physical_device,
-            &logical_device,
+            &core.instance,
+            core.physical_device,
+            &core.device,
             &surfaces,
-            &queue_families,
-            &queues,
-        )
-        .unwrap();
+            &core.queue_families,
+            window_extent,
+            &swapchain_config,
+            vk::SwapchainKHR::null(),
+        )?;
 
-        let render_pass = init_render_pass(
-            &logical_device,
-            physical_device,
-            swapchain.surface_format.format,
-        )
-        .unwrap();
+        swapchain.report();
 
-        let pipeline = Pipeline::init(&logical_device, &swapchain, &render_pass).unwrap();
+        core.init_pipelines(
+            swapchain.surface_format.format,
+            vk::ImageLayout::PRESENT_SRC_KHR,
+        )?;
+
+        let frames = frame::init_frames(
+            &core.device,
+            &mut core.allocator,
+            core.command_pool,
+            config.frames_in_flight.max(1),
+            config.frame_uniform_size,
+        )?;
+        let images_in_flight = vec![vk::Fence::null(); swapchain.images.len()];
 
         Ok(GameEngine {
-            pipeline,
-            window,
-            entry,
-            instance,
-            debug: mem::ManuallyDrop::new(debug),
+            core,
             surfaces: mem::ManuallyDrop::new(surfaces),
-            physical_device,
-            physical_device_properties,
-            queue_families,
-            queues,
-            device: logical_device,
             swapchain,
-            render_pass,
+            frames,
+            current_frame: 0,
+            images_in_flight,
+            window_extent,
+            swapchain_config,
+            swapchain_outdated: false,
+            paused: false,
         })
     }
+
+    /// called whenever the window is resized, the swapchain is rebuilt before the next frame
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.window_extent = vk::Extent2D { width, height };
+        self.swapchain_outdated = true;
+    }
+
+    /// takes effect on the next frame, the mode that is actually used ends up in
+    /// `swapchain.present_mode`
+    pub fn set_present_policy(&mut self, present_policy: PresentPolicy) {
+        if self.swapchain_config.present_policy != present_policy {
+            self.swapchain_config.present_policy = present_policy;
+            self.swapchain_outdated = true;
+        }
+    }
+
+    pub fn set_swapchain_image_count(&mut self, image_count: u32) {
+        if self.swapchain_config.image_count != image_count {
+            self.swapchain_config.image_count = image_count;
+            self.swapchain_outdated = true;
+        }
+    }
+
+    pub fn recreate_swapchain(&mut self) -> Result<(), EngineError> {
+        let core = &mut self.core;
+        let window_extent = self.window_extent;
+        let surface_capabilities = self.surfaces.get_capabilities(core.physical_device)?;
+        let extent = swapchain::choose_extent(&surface_capabilities, window_extent);
+
+        // a minimized window has a 0x0 surface, which we can't create a swapchain for
+        if extent.width == 0 || extent.height == 0 {
+            self.paused = true;
+            return Ok(());
+        }
+        self.paused = false;
+        self.swapchain_outdated = false;
+
+        unsafe {
+            core.device.device_wait_idle().call("device_wait_idle")?;
+            core.render_graph.cleanup(&core.device, &mut core.allocator);
+        }
+
+        let old_format = self.swapchain.surface_format.format;
+        let old_present_mode = self.swapchain.present_mode;
+        self.swapchain.recreate(
+            &core.instance,
+            core.physical_device,
+            &core.device,
+            &self.surfaces,
+            &core.queue_families,
+            window_extent,
+            &self.swapchain_config,
+        )?;
+
+        if self.swapchain.surface_format.format != old_format
+            || self.swapchain.present_mode != old_present_mode
+        {
+            self.swapchain.report();
+        }
+
+        core.set_color_format(self.swapchain.surface_format.format)?;
+
+        self.images_in_flight = vec![vk::Fence::null(); self.swapchain.images.len()];
+
+        Ok(())
+    }
+
+    /// the uniform buffer the next `draw_frame` records with, once the gpu is done with the
+    /// last frame that used it. `None` without `EngineConfig::frame_uniform_size`
+    pub fn frame_uniforms(&mut self) -> Result<Option<&mut Buffer>, EngineError> {
+        let frame = &mut self.frames[self.current_frame];
+        unsafe {
+            self.core
+                .device
+                .wait_for_fences(&[frame.sync.in_flight], true, u64::MAX)
+        }
+        .call("wait_for_fences")?;
+        Ok(frame.uniforms.as_mut())
+    }
+
+    pub fn draw_frame(&mut self) -> Result<(), EngineError> {
+        self.core.reload_shaders()?;
+
+        if self.paused || self.swapchain_outdated {
+            self.recreate_swapchain()?;
+            if self.paused {
+                return Ok(());
+            }
+        }
+
+        let frame = &self.frames[self.current_frame];
+        let command_buffer = frame.command_buffer;
+        let image_available = frame.sync.image_available;
+        let in_flight = frame.sync.in_flight;
+
+        unsafe {
+            self.core
+                .device
+                .wait_for_fences(&[in_flight], true, u64::MAX)
+        }
+        .call("wait_for_fences")?;
+        unsafe {
+            self.frames[self.current_frame]
+                .descriptors
+                .reset(&self.core.device)?
+        };
+        self.core
+            .uploader
+            .collect(&self.core.device, &mut self.core.allocator)?;
+
+        let image_index = match unsafe {
+            self.swapchain.swapchain_loader.acquire_next_image(
+                self.swapchain.swapchain,
+                u64::MAX,
+                image_available,
+                vk::Fence::null(),
+            )
+        } {
+            Ok((image_index, _suboptimal)) => image_index,
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => return self.recreate_swapchain(),
+            Err(err) => return Err(err).call("acquire_next_image"),
+        };
+
+        // an older frame might still be rendering to this image if images are acquired out of order
+        let image_fence = self.images_in_flight[image_index as usize];
+        if image_fence != vk::Fence::null() && image_fence != in_flight {
+            unsafe {
+                self.core
+                    .device
+                    .wait_for_fences(&[image_fence], true, u64::MAX)
+            }
+            .call("wait_for_fences")?;
+        }
+        self.images_in_flight[image_index as usize] = in_flight;
+        let render_finished = self.swapchain.render_finished[image_index as usize];
+
+        unsafe { self.core.device.reset_fences(&[in_flight]) }.call("reset_fences")?;
+        unsafe {
+            self.core
+                .device
+                .reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())
+        }
+        .call("reset_command_buffer")?;
+        self.record_command_buffer(command_buffer, image_index)?;
+
+        let wait_semaphores = [image_available];
+        let wait_stages = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
+        let command_buffers = [command_buffer];
+        let signal_semaphores = [render_finished];
+        let submit_info = vk::SubmitInfo::builder()
+            .wait_semaphores(&wait_semaphores)
+            .wait_dst_stage_mask(&wait_stages)
+            .command_buffers(&command_buffers)
+            .signal_semaphores(&signal_semaphores);
+        unsafe {
+            self.core.device.queue_submit(
+                self.core.queues.graphics_queue,
+                &[submit_info.build()],
+                in_flight,
+            )
+        }
+        .call("queue_submit")?;
+
+        let swapchains = [self.swapchain.swapchain];
+        let image_indices = [image_index];
+        let present_info = vk::PresentInfoKHR::builder()
+            .wait_semaphores(&signal_semaphores)
+            .swapchains(&swapchains)
+            .image_indices(&image_indices);
+        self.current_frame = (self.current_frame + 1) % self.frames.len();
+
+        let present_result = unsafe {
+            self.swapchain
+                .swapchain_loader
+                .queue_present(self.core.queues.present_queue, &present_info)
+        };
+        match present_result {
+            Ok(true) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => self.recreate_swapchain()?,
+            Ok(false) => {}
+            Err(err) => return Err(err).call("queue_present"),
+        }
+
+        Ok(())
+    }
+
+    fn record_command_buffer(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        image_index: u32,
+    ) -> Result<(), EngineError> {
+        let begin_info = vk::CommandBufferBeginInfo::builder();
+        let core = &mut self.core;
+        unsafe {
+            core.device
+                .begin_command_buffer(command_buffer, &begin_info)
+        }
+        .call("begin_command_buffer")?;
+
+        let target_desc =
+            ImageDesc::new(self.swapchain.surface_format.format, self.swapchain.extent);
+        let mut graph = RenderGraph::default();
+        let target = graph.import_image(
+            "swapchain",
+            ImportedImage::new(
+                self.swapchain.images[image_index as usize],
+                self.swapchain.image_views[image_index as usize],
+                target_desc,
+            )
+            .initial_state(ImageState::ACQUIRED)
+            .final_state(ImageState::PRESENT),
+        );
+        add_draw_pass(
+            &mut graph,
+            target,
+            target_desc,
+            core.depth_format,
+            core.samples,
+            &core.pipelines,
+            &core.draws,
+        );
+        graph.execute(
+            &core.device,
+            &mut core.allocator,
+            &mut core.render_graph,
+            command_buffer,
+        )?;
+
+        unsafe { core.device.end_command_buffer(command_buffer) }.call("end_command_buffer")
+    }
 }
 
 impl Drop for GameEngine {
     fn drop(&mut self) {
+        let core = &mut self.core;
         unsafe {
-            self.device.destroy_render_pass(self.render_pass, None);
-            self.swapchain.cleanup(&self.device);
-            self.device.destroy_device(None);
-            std::mem::ManuallyDrop::drop(&mut self.surfaces);
-            std::mem::ManuallyDrop::drop(&mut self.debug);
-            self.instance.destroy_instance(None);
+            let _ = core.device.device_wait_idle();
+            for frame in &mut self.frames {
+                frame.cleanup(&core.device, &mut core.allocator);
+            }
+            // its framebuffers point at the swapchain images
+            core.render_graph.cleanup(&core.device, &mut core.allocator);
+            self.swapchain.cleanup(&core.device);
+            mem::ManuallyDrop::drop(&mut self.surfaces);
         };
     }
 }
 
+/// declares the pass drawing `draws` into `target`, clearing it to black. the depth buffer and
+/// (with msaa) the multisampled color image it resolves from are transients of the graph. its
+/// render pass is compatible with `init_render_pass` for the same formats and sample count
+pub fn add_draw_pass<'a>(
+    graph: &mut RenderGraph<'a>,
+    target: ImageId,
+    target_desc: ImageDesc,
+    depth_format: Option<vk::Format>,
+    samples: vk::SampleCountFlags,
+    pipelines: &'a [Pipeline],
+    draws: &'a [Draw],
+) {
+    let msaa = (samples != vk::SampleCountFlags::TYPE_1)
+        .then(|| graph.create_image("msaa color", target_desc.samples(samples)));
+    let depth = depth_format.map(|format| {
+        graph.create_image(
+            "depth",
+            ImageDesc::new(format, target_desc.extent).samples(samples),
+        )
+    });
+
+    let clear = AttachmentLoad::clear_color([0.0, 0.0, 0.0, 1.0]);
+    let mut pass = graph.add_pass("draw");
+    pass = match msaa {
+        Some(msaa) => pass.color(msaa, clear).resolve(target),
+        None => pass.color(target, clear),
+    };
+    if let Some(depth) = depth {
+        pass = pass.depth(depth, AttachmentLoad::clear_depth(1.0, 0));
+    }
+    pass.record(move |ctx| {
+        pipeline::cmd_set_viewport(ctx.logical_device, ctx.command_buffer, ctx.extent);
+        record_draws(ctx.logical_device, ctx.command_buffer, pipelines, draws)
+    });
+}
+
+/// index of the pipeline built from `default_pipeline_builder` in both engines
+pub const DEFAULT_PIPELINE: usize = 0;
+
+/// a mesh and the index of the pipeline it's drawn with
+pub struct Draw {
+    pub pipeline: usize,
+    pub mesh: Mesh,
+    /// empty if the draw doesn't push anything
+    pub push_constants: Vec<u8>,
+}
+
+/// `shader.vert` and `shader.frag` with `MeshVertex` input and alpha blending. the shaders are
+/// compiled at runtime so they can be hot reloaded, the copies baked in at build time are only
+/// used if that fails
+pub fn default_pipeline_builder(shaders: &mut ShaderManager) -> PipelineBuilder {
+    PipelineBuilder::default()
+        .stage(shaders.load_or_builtin(
+            vk::ShaderStageFlags::VERTEX,
+            "shader.vert",
+            include_glsl!("./shaders/shader.vert"),
+        ))
+        .stage(shaders.load_or_builtin(
+            vk::ShaderStageFlags::FRAGMENT,
+            "shader.frag",
+            include_glsl!("./shaders/shader.frag"),
+        ))
+        .vertex_layout(VertexLayout::default().per_vertex::<MeshVertex>())
+        .blend(BlendMode::Alpha)
+}
+
+/// binds each pipeline only when it changes, the render pass has to be begun already
+pub fn record_draws(
+    logical_device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    pipelines: &[Pipeline],
+    draws: &[Draw],
+) -> Result<(), EngineError> {
+    let mut bound = None;
+    for draw in draws {
+        let pipeline = &pipelines[draw.pipeline];
+        if bound != Some(draw.pipeline) {
+            pipeline.cmd_bind(logical_device, command_buffer);
+            bound = Some(draw.pipeline);
+        }
+        if !draw.push_constants.is_empty() {
+            pipeline.cmd_push_bytes(logical_device, command_buffer, 0, &draw.push_constants)?;
+        }
+        draw.mesh.cmd_draw(logical_device, command_buffer);
+    }
+    Ok(())
+}
+
+/// the debug messenger is only created if `VK_EXT_debug_utils` could be enabled
+pub fn init_debug(
+    entry: &ash::Entry,
+    instance: &ash::Instance,
+    enabled: &EnabledExtensions,
+    config: &EngineConfig,
+) -> Result<Option<Debug>, EngineError> {
+    if enabled.has_instance_extension(ash::extensions::ext::DebugUtils::name()) {
+        Ok(Some(Debug::init(entry, instance, config)?))
+    } else {
+        Ok(None)
+    }
+}
+
+/// returns the instance together with the layers and instance extensions that got enabled
 pub fn init_instance(
     entry: &ash::Entry,
-    layer_names: &[&str],
-) -> Result<ash::Instance, ash::vk::Result> {
-    let app_name = ffi::CString::new("hi :)").unwrap();
+    config: &EngineConfig,
+    surface_extensions: &[&ffi::CStr],
+) -> Result<(ash::Instance, EnabledExtensions), EngineError> {
+    let app_name = ffi::CString::new(config.application_name.as_str())?;
     let app_info = vk::ApplicationInfo::builder()
         .application_name(&app_name)
-        .application_version(vk::make_api_version(0, 1, 0, 0))
-        .api_version(vk::make_api_version(0, 1, 0, 0));
-
-    let layer_names_c: Vec<ffi::CString> = layer_names
-        .iter()
-        .map(|&ln| ffi::CString::new(ln).unwrap())
-        .collect();
-
+        .application_version(config.application_version)
+        .api_version(config.instance_api_version());
+
+    let layer_names = extensions::resolve(
+        "Instance",
+        &extensions::available_layers(entry)?,
+        &config.layers,
+        &config.all_optional_layers(),
+    )
+    .map_err(EngineError::MissingLayers)?;
+    let layer_names_c = config::to_cstrings(&layer_names)?;
     let layer_name_pointers: Vec<*const i8> = layer_names_c.iter().map(|ln| ln.as_ptr()).collect();
 
-    let extension_name_pointers = vec![
-        ash::extensions::ext::DebugUtils::name().as_ptr(),
-        ash::extensions::khr::Surface::name().as_ptr(),
-        ash::extensions::khr::XlibSurface::name().as_ptr(),
-    ];
-
-    let mut debug_create_info = vk::DebugUtilsMessengerCreateInfoEXT::builder()
-        .message_severity(
-            vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
-                | vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE
-                | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
-        )
-        .message_type(
-            vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
-                | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE
-                | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION,
-        )
-        .pfn_user_callback(Some(vulkan_debug_utils_callback));
+    let mut required_extensions = config.instance_extensions.clone();
+    required_extensions.extend(
+        surface_extensions
+            .iter()
+            .map(|&name| extensions::cstr_to_string(name)),
+    );
+    let mut optional_extensions = config.optional_instance_extensions.clone();
+    if config.validation {
+        optional_extensions.push(extensions::cstr_to_string(
+            ash::extensions::ext::DebugUtils::name(),
+        ));
+    }
+    let extension_names = extensions::resolve(
+        "Instance",
+        &extensions::available_instance_extensions(entry, &layer_names)?,
+        &required_extensions,
+        &optional_extensions,
+    )
+    .map_err(EngineError::MissingExtensions)?;
+    let extension_names_c = config::to_cstrings(&extension_names)?;
+    let extension_name_pointers: Vec<*const i8> =
+        extension_names_c.iter().map(|name| name.as_ptr()).collect();
+
+    let enabled = EnabledExtensions {
+        layers: layer_names,
+        instance_extensions: extension_names,
+        device_extensions: Vec::new(),
+    };
+
+    let mut debug_create_info = debug::messenger_create_info(config);
 
-    let instance_create_info = vk::InstanceCreateInfo::builder()
-        .push_next(&mut debug_create_info)
+    let mut instance_create_info = vk::InstanceCreateInfo::builder()
         .application_info(&app_info)
         .enabled_layer_names(&layer_name_pointers)
         .enabled_extension_names(&extension_name_pointers);
+    if enabled.has_instance_extension(ash::extensions::ext::DebugUtils::name()) {
+        instance_create_info = instance_create_info.push_next(&mut debug_create_info);
+    }
 
-    unsafe { entry.create_instance(&instance_create_info, None) }
+    let instance =
+        unsafe { entry.create_instance(&instance_create_info, None) }.call("create_instance")?;
+    Ok((instance, enabled))
 }
 
 pub fn init_physical_devices_and_properties(
     instance: &ash::Instance,
-) -> Result<(vk::PhysicalDevice, vk::PhysicalDeviceProperties), vk::Result> {
-    let physical_devices = unsafe { instance.enumerate_physical_devices().unwrap() };
-    let physical_device = *physical_devices.first().unwrap();
-    let properities = unsafe { instance.get_physical_device_properties(physical_device) };
-    Ok((physical_device, properities))
+    requirements: &DeviceRequirements,
+    device_override: Option<&DeviceOverride>,
+) -> Result<(vk::PhysicalDevice, vk::PhysicalDeviceProperties), EngineError> {
+    device::select_physical_device(instance, requirements, device_override)
 }
 
 pub fn init_devices_and_queues(
     instance: &ash::Instance,
     physical_device: vk::PhysicalDevice,
     queue_families: &QueueFamilies,
-    layer_names: &[&str],
-) -> Result<(ash::Device, Queues), vk::Result> {
-    let layer_names_c: Vec<ffi::CString> = layer_names
-        .iter()
-        .map(|&ln| ffi::CString::new(ln).unwrap())
-        .collect();
+    layer_names: &[String],
+    device_extensions: &[String],
+    features: &vk::PhysicalDeviceFeatures,
+    dynamic_rendering: bool,
+) -> Result<(ash::Device, Queues), EngineError> {
+    let layer_names_c = config::to_cstrings(layer_names)?;
 
     let layer_names_pointers: Vec<*const i8> = layer_names_c
         .iter()
@@ -173,221 +566,143 @@ pub fn init_devices_and_queues(
 
     let priorities = [1.0];
 
-    let queue_infos = [
-        vk::DeviceQueueCreateInfo::builder()
-            .queue_family_index(queue_families.graphics_queue_index.unwrap())
-            .queue_priorities(&priorities)
-            .build(),
-        vk::DeviceQueueCreateInfo::builder()
-            .queue_family_index(queue_families.transfer_queue_index.unwrap())
-            .queue_priorities(&priorities)
-            .build(),
-    ];
-
-    let device_extension_name_pointers = vec![ash::extensions::khr::Swapchain::name().as_ptr()];
-    let device_create_info = vk::DeviceCreateInfo::builder()
+    // vulkan doesn't allow more than one create info per family
+    let queue_infos: Vec<vk::DeviceQueueCreateInfo> = queue_families
+        .unique_indices()
+        .into_iter()
+        .map(|index| {
+            vk::DeviceQueueCreateInfo::builder()
+                .queue_family_index(index)
+                .queue_priorities(&priorities)
+                .build()
+        })
+        .collect();
+
+    let device_extensions_c = config::to_cstrings(device_extensions)?;
+    let device_extension_name_pointers: Vec<*const i8> = device_extensions_c
+        .iter()
+        .map(|extension| extension.as_ptr())
+        .collect();
+    let mut dynamic_rendering_features =
+        vk::PhysicalDeviceDynamicRenderingFeatures::builder().dynamic_rendering(true);
+    let mut device_create_info = vk::DeviceCreateInfo::builder()
         .queue_create_infos(&queue_infos)
         .enabled_extension_names(&device_extension_name_pointers)
-        .enabled_layer_names(&layer_names_pointers);
+        .enabled_layer_names(&layer_names_pointers)
+        .enabled_features(features);
+    if dynamic_rendering {
+        device_create_info = device_create_info.push_next(&mut dynamic_rendering_features);
+    }
 
-    let logical_device = unsafe {
-        instance
-            .create_device(physical_device, &device_create_info, None)
-            .unwrap()
+    let logical_device =
+        unsafe { instance.create_device(physical_device, &device_create_info, None) }
+            .call("create_device")?;
+    let get_queue = |index: u32| unsafe { logical_device.get_device_queue(index, 0) };
+    let queues = Queues {
+        graphics_queue: get_queue(queue_families.graphics()?),
+        present_queue: queue_families
+            .present_queue_index
+            .map_or(vk::Queue::null(), get_queue),
+        compute_queue: get_queue(queue_families.compute()?),
+        transfer_queue: get_queue(queue_families.transfer()?),
     };
-    let graphics_queue =
-        unsafe { logical_device.get_device_queue(queue_families.graphics_queue_index.unwrap(), 0) };
-    let transfer_queue =
-        unsafe { logical_device.get_device_queue(queue_families.transfer_queue_index.unwrap(), 0) };
-
-    Ok((
-        logical_device,
-        Queues {
-            graphics_queue,
-            transfer_queue,
-        },
-    ))
+
+    Ok((logical_device, queues))
 }
 
+/// the render pass pipelines are built against without dynamic rendering, frames are recorded
+/// with a `RenderGraph` whose draw pass (see `add_draw_pass`) is compatible with it. a color
+/// attachment and, with `depth_format`, a depth attachment that is cleared every frame and never
+/// stored. with more than one sample the color attachment is a transient multisampled image,
+/// resolved into the target (the last attachment) at the end of the subpass
 pub fn init_render_pass(
     logical_device: &ash::Device,
-    physical_device: vk::PhysicalDevice,
     format: vk::Format,
-) -> Result<vk::RenderPass, vk::Result> {
-    let attachments = [vk::AttachmentDescription::builder()
+    depth_format: Option<vk::Format>,
+    samples: vk::SampleCountFlags,
+    final_layout: vk::ImageLayout,
+) -> Result<vk::RenderPass, EngineError> {
+    let multisampled = samples != vk::SampleCountFlags::TYPE_1;
+    let (color_store_op, color_final_layout) = if multisampled {
+        (
+            vk::AttachmentStoreOp::DONT_CARE,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        )
+    } else {
+        (vk::AttachmentStoreOp::STORE, final_layout)
+    };
+    let mut attachments = vec![vk::AttachmentDescription::builder()
         .format(format)
         .load_op(vk::AttachmentLoadOp::CLEAR)
-        .store_op(vk::AttachmentStoreOp::STORE)
+        .store_op(color_store_op)
         .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
         .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
         .initial_layout(vk::ImageLayout::UNDEFINED)
-        .final_layout(vk::ImageLayout::PRESENT_SRC_KHR)
-        .samples(vk::SampleCountFlags::TYPE_1)
+        .final_layout(color_final_layout)
+        .samples(samples)
         .build()];
+    if let Some(depth_format) = depth_format {
+        let stencil_load_op = if depth::has_stencil(depth_format) {
+            vk::AttachmentLoadOp::CLEAR
+        } else {
+            vk::AttachmentLoadOp::DONT_CARE
+        };
+        attachments.push(
+            vk::AttachmentDescription::builder()
+                .format(depth_format)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .stencil_load_op(stencil_load_op)
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+                .samples(samples)
+                .build(),
+        );
+    }
+    if multisampled {
+        attachments.push(
+            vk::AttachmentDescription::builder()
+                .format(format)
+                .load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .final_layout(final_layout)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .build(),
+        );
+    }
 
     let color_attachment_references = [vk::AttachmentReference {
         attachment: 0,
         layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
     }];
+    let depth_attachment_reference = vk::AttachmentReference {
+        attachment: 1,
+        layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+    };
+    let resolve_attachment_references = [vk::AttachmentReference {
+        attachment: attachments.len() as u32 - 1,
+        layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+    }];
 
-    let subpasses = [vk::SubpassDescription::builder()
+    let mut subpass = vk::SubpassDescription::builder()
         .color_attachments(&color_attachment_references)
-        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-        .build()];
-
-    let subpass_dependencies = [vk::SubpassDependency::builder()
-        .src_subpass(vk::SUBPASS_EXTERNAL)
-        .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-        .dst_subpass(0)
-        .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-        .dst_access_mask(
-            vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
-        )
-        .build()];
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS);
+    if depth_format.is_some() {
+        subpass = subpass.depth_stencil_attachment(&depth_attachment_reference);
+    }
+    if multisampled {
+        subpass = subpass.resolve_attachments(&resolve_attachment_references);
+    }
+    let subpasses = [subpass.build()];
 
+    // the graph synchronizes its passes with barriers, compatibility ignores dependencies
     let render_pass_info = vk::RenderPassCreateInfo::builder()
         .attachments(&attachments)
-        .subpasses(&subpasses)
-        .dependencies(&subpass_dependencies);
+        .subpasses(&subpasses);
 
-    unsafe { logical_device.create_render_pass(&render_pass_info, None) }
-}
-
-pub struct Pipeline {
-    pub pipeline: vk::Pipeline,
-    pub layout: vk::PipelineLayout,
-}
-
-impl Pipeline {
-    pub fn cleanup(&self, logical_device: &ash::Device) {
-        unsafe {
-            logical_device.destroy_pipeline(self.pipeline, None);
-            logical_device.destroy_pipeline_layout(self.layout, None);
-        }
-    }
-
-    pub fn init(
-        logical_device: &ash::Device,
-        swapchain: &SwapChain,
-        render_pass: &vk::RenderPass,
-    ) -> Result<Pipeline, vk::Result> {
-        let vertex_shader_create_info =
-            vk::ShaderModuleCreateInfo::builder().code(include_glsl!("./shaders/shader.vert"));
-
-        let vertex_shader_module = unsafe {
-            logical_device
-                .create_shader_module(&vertex_shader_create_info, None)
-                .unwrap()
-        };
-
-        let entry_point = ffi::CString::new("main").unwrap();
-
-        let vertex_shader_stage = vk::PipelineShaderStageCreateInfo::builder()
-            .stage(vk::ShaderStageFlags::VERTEX)
-            .module(vertex_shader_module)
-            .name(&entry_point);
-
-        let fragment_shader_create_info =
-            vk::ShaderModuleCreateInfo::builder().code(include_glsl!("./shaders/shader.frag"));
-        let fragment_shader_module = unsafe {
-            logical_device
-                .create_shader_module(&fragment_shader_create_info, None)
-                .unwrap()
-        };
-        let fragment_shader_stage = vk::PipelineShaderStageCreateInfo::builder()
-            .stage(vk::ShaderStageFlags::FRAGMENT)
-            .module(fragment_shader_module)
-            .name(&entry_point);
-
-        let shader_stages = vec![vertex_shader_stage.build(), fragment_shader_stage.build()];
-
-        let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder();
-
-        let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
-            .topology(vk::PrimitiveTopology::POINT_LIST);
-
-        let viewports = [vk::Viewport {
-            x: 0.0,
-            y: 0.0,
-            width: swapchain.extent.width as f32,
-            height: swapchain.extent.height as f32,
-            min_depth: 0.0,
-            max_depth: 0.0,
-        }];
-
-        let scissors = [vk::Rect2D {
-            offset: vk::Offset2D { x: 0, y: 0 },
-            extent: swapchain.extent,
-        }];
-
-        let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
-            .viewports(&viewports)
-            .scissors(&scissors);
-
-        let rasterizing_info = vk::PipelineRasterizationStateCreateInfo::builder()
-            .line_width(1.0)
-            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
-            .cull_mode(vk::CullModeFlags::NONE)
-            .polygon_mode(vk::PolygonMode::FILL);
-
-        let multisampler_info = vk::PipelineMultisampleStateCreateInfo::builder()
-            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
-
-        let color_blend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
-            .blend_enable(true)
-            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
-            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
-            .color_blend_op(vk::BlendOp::ADD)
-            .src_alpha_blend_factor(vk::BlendFactor::SRC_ALPHA)
-            .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
-            .alpha_blend_op(vk::BlendOp::ADD)
-            .color_write_mask(
-                vk::ColorComponentFlags::R
-                    | vk::ColorComponentFlags::G
-                    | vk::ColorComponentFlags::A,
-            )
-            .build()];
-
-        let color_blend_info =
-            vk::PipelineColorBlendStateCreateInfo::builder().attachments(&color_blend_attachments);
-
-        let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder();
-        let pipeline_layout = unsafe {
-            logical_device
-                .create_pipeline_layout(&pipeline_layout_info, None)
-                .unwrap()
-        };
-
-        let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
-            .stages(&shader_stages)
-            .vertex_input_state(&vertex_input_info)
-            .input_assembly_state(&input_assembly_info)
-            .viewport_state(&viewport_info)
-            .rasterization_state(&rasterizing_info)
-            .multisample_state(&multisampler_info)
-            .color_blend_state(&color_blend_info)
-            .layout(pipeline_layout)
-            .render_pass(*render_pass)
-            .subpass(0);
-
-        let pipeline = unsafe {
-            logical_device
-                .create_graphics_pipelines(
-                    vk::PipelineCache::null(),
-                    &[pipeline_info.build()],
-                    None,
-                )
-                .unwrap()
-        }[0];
-
-        unsafe {
-            logical_device.destroy_shader_module(vertex_shader_module, None);
-            logical_device.destroy_shader_module(fragment_shader_module, None);
-        };
-
-        Ok(Pipeline {
-            layout: pipeline_layout,
-            pipeline,
-        })
-    }
+    unsafe { logical_device.create_render_pass(&render_pass_info, None) }.call("create_render_pass")
 }
@@ -0,0 +1,42 @@
+use ash::vk;
+
+/// the sample counts a render target can use, with `depth` it needs a depth attachment with the
+/// same count as well
+pub fn supported_sample_counts(
+    limits: &vk::PhysicalDeviceLimits,
+    depth: bool,
+) -> vk::SampleCountFlags {
+    if depth {
+        limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts
+    } else {
+        limits.framebuffer_color_sample_counts
+    }
+}
+
+/// the highest supported count up to `requested` (1, 2, 4 or 8). anything below 2 turns msaa off
+pub fn choose_sample_count(
+    requested: u32,
+    limits: &vk::PhysicalDeviceLimits,
+    depth: bool,
+) -> vk::SampleCountFlags {
+    let supported = supported_sample_counts(limits, depth);
+    [
+        vk::SampleCountFlags::TYPE_8,
+        vk::SampleCountFlags::TYPE_4,
+        vk::SampleCountFlags::TYPE_2,
+    ]
+    .into_iter()
+    .find(|&samples| samples.as_raw() <= requested && supported.contains(samples))
+    .unwrap_or(vk::SampleCountFlags::TYPE_1)
+}
+
+/// `sample_shading` if it has any effect, which needs msaa and the `sample_rate_shading` feature
+pub fn choose_sample_shading(
+    sample_shading: Option<f32>,
+    samples: vk::SampleCountFlags,
+    features: &vk::PhysicalDeviceFeatures,
+) -> Option<f32> {
+    sample_shading.filter(|_| {
+        samples != vk::SampleCountFlags::TYPE_1 && features.sample_rate_shading == vk::TRUE
+    })
+}
@@ -0,0 +1,141 @@
+use ash::vk;
+
+use super::{
+    allocator::{Allocation, Allocator, MemoryLocation},
+    error::{EngineError, VkResultExt},
+};
+
+/// a color image rendered to instead of a swapchain image, plus a host visible buffer the
+/// finished frame gets copied into so it can be read on the cpu. with msaa the image is the
+/// resolve target of the draw pass
+pub struct OffscreenTarget {
+    pub image: vk::Image,
+    pub image_allocation: Allocation,
+    pub image_view: vk::ImageView,
+    pub readback_buffer: vk::Buffer,
+    pub readback_allocation: Allocation,
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+}
+
+impl OffscreenTarget {
+    pub const FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+    pub const BYTES_PER_PIXEL: u64 = 4;
+
+    pub fn init(
+        logical_device: &ash::Device,
+        allocator: &mut Allocator,
+        extent: vk::Extent2D,
+    ) -> Result<OffscreenTarget, EngineError> {
+        let image_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(Self::FORMAT)
+            .extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            })
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+        let (image, image_allocation) =
+            allocator.create_image(logical_device, &image_info, MemoryLocation::GpuOnly)?;
+
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(1);
+        let image_view_info = vk::ImageViewCreateInfo::builder()
+            .image(image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(Self::FORMAT)
+            .subresource_range(*subresource_range);
+        let image_view = unsafe { logical_device.create_image_view(&image_view_info, None) }
+            .call("create_image_view")?;
+
+        let buffer_info = vk::BufferCreateInfo::builder()
+            .size(extent.width as u64 * extent.height as u64 * Self::BYTES_PER_PIXEL)
+            .usage(vk::BufferUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let (readback_buffer, readback_allocation) =
+            allocator.create_buffer(logical_device, &buffer_info, MemoryLocation::GpuToCpu)?;
+
+        Ok(OffscreenTarget {
+            image,
+            image_allocation,
+            image_view,
+            readback_buffer,
+            readback_allocation,
+            format: Self::FORMAT,
+            extent,
+        })
+    }
+
+    /// records the copy of the rendered image into the readback buffer, the image has to be in
+    /// `TRANSFER_SRC_OPTIMAL`
+    pub fn cmd_copy_to_readback(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+    ) {
+        let region = vk::BufferImageCopy::builder()
+            .buffer_offset(0)
+            .buffer_row_length(0)
+            .buffer_image_height(0)
+            .image_subresource(vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+            .image_extent(vk::Extent3D {
+                width: self.extent.width,
+                height: self.extent.height,
+                depth: 1,
+            });
+        unsafe {
+            logical_device.cmd_copy_image_to_buffer(
+                command_buffer,
+                self.image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                self.readback_buffer,
+                &[region.build()],
+            );
+        }
+    }
+
+    /// copies the readback buffer out as tightly packed rgba8 rows
+    pub fn read_pixels(&self) -> Vec<u8> {
+        let size = self.extent.width as usize
+            * self.extent.height as usize
+            * Self::BYTES_PER_PIXEL as usize;
+        let mapped = self
+            .readback_allocation
+            .mapped_slice()
+            .expect("readback memory is host visible");
+        mapped[..size].to_vec()
+    }
+
+    /// # Safety
+    /// nothing may still be rendering to or copying from the target
+    pub unsafe fn cleanup(&mut self, logical_device: &ash::Device, allocator: &mut Allocator) {
+        logical_device.destroy_image_view(self.image_view, None);
+        allocator.destroy_image(
+            logical_device,
+            self.image,
+            std::mem::take(&mut self.image_allocation),
+        );
+        allocator.destroy_buffer(
+            logical_device,
+            self.readback_buffer,
+            std::mem::take(&mut self.readback_allocation),
+        );
+    }
+}
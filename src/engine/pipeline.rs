@@ -0,0 +1,715 @@
+use std::{borrow::Cow, ffi, mem, path::PathBuf};
+
+use ash::vk;
+
+use super::{
+    buffer::{as_bytes, Pod},
+    depth,
+    descriptor::DescriptorLayoutCache,
+    error::{EngineError, VkResultExt},
+    reflect::PipelineReflection,
+    vertex::VertexLayout,
+};
+
+/// spir-v for one stage, either baked in with `include_glsl!` or loaded at runtime
+#[derive(Debug, Clone)]
+pub struct ShaderStage {
+    pub stage: vk::ShaderStageFlags,
+    pub code: Cow<'static, [u32]>,
+    pub entry_point: String,
+    /// the file the code was compiled from, for hot reloading
+    pub source: Option<PathBuf>,
+}
+
+impl ShaderStage {
+    pub fn new(stage: vk::ShaderStageFlags, code: impl Into<Cow<'static, [u32]>>) -> Self {
+        ShaderStage {
+            stage,
+            code: code.into(),
+            entry_point: "main".to_string(),
+            source: None,
+        }
+    }
+
+    pub fn vertex(code: impl Into<Cow<'static, [u32]>>) -> Self {
+        ShaderStage::new(vk::ShaderStageFlags::VERTEX, code)
+    }
+
+    pub fn fragment(code: impl Into<Cow<'static, [u32]>>) -> Self {
+        ShaderStage::new(vk::ShaderStageFlags::FRAGMENT, code)
+    }
+
+    pub fn entry_point(mut self, entry_point: impl Into<String>) -> Self {
+        self.entry_point = entry_point.into();
+        self
+    }
+
+    pub fn source(mut self, source: impl Into<PathBuf>) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlendMode {
+    /// overwrites the attachment
+    #[default]
+    Opaque,
+    /// `src * a + dst * (1 - a)`
+    Alpha,
+    /// `src * a + dst`
+    Additive,
+    /// `src + dst * (1 - a)`, for colors already multiplied by their alpha
+    Premultiplied,
+}
+
+impl BlendMode {
+    pub fn attachment_state(self) -> vk::PipelineColorBlendAttachmentState {
+        let (src_color, dst_color) = match self {
+            BlendMode::Opaque => (vk::BlendFactor::ONE, vk::BlendFactor::ZERO),
+            BlendMode::Alpha => (
+                vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            ),
+            BlendMode::Additive => (vk::BlendFactor::SRC_ALPHA, vk::BlendFactor::ONE),
+            BlendMode::Premultiplied => {
+                (vk::BlendFactor::ONE, vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            }
+        };
+        let (src_alpha, dst_alpha) = match self {
+            BlendMode::Opaque => (vk::BlendFactor::ONE, vk::BlendFactor::ZERO),
+            BlendMode::Additive => (vk::BlendFactor::ONE, vk::BlendFactor::ONE),
+            BlendMode::Alpha | BlendMode::Premultiplied => {
+                (vk::BlendFactor::ONE, vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            }
+        };
+        vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(self != BlendMode::Opaque)
+            .src_color_blend_factor(src_color)
+            .dst_color_blend_factor(dst_color)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(src_alpha)
+            .dst_alpha_blend_factor(dst_alpha)
+            .alpha_blend_op(vk::BlendOp::ADD)
+            .color_write_mask(vk::ColorComponentFlags::RGBA)
+            .build()
+    }
+}
+
+/// describes a graphics pipeline, set up like the ash builders:
+///
+/// ```ignore
+/// let pipeline = PipelineBuilder::default()
+///     .stage(ShaderStage::vertex(include_glsl!("./shaders/shader.vert")))
+///     .stage(ShaderStage::fragment(include_glsl!("./shaders/shader.frag")))
+///     .vertex_layout(VertexLayout::default().per_vertex::<MeshVertex>())
+///     .cull_mode(vk::CullModeFlags::BACK)
+///     .blend(BlendMode::Alpha)
+///     .build(&device, &mut layout_cache, render_pass, 0)?;
+/// ```
+///
+/// viewport and scissor are dynamic by default, so pipelines survive a resize. the builder is
+/// kept in the `Pipeline`, so it can be rebuilt against a new render pass. building with a null
+/// render pass targets dynamic rendering with `color_formats` and `depth_format` instead
+///
+/// the stages are reflected when building: without set layouts or push constant ranges they are
+/// derived from the shaders (the set layouts come from the `DescriptorLayoutCache`), without a
+/// vertex layout the inputs are packed into binding 0. whatever is given has to match what the
+/// shaders declare
+#[derive(Debug, Clone)]
+pub struct PipelineBuilder {
+    pub stages: Vec<ShaderStage>,
+    pub vertex_layout: VertexLayout,
+    pub topology: vk::PrimitiveTopology,
+    pub polygon_mode: vk::PolygonMode,
+    pub cull_mode: vk::CullModeFlags,
+    pub front_face: vk::FrontFace,
+    pub line_width: f32,
+    pub depth_test: bool,
+    pub depth_write: bool,
+    pub depth_compare_op: vk::CompareOp,
+    /// front and back, `None` disables the stencil test
+    pub stencil: Option<(vk::StencilOpState, vk::StencilOpState)>,
+    /// has to match the render pass, the engines fill it in
+    pub samples: vk::SampleCountFlags,
+    /// shades every sample instead of once per pixel, smoothing aliasing inside triangles too.
+    /// the fraction of samples that are shaded, needs the `sample_rate_shading` feature
+    pub min_sample_shading: Option<f32>,
+    /// one per color attachment of the subpass
+    pub blend: Vec<BlendMode>,
+    /// the attachment formats for dynamic rendering, one per color attachment. only used
+    /// without a render pass, the engines fill them in
+    pub color_formats: Vec<vk::Format>,
+    pub depth_format: Option<vk::Format>,
+    pub dynamic_states: Vec<vk::DynamicState>,
+    /// fixed viewport and scissor, only used without the dynamic states
+    pub static_extent: Option<vk::Extent2D>,
+    /// empty to create them from the reflection
+    pub descriptor_set_layouts: Vec<vk::DescriptorSetLayout>,
+    /// empty to take them from the reflection
+    pub push_constant_ranges: Vec<vk::PushConstantRange>,
+    /// `limits.max_push_constants_size` of the device, the engines fill it in. 128 is the
+    /// minimum every device supports
+    pub max_push_constants_size: u32,
+}
+
+impl Default for PipelineBuilder {
+    fn default() -> Self {
+        PipelineBuilder {
+            stages: Vec::new(),
+            vertex_layout: VertexLayout::default(),
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            polygon_mode: vk::PolygonMode::FILL,
+            cull_mode: vk::CullModeFlags::NONE,
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+            line_width: 1.0,
+            depth_test: false,
+            depth_write: false,
+            depth_compare_op: vk::CompareOp::LESS,
+            stencil: None,
+            samples: vk::SampleCountFlags::TYPE_1,
+            min_sample_shading: None,
+            blend: vec![BlendMode::Opaque],
+            color_formats: Vec::new(),
+            depth_format: None,
+            dynamic_states: vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR],
+            static_extent: None,
+            descriptor_set_layouts: Vec::new(),
+            push_constant_ranges: Vec::new(),
+            max_push_constants_size: 128,
+        }
+    }
+}
+
+impl PipelineBuilder {
+    pub fn stage(mut self, stage: ShaderStage) -> Self {
+        self.stages.push(stage);
+        self
+    }
+
+    pub fn vertex_layout(mut self, vertex_layout: VertexLayout) -> Self {
+        self.vertex_layout = vertex_layout;
+        self
+    }
+
+    pub fn topology(mut self, topology: vk::PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    /// anything but `FILL` needs the `fillModeNonSolid` feature
+    pub fn polygon_mode(mut self, polygon_mode: vk::PolygonMode) -> Self {
+        self.polygon_mode = polygon_mode;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: vk::CullModeFlags) -> Self {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    pub fn front_face(mut self, front_face: vk::FrontFace) -> Self {
+        self.front_face = front_face;
+        self
+    }
+
+    /// widths other than 1.0 need the `wideLines` feature
+    pub fn line_width(mut self, line_width: f32) -> Self {
+        self.line_width = line_width;
+        self
+    }
+
+    pub fn depth(mut self, test: bool, write: bool, compare_op: vk::CompareOp) -> Self {
+        self.depth_test = test;
+        self.depth_write = write;
+        self.depth_compare_op = compare_op;
+        self
+    }
+
+    pub fn stencil(mut self, front: vk::StencilOpState, back: vk::StencilOpState) -> Self {
+        self.stencil = Some((front, back));
+        self
+    }
+
+    pub fn samples(mut self, samples: vk::SampleCountFlags) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    pub fn sample_shading(mut self, min_sample_shading: Option<f32>) -> Self {
+        self.min_sample_shading = min_sample_shading;
+        self
+    }
+
+    /// the same blend mode for every color attachment
+    pub fn blend(mut self, blend: BlendMode) -> Self {
+        let attachments = self.blend.len().max(1);
+        self.blend = vec![blend; attachments];
+        self
+    }
+
+    pub fn attachment_blends(mut self, blend: Vec<BlendMode>) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    pub fn rendering_formats(
+        mut self,
+        color_formats: Vec<vk::Format>,
+        depth_format: Option<vk::Format>,
+    ) -> Self {
+        self.color_formats = color_formats;
+        self.depth_format = depth_format;
+        self
+    }
+
+    pub fn dynamic_state(mut self, dynamic_state: vk::DynamicState) -> Self {
+        if !self.dynamic_states.contains(&dynamic_state) {
+            self.dynamic_states.push(dynamic_state);
+        }
+        self
+    }
+
+    /// bakes viewport and scissor into the pipeline, it has to be rebuilt on resize
+    pub fn static_extent(mut self, extent: vk::Extent2D) -> Self {
+        self.static_extent = Some(extent);
+        self.dynamic_states.retain(|&state| {
+            state != vk::DynamicState::VIEWPORT && state != vk::DynamicState::SCISSOR
+        });
+        self
+    }
+
+    /// the pipeline doesn't take ownership of the set layouts
+    pub fn descriptor_set_layout(mut self, layout: vk::DescriptorSetLayout) -> Self {
+        self.descriptor_set_layouts.push(layout);
+        self
+    }
+
+    pub fn push_constant_range(mut self, range: vk::PushConstantRange) -> Self {
+        self.push_constant_ranges.push(range);
+        self
+    }
+
+    /// a range holding a `T`, placed after the ranges declared so far. push it with
+    /// `Pipeline::cmd_push` at the same offset
+    pub fn push_constants<T: Pod>(self, stage_flags: vk::ShaderStageFlags) -> Self {
+        let offset = self
+            .push_constant_ranges
+            .iter()
+            .map(|range| range.offset + range.size)
+            .max()
+            .unwrap_or(0)
+            .next_multiple_of(4);
+        self.push_constant_range(vk::PushConstantRange {
+            stage_flags,
+            offset,
+            size: mem::size_of::<T>() as u32,
+        })
+    }
+
+    pub fn max_push_constants_size(mut self, max_push_constants_size: u32) -> Self {
+        self.max_push_constants_size = max_push_constants_size;
+        self
+    }
+
+    pub fn build(
+        self,
+        logical_device: &ash::Device,
+        layout_cache: &mut DescriptorLayoutCache,
+        render_pass: vk::RenderPass,
+        subpass: u32,
+    ) -> Result<Pipeline, EngineError> {
+        let reflection = PipelineReflection::from_stages(&self.stages)?;
+        let vertex_layout = if self.vertex_layout.attributes.is_empty() {
+            reflection.vertex_layout()?
+        } else {
+            reflection.check_vertex_layout(&self.vertex_layout)?;
+            self.vertex_layout.clone()
+        };
+        let push_constant_ranges = if self.push_constant_ranges.is_empty() {
+            reflection.push_constant_ranges.clone()
+        } else {
+            // the declared ranges have to hold everything the shaders read
+            for range in &reflection.push_constant_ranges {
+                push_constant_stages(&self.push_constant_ranges, range.offset, range.size)
+                    .filter(|stages| stages.contains(range.stage_flags))
+                    .ok_or_else(|| {
+                        EngineError::PushConstants(format!(
+                            "the shaders read {} bytes in {:?}, which the declared ranges don't cover",
+                            range.size, range.stage_flags
+                        ))
+                    })?;
+            }
+            self.push_constant_ranges.clone()
+        };
+        for range in &push_constant_ranges {
+            if range.size == 0 || !range.offset.is_multiple_of(4) || !range.size.is_multiple_of(4) {
+                return Err(EngineError::PushConstants(format!(
+                    "range at {} with {} bytes isn't a non-empty multiple of 4",
+                    range.offset, range.size
+                )));
+            }
+            if range.offset + range.size > self.max_push_constants_size {
+                return Err(EngineError::PushConstants(format!(
+                    "range at {} with {} bytes exceeds the device limit of {} bytes",
+                    range.offset, range.size, self.max_push_constants_size
+                )));
+            }
+        }
+        let set_layouts = if self.descriptor_set_layouts.is_empty() {
+            reflection.set_layouts(logical_device, layout_cache)?
+        } else {
+            self.descriptor_set_layouts.clone()
+        };
+
+        let layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let layout = unsafe { logical_device.create_pipeline_layout(&layout_info, None) }
+            .call("create_pipeline_layout")?;
+
+        match self.create_pipeline(logical_device, layout, &vertex_layout, render_pass, subpass) {
+            Ok(pipeline) => Ok(Pipeline {
+                pipeline,
+                layout,
+                set_layouts,
+                push_constant_ranges,
+                reflection,
+                render_pass,
+                subpass,
+                builder: self,
+            }),
+            Err(err) => {
+                unsafe { logical_device.destroy_pipeline_layout(layout, None) };
+                Err(err)
+            }
+        }
+    }
+
+    fn create_pipeline(
+        &self,
+        logical_device: &ash::Device,
+        layout: vk::PipelineLayout,
+        vertex_layout: &VertexLayout,
+        render_pass: vk::RenderPass,
+        subpass: u32,
+    ) -> Result<vk::Pipeline, EngineError> {
+        let mut modules = Vec::with_capacity(self.stages.len());
+        let result = self.create_pipeline_with_modules(
+            logical_device,
+            layout,
+            vertex_layout,
+            render_pass,
+            subpass,
+            &mut modules,
+        );
+        for module in modules {
+            unsafe { logical_device.destroy_shader_module(module, None) };
+        }
+        result
+    }
+
+    fn create_pipeline_with_modules(
+        &self,
+        logical_device: &ash::Device,
+        layout: vk::PipelineLayout,
+        vertex_layout: &VertexLayout,
+        render_pass: vk::RenderPass,
+        subpass: u32,
+        modules: &mut Vec<vk::ShaderModule>,
+    ) -> Result<vk::Pipeline, EngineError> {
+        let entry_points = self
+            .stages
+            .iter()
+            .map(|stage| ffi::CString::new(stage.entry_point.as_str()))
+            .collect::<Result<Vec<_>, _>>()?;
+        for stage in &self.stages {
+            let module_info = vk::ShaderModuleCreateInfo::builder().code(&stage.code);
+            modules.push(
+                unsafe { logical_device.create_shader_module(&module_info, None) }
+                    .call("create_shader_module")?,
+            );
+        }
+        let shader_stages: Vec<vk::PipelineShaderStageCreateInfo> = self
+            .stages
+            .iter()
+            .zip(modules.iter())
+            .zip(entry_points.iter())
+            .map(|((stage, &module), entry_point)| {
+                vk::PipelineShaderStageCreateInfo::builder()
+                    .stage(stage.stage)
+                    .module(module)
+                    .name(entry_point)
+                    .build()
+            })
+            .collect();
+
+        let vertex_input_info = vertex_layout.create_info();
+
+        let input_assembly_info =
+            vk::PipelineInputAssemblyStateCreateInfo::builder().topology(self.topology);
+
+        let extent = self.static_extent.unwrap_or_default();
+        let viewports = [vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: extent.width as f32,
+            height: extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        }];
+        let scissors = [vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent,
+        }];
+        // with dynamic viewport and scissor only the counts matter
+        let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
+            .viewports(&viewports)
+            .scissors(&scissors);
+
+        let rasterizing_info = vk::PipelineRasterizationStateCreateInfo::builder()
+            .line_width(self.line_width)
+            .front_face(self.front_face)
+            .cull_mode(self.cull_mode)
+            .polygon_mode(self.polygon_mode);
+
+        let multisampler_info = vk::PipelineMultisampleStateCreateInfo::builder()
+            .rasterization_samples(self.samples)
+            .sample_shading_enable(self.min_sample_shading.is_some())
+            .min_sample_shading(self.min_sample_shading.unwrap_or(0.0).clamp(0.0, 1.0));
+
+        let (stencil_front, stencil_back) = self.stencil.unwrap_or_default();
+        let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(self.depth_test)
+            .depth_write_enable(self.depth_write)
+            .depth_compare_op(self.depth_compare_op)
+            .stencil_test_enable(self.stencil.is_some())
+            .front(stencil_front)
+            .back(stencil_back)
+            .min_depth_bounds(0.0)
+            .max_depth_bounds(1.0);
+
+        let color_blend_attachments: Vec<vk::PipelineColorBlendAttachmentState> = self
+            .blend
+            .iter()
+            .map(|blend| blend.attachment_state())
+            .collect();
+        let color_blend_info =
+            vk::PipelineColorBlendStateCreateInfo::builder().attachments(&color_blend_attachments);
+
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&self.dynamic_states);
+
+        let stencil_format = self
+            .depth_format
+            .filter(|&format| depth::has_stencil(format))
+            .unwrap_or(vk::Format::UNDEFINED);
+        let mut rendering_info = vk::PipelineRenderingCreateInfo::builder()
+            .color_attachment_formats(&self.color_formats)
+            .depth_attachment_format(self.depth_format.unwrap_or(vk::Format::UNDEFINED))
+            .stencil_attachment_format(stencil_format);
+
+        let mut pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_info)
+            .input_assembly_state(&input_assembly_info)
+            .viewport_state(&viewport_info)
+            .rasterization_state(&rasterizing_info)
+            .multisample_state(&multisampler_info)
+            .depth_stencil_state(&depth_stencil_info)
+            .color_blend_state(&color_blend_info)
+            .dynamic_state(&dynamic_state_info)
+            .layout(layout)
+            .render_pass(render_pass)
+            .subpass(subpass);
+        if render_pass == vk::RenderPass::null() {
+            pipeline_info = pipeline_info.push_next(&mut rendering_info);
+        }
+
+        let pipelines = unsafe {
+            logical_device.create_graphics_pipelines(
+                vk::PipelineCache::null(),
+                &[pipeline_info.build()],
+                None,
+            )
+        };
+        Ok(pipelines
+            .map_err(|(_, result)| result)
+            .call("create_graphics_pipelines")?[0])
+    }
+}
+
+pub struct Pipeline {
+    pub pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+    /// the builder's set layouts, or the cached ones matching the reflection. allocate the
+    /// sets bound with this pipeline from these
+    pub set_layouts: Vec<vk::DescriptorSetLayout>,
+    /// the declared ranges, or the reflected ones
+    pub push_constant_ranges: Vec<vk::PushConstantRange>,
+    pub reflection: PipelineReflection,
+    /// null for dynamic rendering
+    pub render_pass: vk::RenderPass,
+    pub subpass: u32,
+    pub builder: PipelineBuilder,
+}
+
+impl Pipeline {
+    /// builds the pipeline again from its builder, e.g. after the render pass was recreated.
+    /// the old pipeline is only destroyed once the new one was created
+    ///
+    /// # Safety
+    /// the gpu must be done with the old pipeline
+    pub unsafe fn rebuild(
+        &mut self,
+        logical_device: &ash::Device,
+        layout_cache: &mut DescriptorLayoutCache,
+        render_pass: vk::RenderPass,
+    ) -> Result<(), EngineError> {
+        let pipeline =
+            self.builder
+                .clone()
+                .build(logical_device, layout_cache, render_pass, self.subpass)?;
+        let mut old_pipeline = std::mem::replace(self, pipeline);
+        old_pipeline.cleanup(logical_device);
+        Ok(())
+    }
+
+    pub fn cmd_bind(&self, logical_device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+        }
+    }
+
+    /// binds `sets` starting at `first_set` with this pipeline's layout
+    pub fn cmd_bind_descriptor_sets(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        first_set: u32,
+        sets: &[vk::DescriptorSet],
+    ) {
+        unsafe {
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.layout,
+                first_set,
+                sets,
+                &[],
+            );
+        }
+    }
+
+    /// checks that `size` bytes at `offset` lie within the push constant ranges and returns the
+    /// stages to push them to
+    pub fn push_constant_stages(
+        &self,
+        offset: u32,
+        size: u32,
+    ) -> Result<vk::ShaderStageFlags, EngineError> {
+        push_constant_stages(&self.push_constant_ranges, offset, size).ok_or_else(|| {
+            EngineError::PushConstants(format!(
+                "{size} bytes at {offset} aren't covered by the pipeline's ranges {:?}",
+                self.push_constant_ranges
+            ))
+        })
+    }
+
+    /// pushes `data` at `offset`, which has to lie within the pipeline's push constant ranges
+    pub fn cmd_push<T: Pod>(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        offset: u32,
+        data: &T,
+    ) -> Result<(), EngineError> {
+        self.cmd_push_bytes(
+            logical_device,
+            command_buffer,
+            offset,
+            as_bytes(std::slice::from_ref(data)),
+        )
+    }
+
+    pub fn cmd_push_bytes(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        offset: u32,
+        bytes: &[u8],
+    ) -> Result<(), EngineError> {
+        let stages = self.push_constant_stages(offset, bytes.len() as u32)?;
+        unsafe {
+            logical_device.cmd_push_constants(command_buffer, self.layout, stages, offset, bytes);
+        }
+        Ok(())
+    }
+
+    /// # Safety
+    /// the gpu must be done with the pipeline
+    pub unsafe fn cleanup(&mut self, logical_device: &ash::Device) {
+        logical_device.destroy_pipeline(self.pipeline, None);
+        logical_device.destroy_pipeline_layout(self.layout, None);
+    }
+}
+
+/// vulkan wants the stages of every range overlapping the pushed bytes, and each of those stages
+/// has to have a range covering every byte. `None` if that doesn't hold
+fn push_constant_stages(
+    ranges: &[vk::PushConstantRange],
+    offset: u32,
+    size: u32,
+) -> Option<vk::ShaderStageFlags> {
+    let end = offset + size;
+    if size == 0 || !offset.is_multiple_of(4) || !size.is_multiple_of(4) {
+        return None;
+    }
+    let stages = ranges
+        .iter()
+        .filter(|range| range.offset < end && offset < range.offset + range.size)
+        .fold(vk::ShaderStageFlags::empty(), |stages, range| {
+            stages | range.stage_flags
+        });
+    let covered = (0..32)
+        .map(|bit| vk::ShaderStageFlags::from_raw(1 << bit))
+        .filter(|&stage| stages.contains(stage))
+        .all(|stage| {
+            (offset..end).step_by(4).all(|byte| {
+                ranges.iter().any(|range| {
+                    range.stage_flags.contains(stage)
+                        && range.offset <= byte
+                        && byte < range.offset + range.size
+                })
+            })
+        });
+    (!stages.is_empty() && covered).then_some(stages)
+}
+
+/// sets the dynamic viewport and scissor to cover `extent`
+pub fn cmd_set_viewport(
+    logical_device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    extent: vk::Extent2D,
+) {
+    let viewports = [vk::Viewport {
+        x: 0.0,
+        y: 0.0,
+        width: extent.width as f32,
+        height: extent.height as f32,
+        min_depth: 0.0,
+        max_depth: 1.0,
+    }];
+    let scissors = [vk::Rect2D {
+        offset: vk::Offset2D { x: 0, y: 0 },
+        extent,
+    }];
+    unsafe {
+        logical_device.cmd_set_viewport(command_buffer, 0, &viewports);
+        logical_device.cmd_set_scissor(command_buffer, 0, &scissors);
+    }
+}
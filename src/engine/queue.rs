@@ -1,11 +1,16 @@
 use ash::vk;
 
-use super::surface::Surfaces;
-
-
+use super::{error::EngineError, surface::Surfaces};
 
+/// the families the engine submits to. several of them can point at the same family when the
+/// device has no dedicated one, use `unique_indices` when creating the device
 pub struct QueueFamilies {
     pub graphics_queue_index: Option<u32>,
+    /// `None` without a surface (headless)
+    pub present_queue_index: Option<u32>,
+    /// prefers a family without graphics, so compute work can run alongside rendering
+    pub compute_queue_index: Option<u32>,
+    /// prefers a family with only transfer (usually a dma engine), falls back to graphics
     pub transfer_queue_index: Option<u32>,
 }
 
@@ -13,40 +18,120 @@ impl QueueFamilies {
     pub fn init(
         instance: &ash::Instance,
         physical_device: vk::PhysicalDevice,
-        surface: &Surfaces,
-    ) -> Result<QueueFamilies, vk::Result> {
+        surface: Option<&Surfaces>,
+    ) -> Result<QueueFamilies, EngineError> {
         let queue_family_properties =
             unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
 
-        let mut found_graphics_queue_index = None;
-        let mut found_transfer_queue_index = None;
-
-        for (index, queue_family) in queue_family_properties.iter().enumerate() {
-            if queue_family.queue_count > 0 {
-                if queue_family.queue_flags.contains(vk::QueueFlags::GRAPHICS) {
-                    if surface
-                        .check_support(physical_device, index as usize)
-                        .unwrap()
-                    {
-                        found_graphics_queue_index = Some(index as u32);
-                    }
-                } else if queue_family.queue_flags.contains(vk::QueueFlags::TRANSFER) {
-                    if found_transfer_queue_index.is_none() // prioritise trasnger queues without graphics
-                        || !queue_family.queue_flags.contains(vk::QueueFlags::GRAPHICS)
-                    {
-                        found_transfer_queue_index = Some(index as u32);
-                    }
-                }
+        let mut can_present = vec![false; queue_family_properties.len()];
+        if let Some(surface) = surface {
+            for (index, supported) in can_present.iter_mut().enumerate() {
+                *supported = surface.check_support(physical_device, index)?;
             }
         }
+
+        let families: Vec<(u32, vk::QueueFlags)> = queue_family_properties
+            .iter()
+            .enumerate()
+            .filter(|(_, queue_family)| queue_family.queue_count > 0)
+            .map(|(index, queue_family)| (index as u32, queue_family.queue_flags))
+            .collect();
+        let find = |wanted: vk::QueueFlags, unwanted: vk::QueueFlags| {
+            families
+                .iter()
+                .find(|(_, flags)| flags.contains(wanted) && !flags.intersects(unwanted))
+                .map(|&(index, _)| index)
+        };
+
+        // presenting from the graphics family saves an ownership transfer of every image
+        let graphics_queue_index = families
+            .iter()
+            .find(|&&(index, flags)| {
+                flags.contains(vk::QueueFlags::GRAPHICS) && can_present[index as usize]
+            })
+            .map(|&(index, _)| index)
+            .or_else(|| find(vk::QueueFlags::GRAPHICS, vk::QueueFlags::empty()));
+
+        let present_queue_index = match graphics_queue_index {
+            Some(index) if can_present[index as usize] => Some(index),
+            _ => families
+                .iter()
+                .find(|&&(index, _)| can_present[index as usize])
+                .map(|&(index, _)| index),
+        };
+
+        let compute_queue_index = find(vk::QueueFlags::COMPUTE, vk::QueueFlags::GRAPHICS)
+            .or_else(|| {
+                graphics_queue_index.filter(|&index| {
+                    queue_family_properties[index as usize]
+                        .queue_flags
+                        .contains(vk::QueueFlags::COMPUTE)
+                })
+            })
+            .or_else(|| find(vk::QueueFlags::COMPUTE, vk::QueueFlags::empty()));
+
+        // graphics and compute families support transfers even if they don't report it
+        let transfer_queue_index = find(
+            vk::QueueFlags::TRANSFER,
+            vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE,
+        )
+        .or_else(|| find(vk::QueueFlags::TRANSFER, vk::QueueFlags::GRAPHICS))
+        .or(graphics_queue_index)
+        .or(compute_queue_index);
+
         Ok(QueueFamilies {
-            graphics_queue_index: found_graphics_queue_index,
-            transfer_queue_index: found_transfer_queue_index,
+            graphics_queue_index,
+            present_queue_index,
+            compute_queue_index,
+            transfer_queue_index,
         })
     }
+
+    pub fn graphics(&self) -> Result<u32, EngineError> {
+        self.graphics_queue_index
+            .ok_or(EngineError::MissingQueueFamily("graphics"))
+    }
+
+    pub fn present(&self) -> Result<u32, EngineError> {
+        self.present_queue_index
+            .ok_or(EngineError::MissingQueueFamily("present"))
+    }
+
+    pub fn compute(&self) -> Result<u32, EngineError> {
+        self.compute_queue_index
+            .ok_or(EngineError::MissingQueueFamily("compute"))
+    }
+
+    pub fn transfer(&self) -> Result<u32, EngineError> {
+        self.transfer_queue_index
+            .ok_or(EngineError::MissingQueueFamily("transfer"))
+    }
+
+    /// every family that was found, each one once
+    pub fn unique_indices(&self) -> Vec<u32> {
+        let mut indices = Vec::with_capacity(4);
+        for index in [
+            self.graphics_queue_index,
+            self.present_queue_index,
+            self.compute_queue_index,
+            self.transfer_queue_index,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if !indices.contains(&index) {
+                indices.push(index);
+            }
+        }
+        indices
+    }
 }
 
+/// one queue per family, so handles are the same queue whenever the families are
 pub struct Queues {
     pub graphics_queue: vk::Queue,
+    /// null without a surface
+    pub present_queue: vk::Queue,
+    pub compute_queue: vk::Queue,
     pub transfer_queue: vk::Queue,
 }
@@ -0,0 +1,1114 @@
+use std::collections::HashMap;
+
+use ash::vk;
+
+use super::{
+    descriptor::DescriptorLayoutCache, error::EngineError, pipeline::ShaderStage,
+    vertex::VertexLayout,
+};
+
+// the parts of the spir-v spec we need, see
+// https://registry.khronos.org/SPIR-V/specs/unified1/SPIRV.html
+const MAGIC: u32 = 0x0723_0203;
+const HEADER_WORDS: usize = 5;
+
+const OP_NAME: u32 = 5;
+const OP_ENTRY_POINT: u32 = 15;
+const OP_TYPE_VOID: u32 = 19;
+const OP_TYPE_BOOL: u32 = 20;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+const DECORATION_BLOCK: u32 = 2;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BUILT_IN: u32 = 11;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+const STORAGE_CLASS_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_CLASS_INPUT: u32 = 1;
+const STORAGE_CLASS_UNIFORM: u32 = 2;
+const STORAGE_CLASS_OUTPUT: u32 = 3;
+const STORAGE_CLASS_PUSH_CONSTANT: u32 = 9;
+const STORAGE_CLASS_STORAGE_BUFFER: u32 = 12;
+
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DescriptorBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    pub count: u32,
+    pub stages: vk::ShaderStageFlags,
+    pub name: String,
+}
+
+/// a vertex input or a varying, matrices and arrays take one entry per location
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceVariable {
+    pub location: u32,
+    pub format: vk::Format,
+    pub name: String,
+}
+
+/// what a single shader stage declares
+#[derive(Debug, Clone, Default)]
+pub struct ShaderReflection {
+    pub stage: vk::ShaderStageFlags,
+    pub bindings: Vec<DescriptorBinding>,
+    /// size of the push constant block in bytes
+    pub push_constant_size: Option<u32>,
+    pub inputs: Vec<InterfaceVariable>,
+    pub outputs: Vec<InterfaceVariable>,
+}
+
+impl ShaderReflection {
+    pub fn from_stage(stage: &ShaderStage) -> Result<ShaderReflection, EngineError> {
+        let module = Module::parse(&stage.code)?;
+        let execution_model = match stage.stage {
+            vk::ShaderStageFlags::VERTEX => 0,
+            vk::ShaderStageFlags::FRAGMENT => 4,
+            vk::ShaderStageFlags::COMPUTE => 5,
+            other => return Err(reflection_error(format!("unsupported stage {other:?}"))),
+        };
+        let entry_point = module
+            .entry_points
+            .iter()
+            .find(|entry_point| {
+                entry_point.execution_model == execution_model
+                    && entry_point.name == stage.entry_point
+            })
+            .ok_or_else(|| {
+                reflection_error(format!(
+                    "no {:?} entry point called {}",
+                    stage.stage, stage.entry_point
+                ))
+            })?;
+
+        let mut reflection = ShaderReflection {
+            stage: stage.stage,
+            ..Default::default()
+        };
+        for variable in &module.variables {
+            let decorations = module.decorations(variable.id);
+            let Some(SpirvType::Pointer { pointee, .. }) = module.types.get(&variable.type_id)
+            else {
+                continue;
+            };
+            let pointee = *pointee;
+            match variable.storage_class {
+                STORAGE_CLASS_INPUT | STORAGE_CLASS_OUTPUT => {
+                    // older spir-v only lists inputs and outputs in the entry point interface
+                    if !entry_point.interface.contains(&variable.id) || decorations.built_in {
+                        continue;
+                    }
+                    let Some(location) = decorations.location else {
+                        continue;
+                    };
+                    let variables =
+                        module.interface_variables(pointee, location, &module.name(variable.id))?;
+                    if variable.storage_class == STORAGE_CLASS_INPUT {
+                        reflection.inputs.extend(variables);
+                    } else {
+                        reflection.outputs.extend(variables);
+                    }
+                }
+                STORAGE_CLASS_PUSH_CONSTANT => {
+                    reflection.push_constant_size = Some(module.size_of(pointee, None)?);
+                }
+                STORAGE_CLASS_UNIFORM_CONSTANT
+                | STORAGE_CLASS_UNIFORM
+                | STORAGE_CLASS_STORAGE_BUFFER => {
+                    let (Some(set), Some(binding)) = (decorations.set, decorations.binding) else {
+                        continue;
+                    };
+                    let (descriptor_type, count) =
+                        module.descriptor_type(pointee, variable.storage_class)?;
+                    reflection.bindings.push(DescriptorBinding {
+                        set,
+                        binding,
+                        descriptor_type,
+                        count,
+                        stages: stage.stage,
+                        name: module.name(variable.id),
+                    });
+                }
+                _ => {}
+            }
+        }
+        reflection.inputs.sort_by_key(|variable| variable.location);
+        reflection.outputs.sort_by_key(|variable| variable.location);
+        Ok(reflection)
+    }
+}
+
+/// every stage of a pipeline merged together
+#[derive(Debug, Clone, Default)]
+pub struct PipelineReflection {
+    /// sorted by set and binding
+    pub bindings: Vec<DescriptorBinding>,
+    pub push_constant_ranges: Vec<vk::PushConstantRange>,
+    pub vertex_inputs: Vec<InterfaceVariable>,
+}
+
+impl PipelineReflection {
+    /// merges the stages, failing if they disagree about a binding or the vertex outputs
+    /// don't match the fragment inputs
+    pub fn from_stages(stages: &[ShaderStage]) -> Result<PipelineReflection, EngineError> {
+        let reflections = stages
+            .iter()
+            .map(ShaderReflection::from_stage)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut merged = PipelineReflection::default();
+        for reflection in &reflections {
+            for binding in &reflection.bindings {
+                let existing = merged
+                    .bindings
+                    .iter_mut()
+                    .find(|b| b.set == binding.set && b.binding == binding.binding);
+                match existing {
+                    Some(existing) => {
+                        if existing.descriptor_type != binding.descriptor_type
+                            || existing.count != binding.count
+                        {
+                            return Err(reflection_error(format!(
+                                "set {} binding {} is {:?}[{}] in {:?} but {:?}[{}] in {:?}",
+                                binding.set,
+                                binding.binding,
+                                existing.descriptor_type,
+                                existing.count,
+                                existing.stages,
+                                binding.descriptor_type,
+                                binding.count,
+                                binding.stages
+                            )));
+                        }
+                        existing.stages |= binding.stages;
+                    }
+                    None => merged.bindings.push(binding.clone()),
+                }
+            }
+        }
+        merged
+            .bindings
+            .sort_by_key(|binding| (binding.set, binding.binding));
+
+        // a single range visible to every stage that uses push constants, big enough for all
+        let push_constant_stages = reflections
+            .iter()
+            .filter(|reflection| reflection.push_constant_size.is_some())
+            .fold(vk::ShaderStageFlags::empty(), |stages, reflection| {
+                stages | reflection.stage
+            });
+        if let Some(size) = reflections
+            .iter()
+            .filter_map(|reflection| reflection.push_constant_size)
+            .max()
+        {
+            merged.push_constant_ranges.push(vk::PushConstantRange {
+                stage_flags: push_constant_stages,
+                offset: 0,
+                size,
+            });
+        }
+
+        let find_stage = |stage| reflections.iter().find(|r| r.stage == stage);
+        if let Some(vertex) = find_stage(vk::ShaderStageFlags::VERTEX) {
+            merged.vertex_inputs = vertex.inputs.clone();
+            if let Some(fragment) = find_stage(vk::ShaderStageFlags::FRAGMENT) {
+                check_interface(&vertex.outputs, &fragment.inputs)?;
+            }
+        }
+        Ok(merged)
+    }
+
+    /// one past the highest set used
+    pub fn set_count(&self) -> u32 {
+        self.bindings
+            .iter()
+            .map(|binding| binding.set + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn set_bindings(&self, set: u32) -> impl Iterator<Item = &DescriptorBinding> {
+        self.bindings
+            .iter()
+            .filter(move |binding| binding.set == set)
+    }
+
+    pub fn set_layout_bindings(&self, set: u32) -> Vec<vk::DescriptorSetLayoutBinding> {
+        self.set_bindings(set)
+            .map(|binding| {
+                vk::DescriptorSetLayoutBinding::builder()
+                    .binding(binding.binding)
+                    .descriptor_type(binding.descriptor_type)
+                    .descriptor_count(binding.count)
+                    .stage_flags(binding.stages)
+                    .build()
+            })
+            .collect()
+    }
+
+    /// one layout per set up to the highest one used, sets in between are empty. the layouts
+    /// belong to the cache
+    pub fn set_layouts(
+        &self,
+        logical_device: &ash::Device,
+        layout_cache: &mut DescriptorLayoutCache,
+    ) -> Result<Vec<vk::DescriptorSetLayout>, EngineError> {
+        (0..self.set_count())
+            .map(|set| layout_cache.get(logical_device, &self.set_layout_bindings(set)))
+            .collect()
+    }
+
+    /// a single per-vertex binding with the inputs tightly packed in location order
+    pub fn vertex_layout(&self) -> Result<VertexLayout, EngineError> {
+        let mut layout = VertexLayout::default();
+        let mut offset = 0;
+        for input in &self.vertex_inputs {
+            layout.attributes.push(vk::VertexInputAttributeDescription {
+                location: input.location,
+                binding: 0,
+                format: input.format,
+                offset,
+            });
+            offset += interface_size(input)?;
+        }
+        if !layout.attributes.is_empty() {
+            layout.bindings.push(vk::VertexInputBindingDescription {
+                binding: 0,
+                stride: offset,
+                input_rate: vk::VertexInputRate::VERTEX,
+            });
+        }
+        Ok(layout)
+    }
+
+    /// every vertex input needs an attribute at its location with the same numeric type, the
+    /// component count may differ
+    pub fn check_vertex_layout(&self, layout: &VertexLayout) -> Result<(), EngineError> {
+        for input in &self.vertex_inputs {
+            let attribute = layout
+                .attributes
+                .iter()
+                .find(|attribute| attribute.location == input.location)
+                .ok_or_else(|| {
+                    reflection_error(format!(
+                        "vertex input {} at location {} has no attribute",
+                        input.name, input.location
+                    ))
+                })?;
+            if numeric_type(attribute.format) != numeric_type(input.format) {
+                return Err(reflection_error(format!(
+                    "vertex input {} at location {} is {:?} but the attribute is {:?}",
+                    input.name, input.location, input.format, attribute.format
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check_interface(
+    outputs: &[InterfaceVariable],
+    inputs: &[InterfaceVariable],
+) -> Result<(), EngineError> {
+    for input in inputs {
+        match outputs
+            .iter()
+            .find(|output| output.location == input.location)
+        {
+            None => {
+                return Err(reflection_error(format!(
+                    "fragment input {} at location {} isn't written by the vertex shader",
+                    input.name, input.location
+                )))
+            }
+            // the fragment shader may read fewer components than were written
+            Some(output)
+                if numeric_type(output.format) != numeric_type(input.format)
+                    || interface_size(output)? < interface_size(input)? =>
+            {
+                return Err(reflection_error(format!(
+                    "fragment input {} at location {} is {:?} but the vertex output {} is {:?}",
+                    input.name, input.location, input.format, output.name, output.format
+                )))
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(())
+}
+
+fn reflection_error(message: String) -> EngineError {
+    EngineError::Reflection(message)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NumericType {
+    Float,
+    Sint,
+    Uint,
+    Other,
+}
+
+/// what a shader reads from a vertex attribute of `format`: normalized, scaled and srgb formats
+/// are converted to floats
+fn numeric_type(format: vk::Format) -> NumericType {
+    use vk::Format as F;
+    match format {
+        F::R8_UNORM
+        | F::R8_SNORM
+        | F::R8_USCALED
+        | F::R8_SSCALED
+        | F::R8_SRGB
+        | F::R8G8_UNORM
+        | F::R8G8_SNORM
+        | F::R8G8_USCALED
+        | F::R8G8_SSCALED
+        | F::R8G8_SRGB
+        | F::R8G8B8_UNORM
+        | F::R8G8B8_SNORM
+        | F::R8G8B8_USCALED
+        | F::R8G8B8_SSCALED
+        | F::R8G8B8_SRGB
+        | F::B8G8R8_UNORM
+        | F::B8G8R8_SNORM
+        | F::B8G8R8_USCALED
+        | F::B8G8R8_SSCALED
+        | F::B8G8R8_SRGB
+        | F::R8G8B8A8_UNORM
+        | F::R8G8B8A8_SNORM
+        | F::R8G8B8A8_USCALED
+        | F::R8G8B8A8_SSCALED
+        | F::R8G8B8A8_SRGB
+        | F::B8G8R8A8_UNORM
+        | F::B8G8R8A8_SNORM
+        | F::B8G8R8A8_USCALED
+        | F::B8G8R8A8_SSCALED
+        | F::B8G8R8A8_SRGB
+        | F::A8B8G8R8_UNORM_PACK32
+        | F::A8B8G8R8_SNORM_PACK32
+        | F::A8B8G8R8_USCALED_PACK32
+        | F::A8B8G8R8_SSCALED_PACK32
+        | F::A8B8G8R8_SRGB_PACK32
+        | F::A2R10G10B10_UNORM_PACK32
+        | F::A2R10G10B10_SNORM_PACK32
+        | F::A2R10G10B10_USCALED_PACK32
+        | F::A2R10G10B10_SSCALED_PACK32
+        | F::A2B10G10R10_UNORM_PACK32
+        | F::A2B10G10R10_SNORM_PACK32
+        | F::A2B10G10R10_USCALED_PACK32
+        | F::A2B10G10R10_SSCALED_PACK32
+        | F::R16_UNORM
+        | F::R16_SNORM
+        | F::R16_USCALED
+        | F::R16_SSCALED
+        | F::R16_SFLOAT
+        | F::R16G16_UNORM
+        | F::R16G16_SNORM
+        | F::R16G16_USCALED
+        | F::R16G16_SSCALED
+        | F::R16G16_SFLOAT
+        | F::R16G16B16_UNORM
+        | F::R16G16B16_SNORM
+        | F::R16G16B16_USCALED
+        | F::R16G16B16_SSCALED
+        | F::R16G16B16_SFLOAT
+        | F::R16G16B16A16_UNORM
+        | F::R16G16B16A16_SNORM
+        | F::R16G16B16A16_USCALED
+        | F::R16G16B16A16_SSCALED
+        | F::R16G16B16A16_SFLOAT
+        | F::R32_SFLOAT
+        | F::R32G32_SFLOAT
+        | F::R32G32B32_SFLOAT
+        | F::R32G32B32A32_SFLOAT
+        | F::R64_SFLOAT
+        | F::R64G64_SFLOAT
+        | F::R64G64B64_SFLOAT
+        | F::R64G64B64A64_SFLOAT
+        | F::B10G11R11_UFLOAT_PACK32
+        | F::E5B9G9R9_UFLOAT_PACK32 => NumericType::Float,
+        F::R8_SINT
+        | F::R8G8_SINT
+        | F::R8G8B8_SINT
+        | F::B8G8R8_SINT
+        | F::R8G8B8A8_SINT
+        | F::B8G8R8A8_SINT
+        | F::A8B8G8R8_SINT_PACK32
+        | F::A2R10G10B10_SINT_PACK32
+        | F::A2B10G10R10_SINT_PACK32
+        | F::R16_SINT
+        | F::R16G16_SINT
+        | F::R16G16B16_SINT
+        | F::R16G16B16A16_SINT
+        | F::R32_SINT
+        | F::R32G32_SINT
+        | F::R32G32B32_SINT
+        | F::R32G32B32A32_SINT
+        | F::R64_SINT
+        | F::R64G64_SINT
+        | F::R64G64B64_SINT
+        | F::R64G64B64A64_SINT => NumericType::Sint,
+        F::R8_UINT
+        | F::R8G8_UINT
+        | F::R8G8B8_UINT
+        | F::B8G8R8_UINT
+        | F::R8G8B8A8_UINT
+        | F::B8G8R8A8_UINT
+        | F::A8B8G8R8_UINT_PACK32
+        | F::A2R10G10B10_UINT_PACK32
+        | F::A2B10G10R10_UINT_PACK32
+        | F::R16_UINT
+        | F::R16G16_UINT
+        | F::R16G16B16_UINT
+        | F::R16G16B16A16_UINT
+        | F::R32_UINT
+        | F::R32G32_UINT
+        | F::R32G32B32_UINT
+        | F::R32G32B32A32_UINT
+        | F::R64_UINT
+        | F::R64G64_UINT
+        | F::R64G64B64_UINT
+        | F::R64G64B64A64_UINT => NumericType::Uint,
+        _ => NumericType::Other,
+    }
+}
+
+fn interface_size(variable: &InterfaceVariable) -> Result<u32, EngineError> {
+    format_size(variable.format).ok_or_else(|| {
+        reflection_error(format!(
+            "{} at location {} has the unsupported format {:?}",
+            variable.name, variable.location, variable.format
+        ))
+    })
+}
+
+/// size in bytes of the formats `interface_format` produces, `None` for any other format
+fn format_size(format: vk::Format) -> Option<u32> {
+    let (components, width) = match format {
+        vk::Format::R32_SFLOAT | vk::Format::R32_SINT | vk::Format::R32_UINT => (1, 4),
+        vk::Format::R32G32_SFLOAT | vk::Format::R32G32_SINT | vk::Format::R32G32_UINT => (2, 4),
+        vk::Format::R32G32B32_SFLOAT | vk::Format::R32G32B32_SINT | vk::Format::R32G32B32_UINT => {
+            (3, 4)
+        }
+        vk::Format::R32G32B32A32_SFLOAT
+        | vk::Format::R32G32B32A32_SINT
+        | vk::Format::R32G32B32A32_UINT => (4, 4),
+        vk::Format::R64_SFLOAT => (1, 8),
+        vk::Format::R64G64_SFLOAT => (2, 8),
+        vk::Format::R64G64B64_SFLOAT => (3, 8),
+        vk::Format::R64G64B64A64_SFLOAT => (4, 8),
+        _ => return None,
+    };
+    Some(components * width)
+}
+
+#[derive(Debug, Clone)]
+enum SpirvType {
+    Void,
+    Bool,
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage,
+    Array { element: u32, length_id: u32 },
+    RuntimeArray,
+    Struct { members: Vec<u32> },
+    Pointer { pointee: u32 },
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Decorations {
+    set: Option<u32>,
+    binding: Option<u32>,
+    location: Option<u32>,
+    built_in: bool,
+    block: bool,
+    buffer_block: bool,
+    array_stride: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct MemberDecorations {
+    offset: Option<u32>,
+    matrix_stride: Option<u32>,
+}
+
+struct Variable {
+    id: u32,
+    type_id: u32,
+    storage_class: u32,
+}
+
+struct EntryPoint {
+    execution_model: u32,
+    name: String,
+    interface: Vec<u32>,
+}
+
+/// just enough of a spir-v module to reflect its interface
+#[derive(Default)]
+struct Module {
+    names: HashMap<u32, String>,
+    decorations: HashMap<u32, Decorations>,
+    member_decorations: HashMap<(u32, u32), MemberDecorations>,
+    types: HashMap<u32, SpirvType>,
+    constants: HashMap<u32, u32>,
+    variables: Vec<Variable>,
+    entry_points: Vec<EntryPoint>,
+}
+
+/// a nul terminated utf-8 string packed into words, returns it and the words it took
+fn parse_string(words: &[u32]) -> (String, usize) {
+    let mut bytes = Vec::new();
+    for (index, word) in words.iter().enumerate() {
+        for byte in word.to_le_bytes() {
+            if byte == 0 {
+                return (String::from_utf8_lossy(&bytes).into_owned(), index + 1);
+            }
+            bytes.push(byte);
+        }
+    }
+    (String::from_utf8_lossy(&bytes).into_owned(), words.len())
+}
+
+impl Module {
+    fn parse(code: &[u32]) -> Result<Module, EngineError> {
+        if code.len() < HEADER_WORDS || code[0] != MAGIC {
+            return Err(reflection_error("not a spir-v module".to_string()));
+        }
+
+        let mut module = Module::default();
+        let mut index = HEADER_WORDS;
+        while index < code.len() {
+            let word_count = (code[index] >> 16) as usize;
+            let opcode = code[index] & 0xffff;
+            if word_count == 0 || index + word_count > code.len() {
+                return Err(reflection_error("truncated spir-v instruction".to_string()));
+            }
+            let operands = &code[index + 1..index + word_count];
+            index += word_count;
+
+            let operand = |i: usize| {
+                operands
+                    .get(i)
+                    .copied()
+                    .ok_or_else(|| reflection_error(format!("opcode {opcode} is too short")))
+            };
+            match opcode {
+                OP_NAME => {
+                    module
+                        .names
+                        .insert(operand(0)?, parse_string(&operands[1..]).0);
+                }
+                OP_ENTRY_POINT => {
+                    let (name, name_words) = parse_string(&operands[2..]);
+                    module.entry_points.push(EntryPoint {
+                        execution_model: operand(0)?,
+                        name,
+                        interface: operands[2 + name_words..].to_vec(),
+                    });
+                }
+                OP_TYPE_VOID => {
+                    module.types.insert(operand(0)?, SpirvType::Void);
+                }
+                OP_TYPE_BOOL => {
+                    module.types.insert(operand(0)?, SpirvType::Bool);
+                }
+                OP_TYPE_INT => {
+                    module.types.insert(
+                        operand(0)?,
+                        SpirvType::Int {
+                            width: operand(1)?,
+                            signed: operand(2)? != 0,
+                        },
+                    );
+                }
+                OP_TYPE_FLOAT => {
+                    module
+                        .types
+                        .insert(operand(0)?, SpirvType::Float { width: operand(1)? });
+                }
+                OP_TYPE_VECTOR => {
+                    module.types.insert(
+                        operand(0)?,
+                        SpirvType::Vector {
+                            component: operand(1)?,
+                            count: operand(2)?,
+                        },
+                    );
+                }
+                OP_TYPE_MATRIX => {
+                    module.types.insert(
+                        operand(0)?,
+                        SpirvType::Matrix {
+                            column: operand(1)?,
+                            count: operand(2)?,
+                        },
+                    );
+                }
+                OP_TYPE_IMAGE => {
+                    module.types.insert(
+                        operand(0)?,
+                        SpirvType::Image {
+                            dim: operand(2)?,
+                            sampled: operand(6)?,
+                        },
+                    );
+                }
+                OP_TYPE_SAMPLER => {
+                    module.types.insert(operand(0)?, SpirvType::Sampler);
+                }
+                OP_TYPE_SAMPLED_IMAGE => {
+                    module.types.insert(operand(0)?, SpirvType::SampledImage);
+                }
+                OP_TYPE_ARRAY => {
+                    module.types.insert(
+                        operand(0)?,
+                        SpirvType::Array {
+                            element: operand(1)?,
+                            length_id: operand(2)?,
+                        },
+                    );
+                }
+                OP_TYPE_RUNTIME_ARRAY => {
+                    module.types.insert(operand(0)?, SpirvType::RuntimeArray);
+                }
+                OP_TYPE_STRUCT => {
+                    module.types.insert(
+                        operand(0)?,
+                        SpirvType::Struct {
+                            members: operands[1..].to_vec(),
+                        },
+                    );
+                }
+                OP_TYPE_POINTER => {
+                    module.types.insert(
+                        operand(0)?,
+                        SpirvType::Pointer {
+                            pointee: operand(2)?,
+                        },
+                    );
+                }
+                OP_CONSTANT => {
+                    // only the low word matters for array lengths
+                    module.constants.insert(operand(1)?, operand(2)?);
+                }
+                OP_VARIABLE => {
+                    module.variables.push(Variable {
+                        type_id: operand(0)?,
+                        id: operand(1)?,
+                        storage_class: operand(2)?,
+                    });
+                }
+                OP_DECORATE => {
+                    let decorations = module.decorations.entry(operand(0)?).or_default();
+                    match operand(1)? {
+                        DECORATION_BLOCK => decorations.block = true,
+                        DECORATION_BUFFER_BLOCK => decorations.buffer_block = true,
+                        DECORATION_ARRAY_STRIDE => decorations.array_stride = Some(operand(2)?),
+                        DECORATION_BUILT_IN => decorations.built_in = true,
+                        DECORATION_LOCATION => decorations.location = Some(operand(2)?),
+                        DECORATION_BINDING => decorations.binding = Some(operand(2)?),
+                        DECORATION_DESCRIPTOR_SET => decorations.set = Some(operand(2)?),
+                        _ => {}
+                    }
+                }
+                OP_MEMBER_DECORATE => {
+                    let decorations = module
+                        .member_decorations
+                        .entry((operand(0)?, operand(1)?))
+                        .or_default();
+                    match operand(2)? {
+                        DECORATION_OFFSET => decorations.offset = Some(operand(3)?),
+                        DECORATION_MATRIX_STRIDE => decorations.matrix_stride = Some(operand(3)?),
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(module)
+    }
+
+    fn decorations(&self, id: u32) -> Decorations {
+        self.decorations.get(&id).copied().unwrap_or_default()
+    }
+
+    fn name(&self, id: u32) -> String {
+        self.names
+            .get(&id)
+            .filter(|name| !name.is_empty())
+            .cloned()
+            .unwrap_or_else(|| format!("%{id}"))
+    }
+
+    fn get_type(&self, id: u32) -> Result<&SpirvType, EngineError> {
+        self.types
+            .get(&id)
+            .ok_or_else(|| reflection_error(format!("unknown type %{id}")))
+    }
+
+    fn array_length(&self, length_id: u32) -> Result<u32, EngineError> {
+        self.constants
+            .get(&length_id)
+            .copied()
+            .ok_or_else(|| reflection_error(format!("array length %{length_id} isn't a constant")))
+    }
+
+    /// size in bytes following the explicit layout decorations (push constant and buffer
+    /// blocks have them)
+    fn size_of(&self, type_id: u32, matrix_stride: Option<u32>) -> Result<u32, EngineError> {
+        Ok(match self.get_type(type_id)? {
+            SpirvType::Bool => 4,
+            SpirvType::Int { width, .. } | SpirvType::Float { width } => width / 8,
+            SpirvType::Vector { component, count } => count * self.size_of(*component, None)?,
+            SpirvType::Matrix { column, count } => match matrix_stride {
+                Some(stride) => count * stride,
+                None => count * self.size_of(*column, None)?,
+            },
+            SpirvType::Array { element, length_id } => {
+                let length = self.array_length(*length_id)?;
+                match self.decorations(type_id).array_stride {
+                    Some(stride) => length * stride,
+                    None => length * self.size_of(*element, matrix_stride)?,
+                }
+            }
+            SpirvType::Struct { members } => {
+                let mut size = 0;
+                for (index, &member) in members.iter().enumerate() {
+                    let decorations = self
+                        .member_decorations
+                        .get(&(type_id, index as u32))
+                        .copied()
+                        .unwrap_or_default();
+                    let end = decorations.offset.unwrap_or(size)
+                        + self.size_of(member, decorations.matrix_stride)?;
+                    size = size.max(end);
+                }
+                size
+            }
+            other => {
+                return Err(reflection_error(format!(
+                    "can't compute the size of {other:?}"
+                )))
+            }
+        })
+    }
+
+    /// scalars and vectors take one location, matrices one per column and arrays one per
+    /// element
+    fn interface_variables(
+        &self,
+        type_id: u32,
+        location: u32,
+        name: &str,
+    ) -> Result<Vec<InterfaceVariable>, EngineError> {
+        Ok(match self.get_type(type_id)? {
+            SpirvType::Matrix { column, count } => (0..*count)
+                .map(|i| {
+                    self.interface_format(*column)
+                        .map(|format| InterfaceVariable {
+                            location: location + i,
+                            format,
+                            name: format!("{name}[{i}]"),
+                        })
+                })
+                .collect::<Result<_, _>>()?,
+            SpirvType::Array { element, length_id } => {
+                let length = self.array_length(*length_id)?;
+                let mut variables = Vec::new();
+                for i in 0..length {
+                    let element_variables = self.interface_variables(
+                        *element,
+                        location + variables.len() as u32,
+                        &format!("{name}[{i}]"),
+                    )?;
+                    variables.extend(element_variables);
+                }
+                variables
+            }
+            _ => vec![InterfaceVariable {
+                location,
+                format: self.interface_format(type_id)?,
+                name: name.to_string(),
+            }],
+        })
+    }
+
+    fn interface_format(&self, type_id: u32) -> Result<vk::Format, EngineError> {
+        let (scalar, count) = match self.get_type(type_id)? {
+            SpirvType::Vector { component, count } => (self.get_type(*component)?, *count),
+            scalar => (scalar, 1),
+        };
+        use vk::Format as F;
+        let formats = match scalar {
+            SpirvType::Float { width: 32 } => [
+                F::R32_SFLOAT,
+                F::R32G32_SFLOAT,
+                F::R32G32B32_SFLOAT,
+                F::R32G32B32A32_SFLOAT,
+            ],
+            SpirvType::Float { width: 64 } => [
+                F::R64_SFLOAT,
+                F::R64G64_SFLOAT,
+                F::R64G64B64_SFLOAT,
+                F::R64G64B64A64_SFLOAT,
+            ],
+            SpirvType::Int {
+                width: 32,
+                signed: true,
+            } => [
+                F::R32_SINT,
+                F::R32G32_SINT,
+                F::R32G32B32_SINT,
+                F::R32G32B32A32_SINT,
+            ],
+            SpirvType::Int {
+                width: 32,
+                signed: false,
+            } => [
+                F::R32_UINT,
+                F::R32G32_UINT,
+                F::R32G32B32_UINT,
+                F::R32G32B32A32_UINT,
+            ],
+            other => {
+                return Err(reflection_error(format!(
+                    "unsupported interface type {other:?}"
+                )))
+            }
+        };
+        formats
+            .get(count as usize - 1)
+            .copied()
+            .ok_or_else(|| reflection_error(format!("vector with {count} components")))
+    }
+
+    /// the descriptor type and count of a resource variable's pointee
+    fn descriptor_type(
+        &self,
+        type_id: u32,
+        storage_class: u32,
+    ) -> Result<(vk::DescriptorType, u32), EngineError> {
+        let (type_id, count) = match self.get_type(type_id)? {
+            SpirvType::Array { element, length_id } => (*element, self.array_length(*length_id)?),
+            SpirvType::RuntimeArray => {
+                return Err(reflection_error(
+                    "runtime sized descriptor arrays aren't supported".to_string(),
+                ))
+            }
+            _ => (type_id, 1),
+        };
+
+        let descriptor_type = match (storage_class, self.get_type(type_id)?) {
+            (STORAGE_CLASS_STORAGE_BUFFER, _) => vk::DescriptorType::STORAGE_BUFFER,
+            (STORAGE_CLASS_UNIFORM, _) if self.decorations(type_id).buffer_block => {
+                vk::DescriptorType::STORAGE_BUFFER
+            }
+            (STORAGE_CLASS_UNIFORM, _) => vk::DescriptorType::UNIFORM_BUFFER,
+            (_, SpirvType::Sampler) => vk::DescriptorType::SAMPLER,
+            (_, SpirvType::SampledImage) => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            (_, SpirvType::Image { dim, sampled }) => match (*dim, *sampled) {
+                (DIM_SUBPASS_DATA, _) => vk::DescriptorType::INPUT_ATTACHMENT,
+                (DIM_BUFFER, 2) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+                (DIM_BUFFER, _) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+                (_, 2) => vk::DescriptorType::STORAGE_IMAGE,
+                _ => vk::DescriptorType::SAMPLED_IMAGE,
+            },
+            (_, other) => {
+                return Err(reflection_error(format!(
+                    "unsupported resource type {other:?}"
+                )))
+            }
+        };
+        Ok((descriptor_type, count))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::engine::{shader, vertex::MeshVertex};
+
+    fn compile(stage: vk::ShaderStageFlags, path: &Path) -> ShaderStage {
+        ShaderStage::new(stage, shader::compile_file(path).unwrap())
+    }
+
+    fn builtin(name: &str) -> ShaderStage {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("shaders")
+            .join(name);
+        let stage = if name.ends_with(".vert") {
+            vk::ShaderStageFlags::VERTEX
+        } else {
+            vk::ShaderStageFlags::FRAGMENT
+        };
+        compile(stage, &path)
+    }
+
+    /// writes `source` to a temporary file, naga picks the stage from the extension
+    fn compile_source(name: &str, source: &str) -> ShaderStage {
+        let path = std::env::temp_dir().join(format!("reflect-test-{}-{name}", std::process::id()));
+        std::fs::write(&path, source).unwrap();
+        let stage = compile(vk::ShaderStageFlags::FRAGMENT, &path);
+        let _ = std::fs::remove_file(&path);
+        stage
+    }
+
+    fn locations_and_formats(variables: &[InterfaceVariable]) -> Vec<(u32, vk::Format)> {
+        variables
+            .iter()
+            .map(|variable| (variable.location, variable.format))
+            .collect()
+    }
+
+    #[test]
+    fn vertex_shader_interface() {
+        let reflection = ShaderReflection::from_stage(&builtin("shader.vert")).unwrap();
+
+        assert_eq!(reflection.stage, vk::ShaderStageFlags::VERTEX);
+        assert_eq!(
+            locations_and_formats(&reflection.inputs),
+            [
+                (0, vk::Format::R32G32B32_SFLOAT),
+                (1, vk::Format::R32G32B32_SFLOAT),
+                (2, vk::Format::R32G32_SFLOAT),
+                (3, vk::Format::R32G32B32A32_SFLOAT),
+            ]
+        );
+        // gl_Position is a built-in and left out
+        assert_eq!(
+            locations_and_formats(&reflection.outputs),
+            [(0, vk::Format::R32G32B32A32_SFLOAT)]
+        );
+        assert!(reflection.bindings.is_empty());
+        assert_eq!(reflection.push_constant_size, None);
+    }
+
+    #[test]
+    fn fragment_shader_interface() {
+        let reflection = ShaderReflection::from_stage(&builtin("shader.frag")).unwrap();
+
+        assert_eq!(
+            locations_and_formats(&reflection.inputs),
+            [(0, vk::Format::R32G32B32A32_SFLOAT)]
+        );
+        assert_eq!(
+            locations_and_formats(&reflection.outputs),
+            [(0, vk::Format::R32G32B32A32_SFLOAT)]
+        );
+    }
+
+    #[test]
+    fn pipeline_vertex_layout_matches_mesh_vertex() {
+        let reflection =
+            PipelineReflection::from_stages(&[builtin("shader.vert"), builtin("shader.frag")])
+                .unwrap();
+        let mesh_layout = VertexLayout::default().per_vertex::<MeshVertex>();
+
+        let layout = reflection.vertex_layout().unwrap();
+        assert_eq!(layout.bindings.len(), 1);
+        assert_eq!(
+            layout.bindings[0].stride,
+            std::mem::size_of::<MeshVertex>() as u32
+        );
+        let offsets: Vec<u32> = layout.attributes.iter().map(|a| a.offset).collect();
+        let mesh_offsets: Vec<u32> = mesh_layout.attributes.iter().map(|a| a.offset).collect();
+        assert_eq!(offsets, mesh_offsets);
+        reflection.check_vertex_layout(&mesh_layout).unwrap();
+    }
+
+    #[test]
+    fn descriptors_and_push_constants() {
+        let stage = compile_source(
+            "bindings.frag",
+            "#version 450
+            layout (set = 0, binding = 0) uniform Camera { mat4 view_proj; } camera;
+            layout (set = 1, binding = 2) uniform texture2D albedo;
+            layout (set = 1, binding = 3) uniform sampler albedo_sampler;
+            layout (set = 2, binding = 0) buffer Lights { vec4 lights[]; };
+            layout (push_constant) uniform Push { mat4 model; vec4 tint; } push;
+            layout (location = 0) out vec4 color;
+            void main() {
+                color = texture(sampler2D(albedo, albedo_sampler), vec2(0.0))
+                    * camera.view_proj[0] * push.model[0] * push.tint * lights[0];
+            }",
+        );
+        let reflection = ShaderReflection::from_stage(&stage).unwrap();
+
+        let mut bindings: Vec<(u32, u32, vk::DescriptorType)> = reflection
+            .bindings
+            .iter()
+            .map(|binding| (binding.set, binding.binding, binding.descriptor_type))
+            .collect();
+        bindings.sort();
+        assert_eq!(
+            bindings,
+            [
+                (0, 0, vk::DescriptorType::UNIFORM_BUFFER),
+                (1, 2, vk::DescriptorType::SAMPLED_IMAGE),
+                (1, 3, vk::DescriptorType::SAMPLER),
+                (2, 0, vk::DescriptorType::STORAGE_BUFFER),
+            ]
+        );
+        assert_eq!(reflection.push_constant_size, Some(80));
+    }
+
+    #[test]
+    fn rejects_invalid_code() {
+        let garbage = ShaderStage::new(vk::ShaderStageFlags::VERTEX, vec![0u32; 8]);
+        assert!(ShaderReflection::from_stage(&garbage).is_err());
+
+        // an instruction claiming five words with none following it
+        let mut truncated = builtin("shader.vert");
+        let mut code = truncated.code.to_vec();
+        code.push(5 << 16 | OP_NAME);
+        truncated.code = code.into();
+        assert!(ShaderReflection::from_stage(&truncated).is_err());
+
+        let mut renamed = builtin("shader.vert");
+        renamed.entry_point = "other".to_string();
+        assert!(ShaderReflection::from_stage(&renamed).is_err());
+    }
+
+    #[test]
+    fn numeric_types_of_vertex_formats() {
+        assert_eq!(numeric_type(vk::Format::R8G8B8A8_SRGB), NumericType::Float);
+        assert_eq!(numeric_type(vk::Format::R16G16_USCALED), NumericType::Float);
+        assert_eq!(numeric_type(vk::Format::R8_SSCALED), NumericType::Float);
+        assert_eq!(numeric_type(vk::Format::R32_SINT), NumericType::Sint);
+        assert_eq!(numeric_type(vk::Format::R16G16_UINT), NumericType::Uint);
+        assert_eq!(numeric_type(vk::Format::D32_SFLOAT), NumericType::Other);
+        assert_eq!(format_size(vk::Format::R32G32B32_SFLOAT), Some(12));
+        assert_eq!(format_size(vk::Format::R8G8B8A8_UNORM), None);
+    }
+}
@@ -0,0 +1,1824 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+};
+
+use ash::vk;
+
+use super::{
+    allocator::{Allocation, Allocator, MemoryLocation},
+    depth,
+    dynamic_rendering::DynamicRendering,
+    error::{EngineError, VkResultExt},
+};
+
+/// an image declared in a `RenderGraph`, only valid for the graph that handed it out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ImageId(usize);
+
+/// a buffer declared in a `RenderGraph`, only valid for the graph that handed it out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BufferId(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ImageDesc {
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+    pub samples: vk::SampleCountFlags,
+}
+
+impl ImageDesc {
+    pub fn new(format: vk::Format, extent: vk::Extent2D) -> Self {
+        ImageDesc {
+            format,
+            extent,
+            samples: vk::SampleCountFlags::TYPE_1,
+        }
+    }
+
+    pub fn samples(mut self, samples: vk::SampleCountFlags) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    fn aspect_mask(&self) -> vk::ImageAspectFlags {
+        if depth::is_depth_format(self.format) {
+            depth::aspect_mask(self.format)
+        } else {
+            vk::ImageAspectFlags::COLOR
+        }
+    }
+}
+
+/// the layout of an image and the last stages that accessed it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageState {
+    pub layout: vk::ImageLayout,
+    pub stages: vk::PipelineStageFlags,
+    pub access: vk::AccessFlags,
+}
+
+impl ImageState {
+    /// nothing to wait for and the contents don't matter
+    pub const UNDEFINED: ImageState = ImageState {
+        layout: vk::ImageLayout::UNDEFINED,
+        stages: vk::PipelineStageFlags::empty(),
+        access: vk::AccessFlags::empty(),
+    };
+    /// a swapchain image whose acquire semaphore is waited on at `COLOR_ATTACHMENT_OUTPUT`
+    pub const ACQUIRED: ImageState = ImageState {
+        layout: vk::ImageLayout::UNDEFINED,
+        stages: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+        access: vk::AccessFlags::empty(),
+    };
+    /// ready to be handed to `queue_present`
+    pub const PRESENT: ImageState = ImageState {
+        layout: vk::ImageLayout::PRESENT_SRC_KHR,
+        stages: vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+        access: vk::AccessFlags::empty(),
+    };
+
+    pub fn new(
+        layout: vk::ImageLayout,
+        stages: vk::PipelineStageFlags,
+        access: vk::AccessFlags,
+    ) -> Self {
+        ImageState {
+            layout,
+            stages,
+            access,
+        }
+    }
+}
+
+/// the last stages that accessed a buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferState {
+    pub stages: vk::PipelineStageFlags,
+    pub access: vk::AccessFlags,
+}
+
+impl BufferState {
+    pub const NONE: BufferState = BufferState {
+        stages: vk::PipelineStageFlags::empty(),
+        access: vk::AccessFlags::empty(),
+    };
+    /// read on the cpu once the submission's fence signalled
+    pub const HOST_READ: BufferState = BufferState {
+        stages: vk::PipelineStageFlags::HOST,
+        access: vk::AccessFlags::HOST_READ,
+    };
+
+    pub fn new(stages: vk::PipelineStageFlags, access: vk::AccessFlags) -> Self {
+        BufferState { stages, access }
+    }
+}
+
+/// an image the graph doesn't own, like a swapchain image. it is an output of the graph, so the
+/// passes writing it are never culled
+#[derive(Debug, Clone, Copy)]
+pub struct ImportedImage {
+    pub image: vk::Image,
+    pub view: vk::ImageView,
+    pub desc: ImageDesc,
+    /// what the image is in when the graph starts
+    pub initial_state: ImageState,
+    /// transitioned to after the last pass, `None` leaves it in whatever the last pass needed
+    pub final_state: Option<ImageState>,
+}
+
+impl ImportedImage {
+    pub fn new(image: vk::Image, view: vk::ImageView, desc: ImageDesc) -> Self {
+        ImportedImage {
+            image,
+            view,
+            desc,
+            initial_state: ImageState::UNDEFINED,
+            final_state: None,
+        }
+    }
+
+    pub fn initial_state(mut self, state: ImageState) -> Self {
+        self.initial_state = state;
+        self
+    }
+
+    pub fn final_state(mut self, state: ImageState) -> Self {
+        self.final_state = Some(state);
+        self
+    }
+}
+
+/// a buffer the graph doesn't own, like a readback buffer. it is an output of the graph, so the
+/// passes writing it are never culled
+#[derive(Debug, Clone, Copy)]
+pub struct ImportedBuffer {
+    pub buffer: vk::Buffer,
+    pub initial_state: BufferState,
+    pub final_state: Option<BufferState>,
+}
+
+impl ImportedBuffer {
+    pub fn new(buffer: vk::Buffer) -> Self {
+        ImportedBuffer {
+            buffer,
+            initial_state: BufferState::NONE,
+            final_state: None,
+        }
+    }
+
+    pub fn initial_state(mut self, state: BufferState) -> Self {
+        self.initial_state = state;
+        self
+    }
+
+    pub fn final_state(mut self, state: BufferState) -> Self {
+        self.final_state = Some(state);
+        self
+    }
+}
+
+/// how a pass uses an image besides as an attachment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageAccess {
+    /// through a sampler in the given shader stages
+    Sampled(vk::PipelineStageFlags),
+    StorageRead(vk::PipelineStageFlags),
+    /// also covers reading it in the same pass
+    StorageWrite(vk::PipelineStageFlags),
+    TransferSrc,
+    TransferDst,
+}
+
+/// how a pass uses a buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferAccess {
+    Uniform(vk::PipelineStageFlags),
+    StorageRead(vk::PipelineStageFlags),
+    /// also covers reading it in the same pass
+    StorageWrite(vk::PipelineStageFlags),
+    Vertex,
+    Index,
+    Indirect,
+    TransferSrc,
+    TransferDst,
+}
+
+/// what happens to an attachment's contents when its pass begins
+#[derive(Clone, Copy)]
+pub enum AttachmentLoad {
+    Clear(vk::ClearValue),
+    /// keeps what earlier passes wrote, which makes them a dependency
+    Load,
+    DontCare,
+}
+
+impl AttachmentLoad {
+    pub fn clear_color(color: [f32; 4]) -> Self {
+        AttachmentLoad::Clear(vk::ClearValue {
+            color: vk::ClearColorValue { float32: color },
+        })
+    }
+
+    pub fn clear_depth(depth: f32, stencil: u32) -> Self {
+        AttachmentLoad::Clear(vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue { depth, stencil },
+        })
+    }
+
+    fn op(&self) -> vk::AttachmentLoadOp {
+        match self {
+            AttachmentLoad::Clear(_) => vk::AttachmentLoadOp::CLEAR,
+            AttachmentLoad::Load => vk::AttachmentLoadOp::LOAD,
+            AttachmentLoad::DontCare => vk::AttachmentLoadOp::DONT_CARE,
+        }
+    }
+}
+
+/// what a pass's callback gets to record its commands
+pub struct PassContext<'r> {
+    pub logical_device: &'r ash::Device,
+    pub command_buffer: vk::CommandBuffer,
+    /// null for passes without attachments and with dynamic rendering
+    pub render_pass: vk::RenderPass,
+    /// of the attachments, zero for passes without any
+    pub extent: vk::Extent2D,
+    images: &'r [PhysicalImage],
+    buffers: &'r [vk::Buffer],
+}
+
+impl PassContext<'_> {
+    pub fn image(&self, id: ImageId) -> vk::Image {
+        self.images[id.0].image
+    }
+
+    pub fn image_view(&self, id: ImageId) -> vk::ImageView {
+        self.images[id.0].view
+    }
+
+    pub fn buffer(&self, id: BufferId) -> vk::Buffer {
+        self.buffers[id.0]
+    }
+}
+
+type RecordFn<'a> = Box<dyn FnOnce(&PassContext) -> Result<(), EngineError> + 'a>;
+
+struct Attachment {
+    image: ImageId,
+    load: AttachmentLoad,
+}
+
+struct PassNode<'a> {
+    name: String,
+    colors: Vec<Attachment>,
+    depth: Option<Attachment>,
+    /// one per color attachment
+    resolves: Vec<Option<ImageId>>,
+    /// resolve targets given before any color attachment, reported by `compile`
+    stray_resolves: Vec<ImageId>,
+    images: Vec<(ImageId, ImageAccess)>,
+    buffers: Vec<(BufferId, BufferAccess)>,
+    side_effects: bool,
+    record: RecordFn<'a>,
+}
+
+/// declares what a pass reads and writes, finished with `record`
+pub struct PassBuilder<'g, 'a> {
+    graph: &'g mut RenderGraph<'a>,
+    name: String,
+    colors: Vec<Attachment>,
+    depth: Option<Attachment>,
+    resolves: Vec<Option<ImageId>>,
+    stray_resolves: Vec<ImageId>,
+    images: Vec<(ImageId, ImageAccess)>,
+    buffers: Vec<(BufferId, BufferAccess)>,
+    side_effects: bool,
+}
+
+impl<'a> PassBuilder<'_, 'a> {
+    /// the next color attachment, in the order the fragment shader writes them
+    pub fn color(mut self, image: ImageId, load: AttachmentLoad) -> Self {
+        self.colors.push(Attachment { image, load });
+        self.resolves.push(None);
+        self
+    }
+
+    pub fn depth(mut self, image: ImageId, load: AttachmentLoad) -> Self {
+        self.depth = Some(Attachment { image, load });
+        self
+    }
+
+    /// resolves the last color attachment into `target` at the end of the pass
+    pub fn resolve(mut self, target: ImageId) -> Self {
+        match self.resolves.last_mut() {
+            Some(resolve) => *resolve = Some(target),
+            None => self.stray_resolves.push(target),
+        }
+        self
+    }
+
+    pub fn image(mut self, image: ImageId, access: ImageAccess) -> Self {
+        self.images.push((image, access));
+        self
+    }
+
+    pub fn buffer(mut self, buffer: BufferId, access: BufferAccess) -> Self {
+        self.buffers.push((buffer, access));
+        self
+    }
+
+    /// keeps the pass even if nothing uses what it writes
+    pub fn side_effects(mut self) -> Self {
+        self.side_effects = true;
+        self
+    }
+
+    /// passes with attachments are recorded inside a render pass (or dynamic rendering) over
+    /// the attachments' extent
+    pub fn record<F>(self, record: F)
+    where
+        F: FnOnce(&PassContext) -> Result<(), EngineError> + 'a,
+    {
+        self.graph.passes.push(PassNode {
+            name: self.name,
+            colors: self.colors,
+            depth: self.depth,
+            resolves: self.resolves,
+            stray_resolves: self.stray_resolves,
+            images: self.images,
+            buffers: self.buffers,
+            side_effects: self.side_effects,
+            record: Box::new(record),
+        });
+    }
+}
+
+struct ImageResource {
+    name: String,
+    desc: ImageDesc,
+    imported: Option<ImportedImage>,
+}
+
+struct BufferResource {
+    name: String,
+    size: u64,
+    imported: Option<ImportedBuffer>,
+}
+
+/// the passes of one frame and the resources they use. passes are ordered so that every pass
+/// reading a resource runs after the passes writing it (passes writing the same resource keep
+/// the order they were added in), passes whose writes nobody reads are culled, and the barriers
+/// and layout transitions between them are inserted when executing. transient images and
+/// buffers, render passes and framebuffers come from a `RenderGraphCache`, so building the
+/// graph anew every frame is cheap
+///
+/// ```ignore
+/// let mut graph = RenderGraph::default();
+/// let target = graph.import_image("swapchain", ImportedImage::new(image, view, desc)
+///     .initial_state(ImageState::ACQUIRED)
+///     .final_state(ImageState::PRESENT));
+/// let depth = graph.create_image("depth", ImageDesc::new(depth_format, extent));
+/// graph
+///     .add_pass("main")
+///     .color(target, AttachmentLoad::clear_color([0.0, 0.0, 0.0, 1.0]))
+///     .depth(depth, AttachmentLoad::clear_depth(1.0, 0))
+///     .record(|ctx| record_draws(ctx.logical_device, ctx.command_buffer, &pipelines, &draws));
+/// graph.execute(&device, &mut allocator, &mut cache, command_buffer)?;
+/// ```
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+    images: Vec<ImageResource>,
+    buffers: Vec<BufferResource>,
+    passes: Vec<PassNode<'a>>,
+}
+
+impl<'a> RenderGraph<'a> {
+    /// an image that only lives during the graph, its usage flags are derived from the passes
+    pub fn create_image(&mut self, name: impl Into<String>, desc: ImageDesc) -> ImageId {
+        self.images.push(ImageResource {
+            name: name.into(),
+            desc,
+            imported: None,
+        });
+        ImageId(self.images.len() - 1)
+    }
+
+    pub fn import_image(&mut self, name: impl Into<String>, image: ImportedImage) -> ImageId {
+        self.images.push(ImageResource {
+            name: name.into(),
+            desc: image.desc,
+            imported: Some(image),
+        });
+        ImageId(self.images.len() - 1)
+    }
+
+    /// a device local buffer that only lives during the graph
+    pub fn create_buffer(&mut self, name: impl Into<String>, size: u64) -> BufferId {
+        self.buffers.push(BufferResource {
+            name: name.into(),
+            size,
+            imported: None,
+        });
+        BufferId(self.buffers.len() - 1)
+    }
+
+    pub fn import_buffer(&mut self, name: impl Into<String>, buffer: ImportedBuffer) -> BufferId {
+        self.buffers.push(BufferResource {
+            name: name.into(),
+            size: vk::WHOLE_SIZE,
+            imported: Some(buffer),
+        });
+        BufferId(self.buffers.len() - 1)
+    }
+
+    pub fn add_pass<'g>(&'g mut self, name: impl Into<String>) -> PassBuilder<'g, 'a> {
+        PassBuilder {
+            graph: self,
+            name: name.into(),
+            colors: Vec::new(),
+            depth: None,
+            resolves: Vec::new(),
+            stray_resolves: Vec::new(),
+            images: Vec::new(),
+            buffers: Vec::new(),
+            side_effects: false,
+        }
+    }
+
+    /// the names of the passes that would run, in the order they would run in
+    pub fn pass_order(&self) -> Result<Vec<&str>, EngineError> {
+        Ok(self
+            .compile()?
+            .order
+            .iter()
+            .map(|&pass| self.passes[pass].name.as_str())
+            .collect())
+    }
+
+    /// records every pass that isn't culled into `command_buffer`, which has to be recording
+    /// already. the resources from `cache` may be in use until the command buffer finished
+    pub fn execute(
+        self,
+        logical_device: &ash::Device,
+        allocator: &mut Allocator,
+        cache: &mut RenderGraphCache,
+        command_buffer: vk::CommandBuffer,
+    ) -> Result<(), EngineError> {
+        let compiled = self.compile()?;
+        let dynamic_rendering = cache.dynamic_rendering;
+
+        let mut taken = HashMap::new();
+        let mut images = Vec::with_capacity(self.images.len());
+        for (resource, &usage) in self.images.iter().zip(&compiled.image_usage) {
+            images.push(match &resource.imported {
+                Some(imported) => PhysicalImage {
+                    image: imported.image,
+                    view: imported.view,
+                    aspect_mask: resource.desc.aspect_mask(),
+                    state: TrackedState::from_state(
+                        imported.initial_state.layout,
+                        imported.initial_state.stages,
+                        imported.initial_state.access,
+                    ),
+                    transient: None,
+                },
+                None if usage.is_empty() => PhysicalImage::default(),
+                None => {
+                    let key = (resource.desc, usage);
+                    let index = taken.entry(key).or_insert(0);
+                    let transient =
+                        cache.image(logical_device, allocator, resource.desc, usage, *index)?;
+                    *index += 1;
+                    PhysicalImage {
+                        image: transient.image,
+                        view: transient.view,
+                        aspect_mask: resource.desc.aspect_mask(),
+                        // transient contents never survive a frame
+                        state: TrackedState {
+                            layout: vk::ImageLayout::UNDEFINED,
+                            ..transient.state
+                        },
+                        transient: Some((key, *index - 1)),
+                    }
+                }
+            });
+        }
+
+        let mut taken = HashMap::new();
+        let mut buffers = Vec::with_capacity(self.buffers.len());
+        let mut buffer_states = Vec::with_capacity(self.buffers.len());
+        let mut buffer_transients = Vec::with_capacity(self.buffers.len());
+        for (resource, &usage) in self.buffers.iter().zip(&compiled.buffer_usage) {
+            match &resource.imported {
+                Some(imported) => {
+                    buffers.push(imported.buffer);
+                    buffer_states.push(TrackedState::from_state(
+                        vk::ImageLayout::UNDEFINED,
+                        imported.initial_state.stages,
+                        imported.initial_state.access,
+                    ));
+                    buffer_transients.push(None);
+                }
+                None if usage.is_empty() => {
+                    buffers.push(vk::Buffer::null());
+                    buffer_states.push(TrackedState::default());
+                    buffer_transients.push(None);
+                }
+                None => {
+                    let key = (resource.size, usage);
+                    let index = taken.entry(key).or_insert(0);
+                    let transient =
+                        cache.buffer(logical_device, allocator, resource.size, usage, *index)?;
+                    buffers.push(transient.buffer);
+                    buffer_states.push(transient.state);
+                    buffer_transients.push(Some((key, *index)));
+                    *index += 1;
+                }
+            }
+        }
+
+        let mut passes: Vec<Option<PassNode>> = self.passes.into_iter().map(Some).collect();
+        for &pass_index in &compiled.order {
+            let pass = passes[pass_index].take().expect("every pass runs once");
+
+            let mut barriers = Barriers::default();
+            for (image, usage) in pass_image_uses(&pass) {
+                let physical = &mut images[image.0];
+                barriers.image(physical, &usage);
+            }
+            for &(buffer, access) in &pass.buffers {
+                barriers.buffer(
+                    buffers[buffer.0],
+                    &mut buffer_states[buffer.0],
+                    &buffer_use(access),
+                );
+            }
+            barriers.record(logical_device, command_buffer);
+
+            if pass.colors.is_empty() && pass.depth.is_none() {
+                let context = PassContext {
+                    logical_device,
+                    command_buffer,
+                    render_pass: vk::RenderPass::null(),
+                    extent: vk::Extent2D::default(),
+                    images: &images,
+                    buffers: &buffers,
+                };
+                (pass.record)(&context)?;
+                continue;
+            }
+
+            let attachments: Vec<&Attachment> = pass.colors.iter().chain(&pass.depth).collect();
+            let extent = self.images[attachments[0].image.0].desc.extent;
+            let render_pass = match &dynamic_rendering {
+                Some(dynamic_rendering) => {
+                    let stored = &compiled.stored[pass_index];
+                    begin_rendering(
+                        dynamic_rendering,
+                        command_buffer,
+                        &pass,
+                        &images,
+                        stored,
+                        extent,
+                    );
+                    vk::RenderPass::null()
+                }
+                None => {
+                    let attachment_key = |attachment: &Attachment, layout| {
+                        let desc = self.images[attachment.image.0].desc;
+                        let store_op = store_op(&compiled.stored[pass_index], attachment.image);
+                        AttachmentKey::new(desc, attachment.load.op(), store_op, layout)
+                    };
+                    let render_pass_key = RenderPassKey {
+                        colors: pass
+                            .colors
+                            .iter()
+                            .map(|color| {
+                                attachment_key(color, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                            })
+                            .collect(),
+                        depth: pass.depth.as_ref().map(|depth| {
+                            attachment_key(depth, vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+                        }),
+                        resolves: pass
+                            .resolves
+                            .iter()
+                            .map(|resolve| {
+                                resolve.map(|image| {
+                                    AttachmentKey::new(
+                                        self.images[image.0].desc,
+                                        vk::AttachmentLoadOp::DONT_CARE,
+                                        vk::AttachmentStoreOp::STORE,
+                                        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                                    )
+                                })
+                            })
+                            .collect(),
+                    };
+                    let render_pass = cache.render_pass(logical_device, render_pass_key)?;
+
+                    let views: Vec<vk::ImageView> = attachments
+                        .iter()
+                        .map(|attachment| images[attachment.image.0].view)
+                        .chain(
+                            pass.resolves
+                                .iter()
+                                .flatten()
+                                .map(|image| images[image.0].view),
+                        )
+                        .collect();
+                    let framebuffer =
+                        cache.framebuffer(logical_device, render_pass, views, extent)?;
+                    // every attachment has a slot, only the cleared ones are read
+                    let clear_values: Vec<vk::ClearValue> = attachments
+                        .iter()
+                        .map(|attachment| match attachment.load {
+                            AttachmentLoad::Clear(value) => value,
+                            _ => vk::ClearValue::default(),
+                        })
+                        .collect();
+
+                    let render_pass_begin_info = vk::RenderPassBeginInfo::builder()
+                        .render_pass(render_pass)
+                        .framebuffer(framebuffer)
+                        .render_area(vk::Rect2D {
+                            offset: vk::Offset2D { x: 0, y: 0 },
+                            extent,
+                        })
+                        .clear_values(&clear_values);
+                    unsafe {
+                        logical_device.cmd_begin_render_pass(
+                            command_buffer,
+                            &render_pass_begin_info,
+                            vk::SubpassContents::INLINE,
+                        );
+                    }
+                    render_pass
+                }
+            };
+            let context = PassContext {
+                logical_device,
+                command_buffer,
+                render_pass,
+                extent,
+                images: &images,
+                buffers: &buffers,
+            };
+            let result = (pass.record)(&context);
+            unsafe {
+                match &dynamic_rendering {
+                    Some(dynamic_rendering) => dynamic_rendering.cmd_end_rendering(command_buffer),
+                    None => logical_device.cmd_end_render_pass(command_buffer),
+                }
+            }
+            result?;
+        }
+
+        let mut barriers = Barriers::default();
+        for (resource, physical) in self.images.iter().zip(&mut images) {
+            if let Some(ImageState {
+                layout,
+                stages,
+                access,
+            }) = resource.imported.and_then(|imported| imported.final_state)
+            {
+                barriers.image(physical, &ResourceUse::read(layout, stages, access));
+            }
+        }
+        for (index, resource) in self.buffers.iter().enumerate() {
+            if let Some(BufferState { stages, access }) =
+                resource.imported.and_then(|imported| imported.final_state)
+            {
+                let usage = ResourceUse::read(vk::ImageLayout::UNDEFINED, stages, access);
+                barriers.buffer(buffers[index], &mut buffer_states[index], &usage);
+            }
+        }
+        barriers.record(logical_device, command_buffer);
+
+        // the next frame's barriers have to wait for this frame's last accesses
+        for image in &images {
+            if let Some((key, index)) = image.transient {
+                cache.images.get_mut(&key).expect("taken from the cache")[index].state =
+                    image.state;
+            }
+        }
+        for (transient, state) in buffer_transients.iter().zip(buffer_states) {
+            if let Some((key, index)) = *transient {
+                cache.buffers.get_mut(&key).expect("taken from the cache")[index].state = state;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn compile(&self) -> Result<CompiledGraph, EngineError> {
+        let mut image_passes: Vec<Vec<(usize, bool, bool)>> = vec![Vec::new(); self.images.len()];
+        for (pass_index, pass) in self.passes.iter().enumerate() {
+            if let Some(image) = pass.stray_resolves.first() {
+                return Err(EngineError::RenderGraph(format!(
+                    "pass {} resolves into {} before it has a color attachment",
+                    pass.name, self.images[image.0].name
+                )));
+            }
+            let attachments: Vec<&Attachment> = pass.colors.iter().chain(&pass.depth).collect();
+            if let Some(first) = attachments.first() {
+                let extent = self.images[first.image.0].desc.extent;
+                let mismatched = attachments
+                    .iter()
+                    .map(|attachment| attachment.image)
+                    .chain(pass.resolves.iter().flatten().copied())
+                    .find(|image| self.images[image.0].desc.extent != extent);
+                if let Some(image) = mismatched {
+                    return Err(EngineError::RenderGraph(format!(
+                        "attachment {} of pass {} doesn't have the extent of the others",
+                        self.images[image.0].name, pass.name
+                    )));
+                }
+            }
+
+            let mut layouts: Vec<(ImageId, vk::ImageLayout)> = Vec::new();
+            for (image, usage) in pass_image_uses(pass) {
+                match layouts.iter().find(|(other, _)| *other == image) {
+                    Some(&(_, layout)) if layout != usage.layout => {
+                        return Err(EngineError::RenderGraph(format!(
+                            "pass {} uses {} in both {layout:?} and {:?}",
+                            pass.name, self.images[image.0].name, usage.layout
+                        )));
+                    }
+                    Some(_) => {}
+                    None => layouts.push((image, usage.layout)),
+                }
+                image_passes[image.0].push((pass_index, usage.reads, usage.writes()));
+            }
+        }
+        if let Some(buffer) = self
+            .buffers
+            .iter()
+            .find(|buffer| buffer.imported.is_none() && buffer.size == 0)
+        {
+            return Err(EngineError::RenderGraph(format!(
+                "transient buffer {} is empty",
+                buffer.name
+            )));
+        }
+        let mut buffer_passes: Vec<Vec<(usize, bool, bool)>> = vec![Vec::new(); self.buffers.len()];
+        for (pass_index, pass) in self.passes.iter().enumerate() {
+            for &(buffer, access) in &pass.buffers {
+                let usage = buffer_use(access);
+                buffer_passes[buffer.0].push((pass_index, usage.reads, usage.writes()));
+            }
+        }
+
+        // (from, to, to needs the data from) edges. a reader depends on the last writer added
+        // before it, and the next writer has to wait until the readers are done
+        let mut edges = Vec::new();
+        for passes in image_passes.iter().chain(&buffer_passes) {
+            let mut last_writer: Option<usize> = None;
+            let mut readers: Vec<usize> = Vec::new();
+            for &(pass, reads, writes) in passes {
+                if let Some(writer) = last_writer.filter(|&writer| writer != pass) {
+                    edges.push((writer, pass, reads));
+                }
+                if writes {
+                    for &reader in readers.iter().filter(|&&reader| reader != pass) {
+                        edges.push((reader, pass, false));
+                    }
+                    readers.clear();
+                    last_writer = Some(pass);
+                } else if reads {
+                    readers.push(pass);
+                }
+            }
+        }
+
+        let writes = |passes: &[(usize, bool, bool)], pass: usize| {
+            passes
+                .iter()
+                .any(|&(other, _, writes)| other == pass && writes)
+        };
+        let writes_output = |pass: usize| {
+            image_passes
+                .iter()
+                .zip(&self.images)
+                .any(|(passes, resource)| resource.imported.is_some() && writes(passes, pass))
+                || buffer_passes
+                    .iter()
+                    .zip(&self.buffers)
+                    .any(|(passes, resource)| resource.imported.is_some() && writes(passes, pass))
+        };
+        let mut kept = vec![false; self.passes.len()];
+        let mut stack: Vec<usize> = (0..self.passes.len())
+            .filter(|&pass| self.passes[pass].side_effects || writes_output(pass))
+            .collect();
+        while let Some(pass) = stack.pop() {
+            if std::mem::replace(&mut kept[pass], true) {
+                continue;
+            }
+            stack.extend(
+                edges
+                    .iter()
+                    .filter(|&&(from, to, data)| data && to == pass && !kept[from])
+                    .map(|&(from, _, _)| from),
+            );
+        }
+
+        let order = topological_order(&kept, &edges).map_err(|stuck| {
+            let stuck: Vec<&str> = stuck
+                .iter()
+                .map(|&pass| self.passes[pass].name.as_str())
+                .collect();
+            EngineError::RenderGraph(format!("passes {stuck:?} depend on each other"))
+        })?;
+
+        let mut image_usage = vec![vk::ImageUsageFlags::empty(); self.images.len()];
+        for pass in order.iter().map(|&pass| &self.passes[pass]) {
+            for (image, usage) in pass_image_uses(pass) {
+                image_usage[image.0] |= usage.image_usage;
+            }
+        }
+        for usage in &mut image_usage {
+            let attachment_only = vk::ImageUsageFlags::COLOR_ATTACHMENT
+                | vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+                | vk::ImageUsageFlags::INPUT_ATTACHMENT;
+            if !usage.is_empty() && attachment_only.contains(*usage) {
+                *usage |= vk::ImageUsageFlags::TRANSIENT_ATTACHMENT;
+            }
+        }
+        let mut buffer_usage = vec![vk::BufferUsageFlags::empty(); self.buffers.len()];
+        for pass in order.iter().map(|&pass| &self.passes[pass]) {
+            for &(buffer, access) in &pass.buffers {
+                buffer_usage[buffer.0] |= buffer_use(access).buffer_usage;
+            }
+        }
+
+        // an attachment is only stored if something after its pass reads it
+        let mut stored = vec![Vec::new(); self.passes.len()];
+        for (position, &pass) in order.iter().enumerate() {
+            for (image, _) in pass_image_uses(&self.passes[pass]) {
+                let read_later = self.images[image.0].imported.is_some()
+                    || order[position + 1..].iter().any(|&later| {
+                        pass_image_uses(&self.passes[later])
+                            .iter()
+                            .any(|(other, usage)| *other == image && usage.reads)
+                    });
+                if read_later {
+                    stored[pass].push(image);
+                }
+            }
+        }
+
+        Ok(CompiledGraph {
+            order,
+            image_usage,
+            buffer_usage,
+            stored,
+        })
+    }
+}
+
+/// the kept passes ordered along the edges, ties go to the pass added first. on a cycle the
+/// passes that never became ready are returned instead
+fn topological_order(
+    kept: &[bool],
+    edges: &[(usize, usize, bool)],
+) -> Result<Vec<usize>, Vec<usize>> {
+    let mut incoming = vec![0; kept.len()];
+    for &(from, to, _) in edges {
+        if kept[from] && kept[to] {
+            incoming[to] += 1;
+        }
+    }
+    let mut ready: BinaryHeap<Reverse<usize>> = (0..kept.len())
+        .filter(|&pass| kept[pass] && incoming[pass] == 0)
+        .map(Reverse)
+        .collect();
+    let mut order = Vec::new();
+    while let Some(Reverse(pass)) = ready.pop() {
+        order.push(pass);
+        for &(from, to, _) in edges {
+            if from == pass && kept[to] {
+                incoming[to] -= 1;
+                if incoming[to] == 0 {
+                    ready.push(Reverse(to));
+                }
+            }
+        }
+    }
+    if order.len() != kept.iter().filter(|&&kept| kept).count() {
+        return Err((0..kept.len())
+            .filter(|&pass| kept[pass] && incoming[pass] > 0)
+            .collect());
+    }
+    Ok(order)
+}
+
+struct CompiledGraph {
+    order: Vec<usize>,
+    image_usage: Vec<vk::ImageUsageFlags>,
+    buffer_usage: Vec<vk::BufferUsageFlags>,
+    /// per pass, the attachments that have to be stored
+    stored: Vec<Vec<ImageId>>,
+}
+
+/// a layout, stages and access a pass needs a resource in
+struct ResourceUse {
+    layout: vk::ImageLayout,
+    stages: vk::PipelineStageFlags,
+    access: vk::AccessFlags,
+    /// whether the pass needs the previous contents
+    reads: bool,
+    image_usage: vk::ImageUsageFlags,
+    buffer_usage: vk::BufferUsageFlags,
+}
+
+impl ResourceUse {
+    fn read(
+        layout: vk::ImageLayout,
+        stages: vk::PipelineStageFlags,
+        access: vk::AccessFlags,
+    ) -> Self {
+        ResourceUse {
+            layout,
+            stages,
+            access,
+            reads: true,
+            image_usage: vk::ImageUsageFlags::empty(),
+            buffer_usage: vk::BufferUsageFlags::empty(),
+        }
+    }
+
+    fn writes(&self) -> bool {
+        self.access.intersects(
+            vk::AccessFlags::SHADER_WRITE
+                | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
+                | vk::AccessFlags::TRANSFER_WRITE
+                | vk::AccessFlags::HOST_WRITE
+                | vk::AccessFlags::MEMORY_WRITE,
+        )
+    }
+}
+
+fn image_use(access: ImageAccess) -> ResourceUse {
+    let (layout, stages, access_flags, image_usage) = match access {
+        ImageAccess::Sampled(stages) => (
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            stages,
+            vk::AccessFlags::SHADER_READ,
+            vk::ImageUsageFlags::SAMPLED,
+        ),
+        ImageAccess::StorageRead(stages) => (
+            vk::ImageLayout::GENERAL,
+            stages,
+            vk::AccessFlags::SHADER_READ,
+            vk::ImageUsageFlags::STORAGE,
+        ),
+        ImageAccess::StorageWrite(stages) => (
+            vk::ImageLayout::GENERAL,
+            stages,
+            vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+            vk::ImageUsageFlags::STORAGE,
+        ),
+        ImageAccess::TransferSrc => (
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::TRANSFER_READ,
+            vk::ImageUsageFlags::TRANSFER_SRC,
+        ),
+        ImageAccess::TransferDst => (
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::ImageUsageFlags::TRANSFER_DST,
+        ),
+    };
+    ResourceUse {
+        layout,
+        stages,
+        access: access_flags,
+        reads: !matches!(access, ImageAccess::TransferDst),
+        image_usage,
+        buffer_usage: vk::BufferUsageFlags::empty(),
+    }
+}
+
+fn buffer_use(access: BufferAccess) -> ResourceUse {
+    let (stages, access_flags, buffer_usage) = match access {
+        BufferAccess::Uniform(stages) => (
+            stages,
+            vk::AccessFlags::UNIFORM_READ,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+        ),
+        BufferAccess::StorageRead(stages) => (
+            stages,
+            vk::AccessFlags::SHADER_READ,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+        ),
+        BufferAccess::StorageWrite(stages) => (
+            stages,
+            vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+        ),
+        BufferAccess::Vertex => (
+            vk::PipelineStageFlags::VERTEX_INPUT,
+            vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+        ),
+        BufferAccess::Index => (
+            vk::PipelineStageFlags::VERTEX_INPUT,
+            vk::AccessFlags::INDEX_READ,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+        ),
+        BufferAccess::Indirect => (
+            vk::PipelineStageFlags::DRAW_INDIRECT,
+            vk::AccessFlags::INDIRECT_COMMAND_READ,
+            vk::BufferUsageFlags::INDIRECT_BUFFER,
+        ),
+        BufferAccess::TransferSrc => (
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::TRANSFER_READ,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+        ),
+        BufferAccess::TransferDst => (
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::BufferUsageFlags::TRANSFER_DST,
+        ),
+    };
+    ResourceUse {
+        layout: vk::ImageLayout::UNDEFINED,
+        stages,
+        access: access_flags,
+        reads: !matches!(access, BufferAccess::TransferDst),
+        image_usage: vk::ImageUsageFlags::empty(),
+        buffer_usage,
+    }
+}
+
+/// every image a pass touches, attachments included
+fn pass_image_uses(pass: &PassNode) -> Vec<(ImageId, ResourceUse)> {
+    let attachment_use = |load: &AttachmentLoad, layout, stages, access, image_usage| ResourceUse {
+        layout,
+        stages,
+        access,
+        reads: matches!(load, AttachmentLoad::Load),
+        image_usage,
+        buffer_usage: vk::BufferUsageFlags::empty(),
+    };
+    let mut uses = Vec::new();
+    for color in &pass.colors {
+        uses.push((
+            color.image,
+            attachment_use(
+                &color.load,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            ),
+        ));
+    }
+    if let Some(depth) = &pass.depth {
+        uses.push((
+            depth.image,
+            attachment_use(
+                &depth.load,
+                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            ),
+        ));
+    }
+    for &image in pass.resolves.iter().flatten() {
+        uses.push((
+            image,
+            attachment_use(
+                &AttachmentLoad::DontCare,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            ),
+        ));
+    }
+    for &(image, access) in &pass.images {
+        uses.push((image, image_use(access)));
+    }
+    uses
+}
+
+/// what is known about a resource while recording: the last write, the reads since then and
+/// which stages the write was made visible to
+#[derive(Debug, Clone, Copy, Default)]
+struct TrackedState {
+    layout: vk::ImageLayout,
+    write_stages: vk::PipelineStageFlags,
+    write_access: vk::AccessFlags,
+    read_stages: vk::PipelineStageFlags,
+    visible_stages: vk::PipelineStageFlags,
+    visible_access: vk::AccessFlags,
+}
+
+impl TrackedState {
+    /// whatever accessed the resource before is treated as a write that has to finish first
+    fn from_state(
+        layout: vk::ImageLayout,
+        stages: vk::PipelineStageFlags,
+        access: vk::AccessFlags,
+    ) -> Self {
+        TrackedState {
+            layout,
+            write_stages: stages,
+            write_access: access,
+            ..Default::default()
+        }
+    }
+
+    /// moves the state on to `next`, returning the stages and access the barrier before it has
+    /// to wait for, if one is needed
+    fn transition(
+        &mut self,
+        next: &ResourceUse,
+    ) -> Option<(vk::PipelineStageFlags, vk::AccessFlags)> {
+        let layout_changes = self.layout != next.layout;
+        if next.writes() {
+            let src_stages = self.write_stages | self.read_stages;
+            let src = (!src_stages.is_empty() || layout_changes)
+                .then_some((src_stages, self.write_access));
+            *self = TrackedState {
+                layout: next.layout,
+                write_stages: next.stages,
+                write_access: next.access
+                    & !(vk::AccessFlags::SHADER_READ
+                        | vk::AccessFlags::COLOR_ATTACHMENT_READ
+                        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ),
+                ..Default::default()
+            };
+            return src;
+        }
+
+        let visible =
+            self.visible_stages.contains(next.stages) && self.visible_access.contains(next.access);
+        if layout_changes {
+            let src = (self.write_stages | self.read_stages, self.write_access);
+            // the transition itself counts as a write the later reads have to wait for
+            *self = TrackedState {
+                layout: next.layout,
+                write_stages: next.stages,
+                write_access: vk::AccessFlags::empty(),
+                read_stages: next.stages,
+                visible_stages: next.stages,
+                visible_access: next.access,
+            };
+            Some(src)
+        } else if !self.write_stages.is_empty() && !visible {
+            self.visible_stages |= next.stages;
+            self.visible_access |= next.access;
+            self.read_stages |= next.stages;
+            Some((self.write_stages, self.write_access))
+        } else {
+            self.read_stages |= next.stages;
+            None
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct PhysicalImage {
+    image: vk::Image,
+    view: vk::ImageView,
+    aspect_mask: vk::ImageAspectFlags,
+    state: TrackedState,
+    /// where to put the final state back in the cache
+    transient: Option<((ImageDesc, vk::ImageUsageFlags), usize)>,
+}
+
+/// the barriers before a pass, recorded as one `cmd_pipeline_barrier`
+#[derive(Default)]
+struct Barriers {
+    src_stages: vk::PipelineStageFlags,
+    dst_stages: vk::PipelineStageFlags,
+    images: Vec<vk::ImageMemoryBarrier>,
+    buffers: Vec<vk::BufferMemoryBarrier>,
+}
+
+impl Barriers {
+    fn image(&mut self, physical: &mut PhysicalImage, next: &ResourceUse) {
+        let old_layout = physical.state.layout;
+        if let Some((src_stages, src_access)) = physical.state.transition(next) {
+            self.src_stages |= src_stages;
+            self.dst_stages |= next.stages;
+            self.images.push(
+                vk::ImageMemoryBarrier::builder()
+                    .src_access_mask(src_access)
+                    .dst_access_mask(next.access)
+                    .old_layout(old_layout)
+                    .new_layout(next.layout)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .image(physical.image)
+                    .subresource_range(vk::ImageSubresourceRange {
+                        aspect_mask: physical.aspect_mask,
+                        base_mip_level: 0,
+                        level_count: vk::REMAINING_MIP_LEVELS,
+                        base_array_layer: 0,
+                        layer_count: vk::REMAINING_ARRAY_LAYERS,
+                    })
+                    .build(),
+            );
+        }
+    }
+
+    fn buffer(&mut self, buffer: vk::Buffer, state: &mut TrackedState, next: &ResourceUse) {
+        if let Some((src_stages, src_access)) = state.transition(next) {
+            if src_stages.is_empty() {
+                return;
+            }
+            self.src_stages |= src_stages;
+            self.dst_stages |= next.stages;
+            self.buffers.push(
+                vk::BufferMemoryBarrier::builder()
+                    .src_access_mask(src_access)
+                    .dst_access_mask(next.access)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .buffer(buffer)
+                    .offset(0)
+                    .size(vk::WHOLE_SIZE)
+                    .build(),
+            );
+        }
+    }
+
+    fn record(self, logical_device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        if self.images.is_empty() && self.buffers.is_empty() {
+            return;
+        }
+        let src_stages = if self.src_stages.is_empty() {
+            vk::PipelineStageFlags::TOP_OF_PIPE
+        } else {
+            self.src_stages
+        };
+        let dst_stages = if self.dst_stages.is_empty() {
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE
+        } else {
+            self.dst_stages
+        };
+        unsafe {
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                src_stages,
+                dst_stages,
+                vk::DependencyFlags::empty(),
+                &[],
+                &self.buffers,
+                &self.images,
+            );
+        }
+    }
+}
+
+fn store_op(stored: &[ImageId], image: ImageId) -> vk::AttachmentStoreOp {
+    if stored.contains(&image) {
+        vk::AttachmentStoreOp::STORE
+    } else {
+        vk::AttachmentStoreOp::DONT_CARE
+    }
+}
+
+/// what the render pass and framebuffer from the cache describe otherwise: the same attachments,
+/// layouts and resolves, with the load and store ops on the attachments themselves
+fn begin_rendering(
+    dynamic_rendering: &DynamicRendering,
+    command_buffer: vk::CommandBuffer,
+    pass: &PassNode,
+    images: &[PhysicalImage],
+    stored: &[ImageId],
+    extent: vk::Extent2D,
+) {
+    let attachment_info = |attachment: &Attachment, layout| {
+        let clear_value = match attachment.load {
+            AttachmentLoad::Clear(value) => value,
+            _ => vk::ClearValue::default(),
+        };
+        vk::RenderingAttachmentInfo::builder()
+            .image_view(images[attachment.image.0].view)
+            .image_layout(layout)
+            .load_op(attachment.load.op())
+            .store_op(store_op(stored, attachment.image))
+            .clear_value(clear_value)
+    };
+    let color_attachments: Vec<vk::RenderingAttachmentInfo> = pass
+        .colors
+        .iter()
+        .zip(&pass.resolves)
+        .map(|(color, resolve)| {
+            let info = attachment_info(color, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+            match resolve {
+                Some(target) => info
+                    .resolve_mode(vk::ResolveModeFlags::AVERAGE)
+                    .resolve_image_view(images[target.0].view)
+                    .resolve_image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                    .build(),
+                None => info.build(),
+            }
+        })
+        .collect();
+    let depth_attachment = pass.depth.as_ref().map(|depth| {
+        attachment_info(depth, vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL).build()
+    });
+
+    let mut rendering_info = vk::RenderingInfo::builder()
+        .render_area(vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent,
+        })
+        .layer_count(1)
+        .color_attachments(&color_attachments);
+    if let (Some(depth), Some(depth_attachment)) = (&pass.depth, &depth_attachment) {
+        rendering_info = rendering_info.depth_attachment(depth_attachment);
+        // a combined format is both the depth and the stencil attachment
+        if images[depth.image.0]
+            .aspect_mask
+            .contains(vk::ImageAspectFlags::STENCIL)
+        {
+            rendering_info = rendering_info.stencil_attachment(depth_attachment);
+        }
+    }
+    unsafe { dynamic_rendering.cmd_begin_rendering(command_buffer, &rendering_info) };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct AttachmentKey {
+    format: vk::Format,
+    samples: vk::SampleCountFlags,
+    load_op: vk::AttachmentLoadOp,
+    store_op: vk::AttachmentStoreOp,
+    stencil_load_op: vk::AttachmentLoadOp,
+    stencil_store_op: vk::AttachmentStoreOp,
+    layout: vk::ImageLayout,
+}
+
+impl AttachmentKey {
+    fn new(
+        desc: ImageDesc,
+        load_op: vk::AttachmentLoadOp,
+        store_op: vk::AttachmentStoreOp,
+        layout: vk::ImageLayout,
+    ) -> Self {
+        let (stencil_load_op, stencil_store_op) = if depth::has_stencil(desc.format) {
+            (load_op, store_op)
+        } else {
+            (
+                vk::AttachmentLoadOp::DONT_CARE,
+                vk::AttachmentStoreOp::DONT_CARE,
+            )
+        };
+        AttachmentKey {
+            format: desc.format,
+            samples: desc.samples,
+            load_op,
+            store_op,
+            stencil_load_op,
+            stencil_store_op,
+            layout,
+        }
+    }
+
+    fn description(&self) -> vk::AttachmentDescription {
+        vk::AttachmentDescription::builder()
+            .format(self.format)
+            .samples(self.samples)
+            .load_op(self.load_op)
+            .store_op(self.store_op)
+            .stencil_load_op(self.stencil_load_op)
+            .stencil_store_op(self.stencil_store_op)
+            .initial_layout(self.layout)
+            .final_layout(self.layout)
+            .build()
+    }
+}
+
+/// attachments are laid out like in `init_render_pass`: colors, depth, then the resolve targets,
+/// so the render passes stay compatible with the pipelines built against it
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct RenderPassKey {
+    colors: Vec<AttachmentKey>,
+    depth: Option<AttachmentKey>,
+    resolves: Vec<Option<AttachmentKey>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct FramebufferKey {
+    render_pass: vk::RenderPass,
+    views: Vec<vk::ImageView>,
+    extent: vk::Extent2D,
+}
+
+struct TransientImage {
+    image: vk::Image,
+    allocation: Allocation,
+    view: vk::ImageView,
+    state: TrackedState,
+}
+
+struct TransientBuffer {
+    buffer: vk::Buffer,
+    allocation: Allocation,
+    state: TrackedState,
+}
+
+/// what the graphs of consecutive frames share: transient images and buffers, render passes
+/// and framebuffers. everything lives until `cleanup`, which has to be called whenever imported
+/// views go away (e.g. with the swapchain), since framebuffers are looked up by view
+#[derive(Default)]
+pub struct RenderGraphCache {
+    /// begins passes with `cmd_begin_rendering` instead of render passes and framebuffers
+    dynamic_rendering: Option<DynamicRendering>,
+    render_passes: HashMap<RenderPassKey, vk::RenderPass>,
+    framebuffers: HashMap<FramebufferKey, vk::Framebuffer>,
+    images: HashMap<(ImageDesc, vk::ImageUsageFlags), Vec<TransientImage>>,
+    buffers: HashMap<(u64, vk::BufferUsageFlags), Vec<TransientBuffer>>,
+}
+
+impl RenderGraphCache {
+    /// without `dynamic_rendering` this is the same as `default`. pipelines used by the passes
+    /// have to be built for the same kind of rendering
+    pub fn new(dynamic_rendering: Option<DynamicRendering>) -> Self {
+        RenderGraphCache {
+            dynamic_rendering,
+            ..Default::default()
+        }
+    }
+
+    pub fn image_count(&self) -> usize {
+        self.images.values().map(Vec::len).sum()
+    }
+
+    pub fn buffer_count(&self) -> usize {
+        self.buffers.values().map(Vec::len).sum()
+    }
+
+    /// destroys everything, the cache can be used again afterwards
+    ///
+    /// # Safety
+    /// the gpu must be done with every graph executed with this cache
+    pub unsafe fn cleanup(&mut self, logical_device: &ash::Device, allocator: &mut Allocator) {
+        for (_, framebuffer) in self.framebuffers.drain() {
+            logical_device.destroy_framebuffer(framebuffer, None);
+        }
+        for (_, render_pass) in self.render_passes.drain() {
+            logical_device.destroy_render_pass(render_pass, None);
+        }
+        for (_, images) in self.images.drain() {
+            for image in images {
+                logical_device.destroy_image_view(image.view, None);
+                allocator.destroy_image(logical_device, image.image, image.allocation);
+            }
+        }
+        for (_, buffers) in self.buffers.drain() {
+            for buffer in buffers {
+                allocator.destroy_buffer(logical_device, buffer.buffer, buffer.allocation);
+            }
+        }
+    }
+
+    /// the `index`th image with this desc and usage, created if there are fewer
+    fn image(
+        &mut self,
+        logical_device: &ash::Device,
+        allocator: &mut Allocator,
+        desc: ImageDesc,
+        usage: vk::ImageUsageFlags,
+        index: usize,
+    ) -> Result<&TransientImage, EngineError> {
+        let images = self.images.entry((desc, usage)).or_default();
+        while images.len() <= index {
+            let image_info = vk::ImageCreateInfo::builder()
+                .image_type(vk::ImageType::TYPE_2D)
+                .format(desc.format)
+                .extent(vk::Extent3D {
+                    width: desc.extent.width,
+                    height: desc.extent.height,
+                    depth: 1,
+                })
+                .mip_levels(1)
+                .array_layers(1)
+                .samples(desc.samples)
+                .tiling(vk::ImageTiling::OPTIMAL)
+                .usage(usage)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE)
+                .initial_layout(vk::ImageLayout::UNDEFINED);
+            let (image, allocation) =
+                allocator.create_image(logical_device, &image_info, MemoryLocation::GpuOnly)?;
+
+            let view_info = vk::ImageViewCreateInfo::builder()
+                .image(image)
+                .view_type(vk::ImageViewType::TYPE_2D)
+                .format(desc.format)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask: desc.aspect_mask(),
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                });
+            let view = match unsafe { logical_device.create_image_view(&view_info, None) }
+                .call("create_image_view")
+            {
+                Ok(view) => view,
+                Err(err) => {
+                    unsafe { allocator.destroy_image(logical_device, image, allocation) };
+                    return Err(err);
+                }
+            };
+            images.push(TransientImage {
+                image,
+                allocation,
+                view,
+                state: TrackedState::default(),
+            });
+        }
+        Ok(&images[index])
+    }
+
+    fn buffer(
+        &mut self,
+        logical_device: &ash::Device,
+        allocator: &mut Allocator,
+        size: u64,
+        usage: vk::BufferUsageFlags,
+        index: usize,
+    ) -> Result<&TransientBuffer, EngineError> {
+        let buffers = self.buffers.entry((size, usage)).or_default();
+        while buffers.len() <= index {
+            let buffer_info = vk::BufferCreateInfo::builder()
+                .size(size)
+                .usage(usage)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE);
+            let (buffer, allocation) =
+                allocator.create_buffer(logical_device, &buffer_info, MemoryLocation::GpuOnly)?;
+            buffers.push(TransientBuffer {
+                buffer,
+                allocation,
+                state: TrackedState::default(),
+            });
+        }
+        Ok(&buffers[index])
+    }
+
+    fn render_pass(
+        &mut self,
+        logical_device: &ash::Device,
+        key: RenderPassKey,
+    ) -> Result<vk::RenderPass, EngineError> {
+        if let Some(&render_pass) = self.render_passes.get(&key) {
+            return Ok(render_pass);
+        }
+
+        let attachments: Vec<vk::AttachmentDescription> = key
+            .colors
+            .iter()
+            .chain(&key.depth)
+            .chain(key.resolves.iter().flatten())
+            .map(AttachmentKey::description)
+            .collect();
+        let color_references: Vec<vk::AttachmentReference> = (0..key.colors.len() as u32)
+            .map(|attachment| vk::AttachmentReference {
+                attachment,
+                layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            })
+            .collect();
+        let depth_reference = vk::AttachmentReference {
+            attachment: key.colors.len() as u32,
+            layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        };
+        let mut next_resolve = (key.colors.len() + key.depth.iter().len()) as u32;
+        let resolve_references: Vec<vk::AttachmentReference> = key
+            .resolves
+            .iter()
+            .map(|resolve| {
+                let attachment = match resolve {
+                    Some(_) => {
+                        next_resolve += 1;
+                        next_resolve - 1
+                    }
+                    None => vk::ATTACHMENT_UNUSED,
+                };
+                vk::AttachmentReference {
+                    attachment,
+                    layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                }
+            })
+            .collect();
+
+        let mut subpass = vk::SubpassDescription::builder()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(&color_references);
+        if key.depth.is_some() {
+            subpass = subpass.depth_stencil_attachment(&depth_reference);
+        }
+        if key.resolves.iter().any(Option::is_some) {
+            subpass = subpass.resolve_attachments(&resolve_references);
+        }
+        let subpasses = [subpass.build()];
+
+        // the graph's barriers take care of everything outside the pass
+        let render_pass_info = vk::RenderPassCreateInfo::builder()
+            .attachments(&attachments)
+            .subpasses(&subpasses);
+        let render_pass = unsafe { logical_device.create_render_pass(&render_pass_info, None) }
+            .call("create_render_pass")?;
+        self.render_passes.insert(key, render_pass);
+        Ok(render_pass)
+    }
+
+    fn framebuffer(
+        &mut self,
+        logical_device: &ash::Device,
+        render_pass: vk::RenderPass,
+        views: Vec<vk::ImageView>,
+        extent: vk::Extent2D,
+    ) -> Result<vk::Framebuffer, EngineError> {
+        let key = FramebufferKey {
+            render_pass,
+            views,
+            extent,
+        };
+        if let Some(&framebuffer) = self.framebuffers.get(&key) {
+            return Ok(framebuffer);
+        }
+
+        let framebuffer_info = vk::FramebufferCreateInfo::builder()
+            .render_pass(render_pass)
+            .attachments(&key.views)
+            .width(extent.width)
+            .height(extent.height)
+            .layers(1);
+        let framebuffer = unsafe { logical_device.create_framebuffer(&framebuffer_info, None) }
+            .call("create_framebuffer")?;
+        self.framebuffers.insert(key, framebuffer);
+        Ok(framebuffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXTENT: vk::Extent2D = vk::Extent2D {
+        width: 64,
+        height: 64,
+    };
+
+    fn desc() -> ImageDesc {
+        ImageDesc::new(vk::Format::R8G8B8A8_UNORM, EXTENT)
+    }
+
+    fn import(graph: &mut RenderGraph, name: &str) -> ImageId {
+        graph.import_image(
+            name,
+            ImportedImage::new(vk::Image::null(), vk::ImageView::null(), desc()),
+        )
+    }
+
+    fn clear() -> AttachmentLoad {
+        AttachmentLoad::clear_color([0.0; 4])
+    }
+
+    fn sampled() -> ImageAccess {
+        ImageAccess::Sampled(vk::PipelineStageFlags::FRAGMENT_SHADER)
+    }
+
+    #[test]
+    fn readers_run_before_the_next_writer() {
+        let mut graph = RenderGraph::default();
+        let x = import(&mut graph, "x");
+        let y = import(&mut graph, "y");
+        let out = import(&mut graph, "out");
+        graph.add_pass("a").color(x, clear()).record(|_| Ok(()));
+        graph
+            .add_pass("r")
+            .color(out, clear())
+            .image(x, sampled())
+            .image(y, sampled())
+            .record(|_| Ok(()));
+        graph.add_pass("w").color(x, clear()).record(|_| Ok(()));
+        graph.add_pass("p").color(y, clear()).record(|_| Ok(()));
+
+        assert_eq!(graph.pass_order().unwrap(), ["a", "r", "w", "p"]);
+    }
+
+    #[test]
+    fn readers_wait_for_the_last_writer_before_them() {
+        let mut graph = RenderGraph::default();
+        let x = graph.create_image("x", desc());
+        let out = import(&mut graph, "out");
+        graph
+            .add_pass("read")
+            .color(out, clear())
+            .image(x, sampled())
+            .record(|_| Ok(()));
+        graph.add_pass("write").color(x, clear()).record(|_| Ok(()));
+
+        // the write comes after the read, so it doesn't feed it and gets culled
+        assert_eq!(graph.pass_order().unwrap(), ["read"]);
+    }
+
+    #[test]
+    fn unread_transients_are_culled() {
+        let mut graph = RenderGraph::default();
+        let shadow = graph.create_image("shadow", desc());
+        let unused = graph.create_image("unused", desc());
+        let out = import(&mut graph, "out");
+        graph
+            .add_pass("unused")
+            .color(unused, clear())
+            .record(|_| Ok(()));
+        graph
+            .add_pass("shadow")
+            .color(shadow, clear())
+            .record(|_| Ok(()));
+        graph
+            .add_pass("main")
+            .color(out, clear())
+            .image(shadow, sampled())
+            .record(|_| Ok(()));
+
+        assert_eq!(graph.pass_order().unwrap(), ["shadow", "main"]);
+    }
+
+    #[test]
+    fn side_effects_keep_a_pass() {
+        let mut graph = RenderGraph::default();
+        let culled = graph.create_buffer("culled", 16);
+        let kept = graph.create_buffer("kept", 16);
+        let storage = BufferAccess::StorageWrite(vk::PipelineStageFlags::COMPUTE_SHADER);
+        graph
+            .add_pass("culled")
+            .buffer(culled, storage)
+            .record(|_| Ok(()));
+        graph
+            .add_pass("kept")
+            .buffer(kept, storage)
+            .side_effects()
+            .record(|_| Ok(()));
+
+        assert_eq!(graph.pass_order().unwrap(), ["kept"]);
+    }
+
+    #[test]
+    fn resolves_without_a_color_attachment_are_reported() {
+        let mut graph = RenderGraph::default();
+        let msaa = graph.create_image("msaa", desc().samples(vk::SampleCountFlags::TYPE_4));
+        let out = import(&mut graph, "out");
+        graph
+            .add_pass("main")
+            .resolve(out)
+            .color(msaa, clear())
+            .record(|_| Ok(()));
+
+        assert!(matches!(
+            graph.pass_order(),
+            Err(EngineError::RenderGraph(message)) if message.contains("resolves into out")
+        ));
+    }
+
+    #[test]
+    fn cycles_are_reported() {
+        let kept = [true, true, true];
+        let edges = [(0, 1, true), (1, 2, true), (2, 1, false)];
+
+        assert_eq!(topological_order(&kept, &edges), Err(vec![1, 2]));
+        assert_eq!(topological_order(&kept, &edges[..2]), Ok(vec![0, 1, 2]));
+    }
+
+    #[test]
+    fn attachments_are_stored_only_when_read_later() {
+        let mut graph = RenderGraph::default();
+        let gbuffer = graph.create_image("gbuffer", desc());
+        let depth = graph.create_image("depth", ImageDesc::new(vk::Format::D32_SFLOAT, EXTENT));
+        let out = import(&mut graph, "out");
+        graph
+            .add_pass("geometry")
+            .color(gbuffer, clear())
+            .depth(depth, AttachmentLoad::clear_depth(1.0, 0))
+            .record(|_| Ok(()));
+        graph
+            .add_pass("lighting")
+            .color(out, clear())
+            .image(gbuffer, sampled())
+            .record(|_| Ok(()));
+
+        let compiled = graph.compile().unwrap();
+        let geometry = &compiled.stored[0];
+        let lighting = &compiled.stored[1];
+        assert_eq!(store_op(geometry, gbuffer), vk::AttachmentStoreOp::STORE);
+        assert_eq!(store_op(geometry, depth), vk::AttachmentStoreOp::DONT_CARE);
+        // imported images are always stored
+        assert_eq!(store_op(lighting, out), vk::AttachmentStoreOp::STORE);
+    }
+}
@@ -0,0 +1,228 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use ash::vk;
+
+use super::{
+    descriptor::DescriptorLayoutCache,
+    error::{EngineError, VkResultExt},
+    pipeline::{Pipeline, ShaderStage},
+};
+
+/// how often the watched files are checked for changes
+pub const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// compiles glsl (`.vert`, `.frag`, `.comp`) and wgsl (`.wgsl`) to spir-v with naga
+pub fn compile_file(path: &Path) -> Result<Vec<u32>, EngineError> {
+    let shader_error = |message: String| EngineError::Shader {
+        path: path.to_path_buf(),
+        message,
+    };
+    let source = fs::read_to_string(path)?;
+    let path_name = path.to_string_lossy();
+
+    let extension = path.extension().and_then(|extension| extension.to_str());
+    let (module, flags) = match extension {
+        Some("wgsl") => {
+            let module = naga::front::wgsl::parse_str(&source)
+                .map_err(|err| shader_error(err.emit_to_string_with_path(&source, &path_name)))?;
+            // wgsl clip space has y pointing up, vulkan has it pointing down
+            (
+                module,
+                naga::back::spv::WriterFlags::ADJUST_COORDINATE_SPACE,
+            )
+        }
+        Some(extension) => {
+            let stage = match extension {
+                "vert" => naga::ShaderStage::Vertex,
+                "frag" => naga::ShaderStage::Fragment,
+                "comp" => naga::ShaderStage::Compute,
+                _ => {
+                    return Err(shader_error(format!(
+                        "unknown shader extension .{extension}"
+                    )))
+                }
+            };
+            let module = naga::front::glsl::Frontend::default()
+                .parse(&naga::front::glsl::Options::from(stage), &source)
+                .map_err(|err| shader_error(err.emit_to_string_with_path(&source, &path_name)))?;
+            (module, naga::back::spv::WriterFlags::empty())
+        }
+        None => return Err(shader_error("missing shader extension".to_string())),
+    };
+
+    // immediates are what naga calls push constants
+    let info = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::default() | naga::valid::Capabilities::IMMEDIATES,
+    )
+    .validate(&module)
+    .map_err(|err| shader_error(err.emit_to_string_with_path(&source, &path_name)))?;
+
+    let options = naga::back::spv::Options {
+        flags: flags | naga::back::spv::WriterFlags::LABEL_VARYINGS,
+        ..Default::default()
+    };
+    naga::back::spv::write_vec(&module, &info, &options, None)
+        .map_err(|err| shader_error(err.to_string()))
+}
+
+/// loads shaders from a directory at runtime and keeps track of their modification times, so
+/// pipelines using them can be rebuilt when they change (see `reload_pipelines`)
+pub struct ShaderManager {
+    pub root: PathBuf,
+    pub hot_reload: bool,
+    watched: HashMap<PathBuf, Option<SystemTime>>,
+    last_poll: Instant,
+}
+
+impl ShaderManager {
+    pub fn new(root: impl Into<PathBuf>, hot_reload: bool) -> ShaderManager {
+        ShaderManager {
+            root: root.into(),
+            hot_reload,
+            watched: HashMap::new(),
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// compiles `path` (relative to the root) and watches it for changes
+    pub fn load(
+        &mut self,
+        stage: vk::ShaderStageFlags,
+        path: impl AsRef<Path>,
+    ) -> Result<ShaderStage, EngineError> {
+        let path = self.root.join(path);
+        self.watch(&path);
+        let code = compile_file(&path)?;
+        Ok(ShaderStage::new(stage, code).source(path))
+    }
+
+    /// like `load`, but falls back to `builtin` (e.g. from `include_glsl!`) when the file is
+    /// missing or doesn't compile. the file is still watched, so creating or fixing it swaps
+    /// the builtin out
+    pub fn load_or_builtin(
+        &mut self,
+        stage: vk::ShaderStageFlags,
+        path: impl AsRef<Path>,
+        builtin: &'static [u32],
+    ) -> ShaderStage {
+        let full_path = self.root.join(&path);
+        if !full_path.exists() {
+            self.watch(&full_path);
+            return ShaderStage::new(stage, builtin).source(full_path);
+        }
+        match self.load(stage, path) {
+            Ok(shader_stage) => shader_stage,
+            Err(err) => {
+                eprintln!("[Shader] {err}\nusing the builtin version instead");
+                ShaderStage::new(stage, builtin).source(full_path)
+            }
+        }
+    }
+
+    fn watch(&mut self, path: &Path) {
+        let modified = modified_time(path);
+        self.watched.insert(path.to_path_buf(), modified);
+    }
+
+    /// the watched files that changed since the last call, checked at most every
+    /// `POLL_INTERVAL`
+    pub fn changed_files(&mut self) -> Vec<PathBuf> {
+        if !self.hot_reload || self.last_poll.elapsed() < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+
+        let mut changed = Vec::new();
+        for (path, last_modified) in &mut self.watched {
+            let modified = modified_time(path);
+            if modified != *last_modified {
+                *last_modified = modified;
+                changed.push(path.clone());
+            }
+        }
+        changed
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// recompiles the changed shaders and rebuilds only the pipelines using them. a shader that
+/// doesn't compile (or a pipeline that can't be built) is reported and the last good pipeline
+/// stays in use. only vulkan errors while waiting for the device are returned
+pub fn reload_pipelines(
+    logical_device: &ash::Device,
+    shaders: &mut ShaderManager,
+    layout_cache: &mut DescriptorLayoutCache,
+    pipelines: &mut [Pipeline],
+) -> Result<(), EngineError> {
+    let changed = shaders.changed_files();
+    if changed.is_empty() {
+        return Ok(());
+    }
+
+    // every changed file is compiled once, even if several pipelines use it
+    let mut compiled: HashMap<PathBuf, Option<Cow<'static, [u32]>>> = HashMap::new();
+    for path in &changed {
+        let code = match compile_file(path) {
+            Ok(code) => {
+                println!("[Shader] reloaded {}", path.display());
+                Some(Cow::Owned(code))
+            }
+            Err(err) => {
+                eprintln!("[Shader] {err}\nkeeping the last good pipeline");
+                None
+            }
+        };
+        compiled.insert(path.clone(), code);
+    }
+
+    let mut device_idle = false;
+    for pipeline in pipelines {
+        let mut builder = pipeline.builder.clone();
+        let mut affected = false;
+        let mut failed = false;
+        for stage in &mut builder.stages {
+            let Some(code) = stage.source.as_ref().and_then(|path| compiled.get(path)) else {
+                continue;
+            };
+            affected = true;
+            match code {
+                Some(code) => stage.code = code.clone(),
+                None => failed = true,
+            }
+        }
+        if !affected || failed {
+            continue;
+        }
+
+        match builder.build(
+            logical_device,
+            layout_cache,
+            pipeline.render_pass,
+            pipeline.subpass,
+        ) {
+            Ok(new_pipeline) => {
+                // frames in flight might still use the old pipeline
+                if !device_idle {
+                    unsafe { logical_device.device_wait_idle() }.call("device_wait_idle")?;
+                    device_idle = true;
+                }
+                let mut old_pipeline = std::mem::replace(pipeline, new_pipeline);
+                unsafe { old_pipeline.cleanup(logical_device) };
+            }
+            Err(err) => eprintln!("[Shader] rebuilding a pipeline failed: {err}"),
+        }
+    }
+    Ok(())
+}
@@ -1,34 +1,77 @@
+use std::ffi;
+
 use ash::vk;
-use winit::platform::unix::WindowExtUnix;
+use raw_window_handle::{
+    HasRawDisplayHandle, HasRawWindowHandle, RawDisplayHandle, RawWindowHandle,
+};
+
+use super::error::{EngineError, VkResultExt};
 
 pub struct Surfaces {
-    pub xlib_surface_loader: ash::extensions::khr::XlibSurface,
     pub surface: vk::SurfaceKHR,
     pub surface_loader: ash::extensions::khr::Surface,
 }
 
+/// the instance extensions needed to create a surface for this kind of display, only the
+/// platform specific one that matches the handle is returned next to `VK_KHR_surface`
+pub fn required_extensions(
+    display_handle: RawDisplayHandle,
+) -> Result<[&'static ffi::CStr; 2], EngineError> {
+    let platform_extension = match display_handle {
+        RawDisplayHandle::Xlib(_) => ash::extensions::khr::XlibSurface::name(),
+        RawDisplayHandle::Xcb(_) => ash::extensions::khr::XcbSurface::name(),
+        RawDisplayHandle::Wayland(_) => ash::extensions::khr::WaylandSurface::name(),
+        other => {
+            return Err(EngineError::Surface(format!(
+                "unsupported display handle {other:?}"
+            )))
+        }
+    };
+    Ok([ash::extensions::khr::Surface::name(), platform_extension])
+}
+
 impl Surfaces {
-    pub fn init(
-        window: &winit::window::Window,
+    /// the window has to outlive the returned surface
+    pub fn init<W: HasRawWindowHandle + HasRawDisplayHandle>(
+        window: &W,
         entry: &ash::Entry,
         instance: &ash::Instance,
-    ) -> Result<Surfaces, vk::Result> {
-        let x11_display = window.xlib_display().unwrap();
-        let x11_window = window.xlib_window().unwrap();
-        let x11_create_info = vk::XlibSurfaceCreateInfoKHR::builder()
-            .window(x11_window)
-            .dpy(x11_display as *mut vk::Display);
-        let xlib_surface_loader = ash::extensions::khr::XlibSurface::new(entry, instance);
-        let surface = unsafe {
-            xlib_surface_loader
-                .create_xlib_surface(&x11_create_info, None)
-                .unwrap()
+    ) -> Result<Surfaces, EngineError> {
+        let surface = match (window.raw_display_handle(), window.raw_window_handle()) {
+            (RawDisplayHandle::Xlib(display), RawWindowHandle::Xlib(window)) => {
+                let create_info = vk::XlibSurfaceCreateInfoKHR::builder()
+                    .dpy(display.display as *mut vk::Display)
+                    .window(window.window);
+                let loader = ash::extensions::khr::XlibSurface::new(entry, instance);
+                unsafe { loader.create_xlib_surface(&create_info, None) }
+                    .call("create_xlib_surface")?
+            }
+            (RawDisplayHandle::Xcb(display), RawWindowHandle::Xcb(window)) => {
+                let create_info = vk::XcbSurfaceCreateInfoKHR::builder()
+                    .connection(display.connection as *mut vk::xcb_connection_t)
+                    .window(window.window);
+                let loader = ash::extensions::khr::XcbSurface::new(entry, instance);
+                unsafe { loader.create_xcb_surface(&create_info, None) }
+                    .call("create_xcb_surface")?
+            }
+            (RawDisplayHandle::Wayland(display), RawWindowHandle::Wayland(window)) => {
+                let create_info = vk::WaylandSurfaceCreateInfoKHR::builder()
+                    .display(display.display as *mut vk::wl_display)
+                    .surface(window.surface as *mut vk::wl_surface);
+                let loader = ash::extensions::khr::WaylandSurface::new(entry, instance);
+                unsafe { loader.create_wayland_surface(&create_info, None) }
+                    .call("create_wayland_surface")?
+            }
+            (display, window) => {
+                return Err(EngineError::Surface(format!(
+                    "unsupported window handle {window:?} on display {display:?}"
+                )))
+            }
         };
 
         let surface_loader = ash::extensions::khr::Surface::new(entry, instance);
 
         Ok(Surfaces {
-            xlib_surface_loader,
             surface,
             surface_loader,
         })
@@ -47,17 +90,18 @@ impl Surfaces {
     pub fn get_capabilities(
         &self,
         physical_device: vk::PhysicalDevice,
-    ) -> Result<vk::SurfaceCapabilitiesKHR, vk::Result> {
+    ) -> Result<vk::SurfaceCapabilitiesKHR, EngineError> {
         unsafe {
             self.surface_loader
                 .get_physical_device_surface_capabilities(physical_device, self.surface)
         }
+        .call("get_physical_device_surface_capabilities")
     }
     pub fn check_support(
         &self,
         physical_device: vk::PhysicalDevice,
         queue_family_index: usize,
-    ) -> Result<bool, vk::Result> {
+    ) -> Result<bool, EngineError> {
         unsafe {
             self.surface_loader.get_physical_device_surface_support(
                 physical_device,
@@ -65,24 +109,27 @@ impl Surfaces {
                 self.surface,
             )
         }
+        .call("get_physical_device_surface_support")
     }
     pub fn get_formats(
         &self,
         physical_device: vk::PhysicalDevice,
-    ) -> Result<Vec<vk::SurfaceFormatKHR>, vk::Result> {
+    ) -> Result<Vec<vk::SurfaceFormatKHR>, EngineError> {
         unsafe {
             self.surface_loader
                 .get_physical_device_surface_formats(physical_device, self.surface)
         }
+        .call("get_physical_device_surface_formats")
     }
 
     pub fn get_present_modes(
         &self,
         physical_device: vk::PhysicalDevice,
-    ) -> Result<Vec<vk::PresentModeKHR>, vk::Result> {
+    ) -> Result<Vec<vk::PresentModeKHR>, EngineError> {
         unsafe {
             self.surface_loader
                 .get_physical_device_surface_present_modes(physical_device, self.surface)
         }
+        .call("get_physical_device_surface_present_modes")
     }
 }
@@ -1,66 +1,143 @@
 use ash::vk;
 
 use super::{
-    queue::{QueueFamilies, Queues},
+    error::{EngineError, VkResultExt},
+    queue::QueueFamilies,
     surface::Surfaces,
 };
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PresentPolicy {
+    /// `FIFO`, always available
+    #[default]
+    VSync,
+    /// `MAILBOX`, falls back to `FIFO`
+    LowLatency,
+    /// `IMMEDIATE`, falls back to `MAILBOX` and then `FIFO`
+    Uncapped,
+    /// `FIFO_RELAXED`, falls back to `FIFO`
+    FifoRelaxed,
+}
+
+impl PresentPolicy {
+    fn preferred_modes(self) -> &'static [vk::PresentModeKHR] {
+        use vk::PresentModeKHR as Mode;
+        match self {
+            PresentPolicy::VSync => &[Mode::FIFO],
+            PresentPolicy::LowLatency => &[Mode::MAILBOX, Mode::FIFO],
+            PresentPolicy::Uncapped => &[Mode::IMMEDIATE, Mode::MAILBOX, Mode::FIFO],
+            PresentPolicy::FifoRelaxed => &[Mode::FIFO_RELAXED, Mode::FIFO],
+        }
+    }
+}
+
+/// 8-bit srgb formats, used when none of the preferred formats is available
+pub const DEFAULT_SURFACE_FORMATS: [vk::SurfaceFormatKHR; 2] = [
+    vk::SurfaceFormatKHR {
+        format: vk::Format::B8G8R8A8_SRGB,
+        color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+    },
+    vk::SurfaceFormatKHR {
+        format: vk::Format::R8G8B8A8_SRGB,
+        color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+    },
+];
+
+/// what we'd like the swapchain to look like, negotiated against the surface every time it's
+/// (re)created
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwapChainConfig {
+    pub present_policy: PresentPolicy,
+    /// clamped to what the surface supports
+    pub image_count: u32,
+    /// tried in order before `DEFAULT_SURFACE_FORMATS`, e.g. a `UNORM` format for shaders that
+    /// do the gamma correction themselves
+    pub surface_formats: Vec<vk::SurfaceFormatKHR>,
+}
+
+impl Default for SwapChainConfig {
+    fn default() -> Self {
+        SwapChainConfig {
+            present_policy: PresentPolicy::default(),
+            image_count: 3,
+            surface_formats: Vec::new(),
+        }
+    }
+}
+
 pub struct SwapChain {
     pub swapchain_loader: ash::extensions::khr::Swapchain,
     pub swapchain: vk::SwapchainKHR,
     pub images: Vec<vk::Image>,
     pub image_views: Vec<vk::ImageView>,
-    pub framebuffers: Vec<vk::Framebuffer>,
+    /// signalled when rendering to the image with the same index is done, the present waits on
+    /// it. a frame's own semaphore could be signalled again while an earlier present of that
+    /// frame is still waiting, once there are more images than frames in flight
+    pub render_finished: Vec<vk::Semaphore>,
     pub surface_format: vk::SurfaceFormatKHR,
     pub extent: vk::Extent2D,
+    pub present_mode: vk::PresentModeKHR,
 }
 
 impl SwapChain {
+    #[allow(clippy::too_many_arguments)]
     pub fn init(
         instance: &ash::Instance,
         physical_device: vk::PhysicalDevice,
         logical_device: &ash::Device,
         surfaces: &Surfaces,
         queue_families: &QueueFamilies,
-        queues: &Queues,
-    ) -> Result<SwapChain, vk::Result> {
-        let surface_capabilities = surfaces.get_capabilities(physical_device).unwrap();
-        let surface_format = *surfaces
-            .get_formats(physical_device)
-            .unwrap()
-            .first()
-            .unwrap();
-        let extent = surface_capabilities.current_extent;
-        let queue_families = [queue_families.graphics_queue_index.unwrap()];
+        window_extent: vk::Extent2D,
+        swapchain_config: &SwapChainConfig,
+        old_swapchain: vk::SwapchainKHR,
+    ) -> Result<SwapChain, EngineError> {
+        let surface_capabilities = surfaces.get_capabilities(physical_device)?;
+        let surface_format = choose_surface_format(
+            &swapchain_config.surface_formats,
+            &surfaces.get_formats(physical_device)?,
+        )?;
+        let present_mode = choose_present_mode(
+            swapchain_config.present_policy,
+            &surfaces.get_present_modes(physical_device)?,
+        );
+        let extent = choose_extent(&surface_capabilities, window_extent);
+        let graphics_family = queue_families.graphics()?;
+        let present_family = queue_families.present()?;
+        // images are shared instead of transferring ownership between the families every frame
+        let (sharing_mode, queue_family_indices) = if graphics_family == present_family {
+            (vk::SharingMode::EXCLUSIVE, vec![graphics_family])
+        } else {
+            (
+                vk::SharingMode::CONCURRENT,
+                vec![graphics_family, present_family],
+            )
+        };
 
         // create swap chains
         let swapchain_create_info = vk::SwapchainCreateInfoKHR::builder()
             .surface(surfaces.surface)
-            .min_image_count(match surface_capabilities.max_image_count {
-                0 => 3.max(surface_capabilities.min_image_count), // 0 == infinite (see https://registry.khronos.org/vulkan/specs/1.3-extensions/man/html/VkSurfaceCapabilities2EXT.html)
-                _ => 3
-                    .max(surface_capabilities.min_image_count)
-                    .max(surface_capabilities.max_image_count),
-            })
+            .min_image_count(choose_image_count(
+                &surface_capabilities,
+                swapchain_config.image_count,
+            ))
             .image_format(surface_format.format)
             .image_color_space(surface_format.color_space)
-            .image_extent(surface_capabilities.current_extent)
+            .image_extent(extent)
             .image_array_layers(1)
             .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
-            .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
-            .queue_family_indices(&queue_families)
+            .image_sharing_mode(sharing_mode)
+            .queue_family_indices(&queue_family_indices)
             .pre_transform(surface_capabilities.current_transform)
             .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
-            .present_mode(vk::PresentModeKHR::FIFO);
+            .present_mode(present_mode)
+            .old_swapchain(old_swapchain);
 
         let swapchain_loader = ash::extensions::khr::Swapchain::new(instance, logical_device);
-        let swapchain = unsafe {
-            swapchain_loader
-                .create_swapchain(&swapchain_create_info, None)
-                .unwrap()
-        };
+        let swapchain = unsafe { swapchain_loader.create_swapchain(&swapchain_create_info, None) }
+            .call("create_swapchain")?;
 
-        let swapchain_images = unsafe { swapchain_loader.get_swapchain_images(swapchain).unwrap() };
+        let swapchain_images = unsafe { swapchain_loader.get_swapchain_images(swapchain) }
+            .call("get_swapchain_images")?;
 
         let mut swapchain_image_views = Vec::with_capacity(swapchain_images.len());
 
@@ -75,59 +152,146 @@ impl SwapChain {
             let image_view_create_info = vk::ImageViewCreateInfo::builder()
                 .image(*image)
                 .view_type(vk::ImageViewType::TYPE_2D)
-                .format(vk::Format::B8G8R8A8_SRGB)
+                .format(surface_format.format)
                 .subresource_range(*subresource_range);
-            let image_view = unsafe {
-                logical_device
-                    .create_image_view(&image_view_create_info, None)
-                    .unwrap()
-            };
+            let image_view =
+                unsafe { logical_device.create_image_view(&image_view_create_info, None) }
+                    .call("create_image_view")?;
             swapchain_image_views.push(image_view);
         }
 
+        let semaphore_info = vk::SemaphoreCreateInfo::builder();
+        let render_finished = swapchain_images
+            .iter()
+            .map(|_| {
+                unsafe { logical_device.create_semaphore(&semaphore_info, None) }
+                    .call("create_semaphore")
+            })
+            .collect::<Result<_, _>>()?;
+
         Ok(SwapChain {
-            framebuffers: Vec::new(),
             surface_format,
             extent,
+            present_mode,
             swapchain,
             swapchain_loader,
             image_views: swapchain_image_views,
+            render_finished,
             images: swapchain_images,
         })
     }
 
-    pub fn create_framebuffers(
+    /// prints the negotiated format and present mode
+    pub fn report(&self) {
+        println!(
+            "[Swapchain] {}x{}, {:?} {:?}, {:?}, {} images",
+            self.extent.width,
+            self.extent.height,
+            self.surface_format.format,
+            self.surface_format.color_space,
+            self.present_mode,
+            self.images.len()
+        );
+    }
+
+    /// builds a new swapchain from the current surface state, handing the old one over as
+    /// `old_swapchain` and destroying it afterwards. the caller has to make sure the device is
+    /// idle, since the old image views are destroyed as well
+    #[allow(clippy::too_many_arguments)]
+    pub fn recreate(
         &mut self,
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
         logical_device: &ash::Device,
-        render_pass: vk::RenderPass,
-    ) -> Result<(), vk::Result> {
-        for iv in &self.image_views {
-            let image_view = [*iv];
-            let framebuffer_info = vk::FramebufferCreateInfo::builder()
-                .render_pass(render_pass)
-                .attachments(&image_view)
-                .width(self.extent.width)
-                .height(self.extent.height)
-                .layers(1);
-            let fb = unsafe {
-                logical_device
-                    .create_framebuffer(&framebuffer_info, None)
-                    .unwrap()
-            };
-            self.framebuffers.push(fb);
-        }
-
+        surfaces: &Surfaces,
+        queue_families: &QueueFamilies,
+        window_extent: vk::Extent2D,
+        swapchain_config: &SwapChainConfig,
+    ) -> Result<(), EngineError> {
+        let new_swapchain = SwapChain::init(
+            instance,
+            physical_device,
+            logical_device,
+            surfaces,
+            queue_families,
+            window_extent,
+            swapchain_config,
+            self.swapchain,
+        )?;
+        let mut old_swapchain = std::mem::replace(self, new_swapchain);
+        unsafe { old_swapchain.cleanup(logical_device) };
         Ok(())
     }
 
+    /// # Safety
+    /// the device must be idle, or at least done with every image of this swapchain
     pub unsafe fn cleanup(&mut self, logical_device: &ash::Device) {
-        for frame_buffer in &self.framebuffers {
-            logical_device.destroy_framebuffer(*frame_buffer, None);
-        }
         for image_view in &self.image_views {
             logical_device.destroy_image_view(*image_view, None);
         }
+        for semaphore in &self.render_finished {
+            logical_device.destroy_semaphore(*semaphore, None);
+        }
         self.swapchain_loader
             .destroy_swapchain(self.swapchain, None);
     }
 }
+
+/// the surface dictates the extent unless `current_extent` is the special value 0xFFFFFFFF, in
+/// which case the window size is used (clamped to what the surface allows)
+pub fn choose_extent(
+    surface_capabilities: &vk::SurfaceCapabilitiesKHR,
+    window_extent: vk::Extent2D,
+) -> vk::Extent2D {
+    if surface_capabilities.current_extent.width != u32::MAX {
+        return surface_capabilities.current_extent;
+    }
+    let min = surface_capabilities.min_image_extent;
+    let max = surface_capabilities.max_image_extent;
+    vk::Extent2D {
+        width: window_extent.width.clamp(min.width, max.width),
+        height: window_extent.height.clamp(min.height, max.height),
+    }
+}
+
+/// the first of `preferred` (then `DEFAULT_SURFACE_FORMATS`) the surface supports, otherwise
+/// whatever the surface lists first
+pub fn choose_surface_format(
+    preferred: &[vk::SurfaceFormatKHR],
+    available: &[vk::SurfaceFormatKHR],
+) -> Result<vk::SurfaceFormatKHR, EngineError> {
+    let mut candidates = preferred.iter().chain(DEFAULT_SURFACE_FORMATS.iter());
+    // a single UNDEFINED entry means the surface has no preference at all
+    if let [only] = available {
+        if only.format == vk::Format::UNDEFINED {
+            return Ok(*candidates.next().expect("the defaults are never empty"));
+        }
+    }
+    candidates
+        .find(|candidate| available.contains(candidate))
+        .or_else(|| available.first())
+        .copied()
+        .ok_or_else(|| EngineError::Surface("no surface formats available".to_string()))
+}
+
+/// the first mode of the policy that the surface supports, `FIFO` is guaranteed to exist
+pub fn choose_present_mode(
+    present_policy: PresentPolicy,
+    available_modes: &[vk::PresentModeKHR],
+) -> vk::PresentModeKHR {
+    present_policy
+        .preferred_modes()
+        .iter()
+        .copied()
+        .find(|mode| available_modes.contains(mode))
+        .unwrap_or(vk::PresentModeKHR::FIFO)
+}
+
+/// a `max_image_count` of 0 means there is no upper limit
+pub fn choose_image_count(surface_capabilities: &vk::SurfaceCapabilitiesKHR, desired: u32) -> u32 {
+    let count = desired.max(surface_capabilities.min_image_count);
+    match surface_capabilities.max_image_count {
+        0 => count,
+        max => count.min(max),
+    }
+}
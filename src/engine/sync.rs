@@ -0,0 +1,35 @@
+use ash::vk;
+
+use super::error::{EngineError, VkResultExt};
+
+/// the per-frame half of the synchronization, what a present waits on belongs to the swapchain
+/// image instead (see `SwapChain::render_finished`)
+pub struct SyncObjects {
+    pub image_available: vk::Semaphore,
+    pub in_flight: vk::Fence,
+}
+
+impl SyncObjects {
+    pub fn init(logical_device: &ash::Device) -> Result<SyncObjects, EngineError> {
+        let semaphore_info = vk::SemaphoreCreateInfo::builder();
+        // start signaled so the first frame doesn't wait forever
+        let fence_info = vk::FenceCreateInfo::builder().flags(vk::FenceCreateFlags::SIGNALED);
+
+        let image_available = unsafe { logical_device.create_semaphore(&semaphore_info, None) }
+            .call("create_semaphore")?;
+        let in_flight =
+            unsafe { logical_device.create_fence(&fence_info, None) }.call("create_fence")?;
+
+        Ok(SyncObjects {
+            image_available,
+            in_flight,
+        })
+    }
+
+    /// # Safety
+    /// the semaphores and the fence must not be in use by any pending submission
+    pub unsafe fn cleanup(&self, logical_device: &ash::Device) {
+        logical_device.destroy_semaphore(self.image_available, None);
+        logical_device.destroy_fence(self.in_flight, None);
+    }
+}
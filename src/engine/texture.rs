@@ -0,0 +1,327 @@
+use std::path::Path;
+
+use ash::vk;
+
+use super::{
+    allocator::{Allocation, Allocator, MemoryLocation},
+    error::{EngineError, VkResultExt},
+    queue::Queues,
+    upload::Uploader,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextureConfig {
+    /// colors are stored in srgb, turn it off for data like normal maps
+    pub srgb: bool,
+    /// only generated if the format supports linear blits
+    pub mipmaps: bool,
+}
+
+impl Default for TextureConfig {
+    fn default() -> Self {
+        TextureConfig {
+            srgb: true,
+            mipmaps: true,
+        }
+    }
+}
+
+impl TextureConfig {
+    pub fn srgb(mut self, srgb: bool) -> Self {
+        self.srgb = srgb;
+        self
+    }
+
+    pub fn mipmaps(mut self, mipmaps: bool) -> Self {
+        self.mipmaps = mipmaps;
+        self
+    }
+
+    pub fn format(&self) -> vk::Format {
+        if self.srgb {
+            vk::Format::R8G8B8A8_SRGB
+        } else {
+            vk::Format::R8G8B8A8_UNORM
+        }
+    }
+}
+
+/// a sampled 2d rgba8 image with a view over all of its mip levels, ready to be bound as a
+/// `COMBINED_IMAGE_SAMPLER` together with a sampler from `create_sampler`
+pub struct Texture {
+    pub image: vk::Image,
+    pub view: vk::ImageView,
+    pub allocation: Allocation,
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+    pub mip_levels: u32,
+}
+
+impl Texture {
+    /// decodes a png, jpeg or tga file to rgba8 and uploads it
+    #[allow(clippy::too_many_arguments)]
+    pub fn load<P: AsRef<Path>>(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        logical_device: &ash::Device,
+        allocator: &mut Allocator,
+        uploader: &mut Uploader,
+        queues: &Queues,
+        path: P,
+        config: &TextureConfig,
+    ) -> Result<Texture, EngineError> {
+        let pixels = image::open(path)?.into_rgba8();
+        let extent = vk::Extent2D {
+            width: pixels.width(),
+            height: pixels.height(),
+        };
+        Texture::from_rgba8(
+            instance,
+            physical_device,
+            logical_device,
+            allocator,
+            uploader,
+            queues,
+            &pixels,
+            extent,
+            config,
+        )
+    }
+
+    /// `pixels` are tightly packed rows, top row first. the copy is only submitted here, like
+    /// `Uploader::upload_buffer`
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_rgba8(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        logical_device: &ash::Device,
+        allocator: &mut Allocator,
+        uploader: &mut Uploader,
+        queues: &Queues,
+        pixels: &[u8],
+        extent: vk::Extent2D,
+        config: &TextureConfig,
+    ) -> Result<Texture, EngineError> {
+        if extent.width == 0 || extent.height == 0 {
+            return Err(EngineError::Texture(format!(
+                "{}x{} is empty",
+                extent.width, extent.height
+            )));
+        }
+        let expected = extent.width as usize * extent.height as usize * 4;
+        if pixels.len() != expected {
+            return Err(EngineError::Texture(format!(
+                "expected {expected} bytes of tightly packed rgba8 pixels for {}x{}, got {}",
+                extent.width,
+                extent.height,
+                pixels.len()
+            )));
+        }
+        let format = config.format();
+        let mip_levels =
+            if config.mipmaps && supports_linear_blit(instance, physical_device, format) {
+                mip_levels(extent)
+            } else {
+                1
+            };
+
+        let mut usage = vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED;
+        if mip_levels > 1 {
+            usage |= vk::ImageUsageFlags::TRANSFER_SRC;
+        }
+        let image_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(format)
+            .extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            })
+            .mip_levels(mip_levels)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+        let (image, allocation) =
+            allocator.create_image(logical_device, &image_info, MemoryLocation::GpuOnly)?;
+
+        let mut texture = Texture {
+            image,
+            view: vk::ImageView::null(),
+            allocation,
+            format,
+            extent,
+            mip_levels,
+        };
+        let result = uploader
+            .upload_image(
+                logical_device,
+                allocator,
+                queues,
+                pixels,
+                image,
+                extent,
+                mip_levels,
+            )
+            .and_then(|()| {
+                let view_info = vk::ImageViewCreateInfo::builder()
+                    .image(image)
+                    .view_type(vk::ImageViewType::TYPE_2D)
+                    .format(format)
+                    .subresource_range(vk::ImageSubresourceRange {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        base_mip_level: 0,
+                        level_count: mip_levels,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    });
+                unsafe { logical_device.create_image_view(&view_info, None) }
+                    .call("create_image_view")
+            });
+        match result {
+            Ok(view) => {
+                texture.view = view;
+                Ok(texture)
+            }
+            Err(err) => {
+                unsafe {
+                    // the upload might already be in flight
+                    let _ = logical_device.device_wait_idle();
+                    texture.cleanup(logical_device, allocator);
+                }
+                Err(err)
+            }
+        }
+    }
+
+    /// # Safety
+    /// the gpu must be done with the texture
+    pub unsafe fn cleanup(&mut self, logical_device: &ash::Device, allocator: &mut Allocator) {
+        logical_device.destroy_image_view(self.view, None);
+        allocator.destroy_image(
+            logical_device,
+            self.image,
+            std::mem::take(&mut self.allocation),
+        );
+    }
+}
+
+/// enough levels to go down to 1x1
+pub fn mip_levels(extent: vk::Extent2D) -> u32 {
+    32 - extent.width.max(extent.height).max(1).leading_zeros()
+}
+
+/// mipmaps are blitted with a linear filter, which not every format supports
+pub fn supports_linear_blit(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    format: vk::Format,
+) -> bool {
+    let properties =
+        unsafe { instance.get_physical_device_format_properties(physical_device, format) };
+    properties.optimal_tiling_features.contains(
+        vk::FormatFeatureFlags::BLIT_SRC
+            | vk::FormatFeatureFlags::BLIT_DST
+            | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR,
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplerConfig {
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
+    /// the same for u, v and w
+    pub address_mode: vk::SamplerAddressMode,
+    pub border_color: vk::BorderColor,
+    /// clamped to `limits.max_sampler_anisotropy`, ignored without the `sampler_anisotropy`
+    /// feature. `None` disables it
+    pub max_anisotropy: Option<f32>,
+    pub max_lod: f32,
+}
+
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        SamplerConfig {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            address_mode: vk::SamplerAddressMode::REPEAT,
+            border_color: vk::BorderColor::INT_OPAQUE_BLACK,
+            max_anisotropy: Some(16.0),
+            max_lod: vk::LOD_CLAMP_NONE,
+        }
+    }
+}
+
+impl SamplerConfig {
+    /// no filtering at all, e.g. for pixel art
+    pub fn nearest() -> Self {
+        SamplerConfig {
+            mag_filter: vk::Filter::NEAREST,
+            min_filter: vk::Filter::NEAREST,
+            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+            max_anisotropy: None,
+            ..Default::default()
+        }
+    }
+
+    pub fn filter(mut self, mag_filter: vk::Filter, min_filter: vk::Filter) -> Self {
+        self.mag_filter = mag_filter;
+        self.min_filter = min_filter;
+        self
+    }
+
+    pub fn mipmap_mode(mut self, mipmap_mode: vk::SamplerMipmapMode) -> Self {
+        self.mipmap_mode = mipmap_mode;
+        self
+    }
+
+    pub fn address_mode(mut self, address_mode: vk::SamplerAddressMode) -> Self {
+        self.address_mode = address_mode;
+        self
+    }
+
+    pub fn border_color(mut self, border_color: vk::BorderColor) -> Self {
+        self.border_color = border_color;
+        self
+    }
+
+    pub fn max_anisotropy(mut self, max_anisotropy: Option<f32>) -> Self {
+        self.max_anisotropy = max_anisotropy;
+        self
+    }
+
+    pub fn max_lod(mut self, max_lod: f32) -> Self {
+        self.max_lod = max_lod;
+        self
+    }
+}
+
+/// `features` are the ones the device was created with
+pub fn create_sampler(
+    logical_device: &ash::Device,
+    config: &SamplerConfig,
+    limits: &vk::PhysicalDeviceLimits,
+    features: &vk::PhysicalDeviceFeatures,
+) -> Result<vk::Sampler, EngineError> {
+    let anisotropy = config
+        .max_anisotropy
+        .filter(|_| features.sampler_anisotropy == vk::TRUE)
+        .map(|anisotropy| anisotropy.clamp(1.0, limits.max_sampler_anisotropy));
+    let sampler_info = vk::SamplerCreateInfo::builder()
+        .mag_filter(config.mag_filter)
+        .min_filter(config.min_filter)
+        .mipmap_mode(config.mipmap_mode)
+        .address_mode_u(config.address_mode)
+        .address_mode_v(config.address_mode)
+        .address_mode_w(config.address_mode)
+        .anisotropy_enable(anisotropy.is_some())
+        .max_anisotropy(anisotropy.unwrap_or(1.0))
+        .border_color(config.border_color)
+        .min_lod(0.0)
+        .max_lod(config.max_lod);
+    unsafe { logical_device.create_sampler(&sampler_info, None) }.call("create_sampler")
+}
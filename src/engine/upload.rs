@@ -0,0 +1,542 @@
+use ash::vk;
+
+use super::{
+    allocator::{Allocator, MemoryLocation},
+    buffer::{self, Buffer, BufferUsage, Pod},
+    command,
+    error::{EngineError, VkResultExt},
+    queue::{QueueFamilies, Queues},
+};
+
+/// fills `GpuOnly` buffers through a staging buffer on the transfer queue. when the transfer
+/// family differs from the graphics family, ownership is released on the transfer queue and
+/// acquired on the graphics queue after a semaphore, so the graphics queue never waits on the
+/// cpu. the acquire is a barrier on the graphics queue, so buffers can be used by any submit
+/// that comes after `upload_buffer`. images go through the graphics queue, see `upload_image`
+pub struct Uploader {
+    transfer_family: u32,
+    graphics_family: u32,
+    transfer_pool: vk::CommandPool,
+    graphics_pool: vk::CommandPool,
+    pending: Vec<PendingUpload>,
+}
+
+struct PendingUpload {
+    staging: Buffer,
+    // null for images
+    transfer_command_buffer: vk::CommandBuffer,
+    // null if no ownership transfer was needed
+    graphics_command_buffer: vk::CommandBuffer,
+    semaphore: vk::Semaphore,
+    // signaled once the buffer is on the gpu and owned by the graphics family
+    fence: vk::Fence,
+}
+
+impl Uploader {
+    pub fn init(
+        logical_device: &ash::Device,
+        queue_families: &QueueFamilies,
+    ) -> Result<Uploader, EngineError> {
+        let transfer_family = queue_families.transfer()?;
+        let graphics_family = queue_families.graphics()?;
+        let transfer_pool = command::create_command_pool(
+            logical_device,
+            transfer_family,
+            vk::CommandPoolCreateFlags::TRANSIENT,
+        )?;
+        let graphics_pool = command::create_command_pool(
+            logical_device,
+            graphics_family,
+            vk::CommandPoolCreateFlags::TRANSIENT,
+        )?;
+        Ok(Uploader {
+            transfer_family,
+            graphics_family,
+            transfer_pool,
+            graphics_pool,
+            pending: Vec::new(),
+        })
+    }
+
+    /// creates a device local buffer holding `data`. the copy is only submitted here,
+    /// `collect` frees the staging memory once it's done
+    pub fn upload_buffer<T: Pod>(
+        &mut self,
+        logical_device: &ash::Device,
+        allocator: &mut Allocator,
+        queues: &Queues,
+        data: &[T],
+        usage: BufferUsage,
+    ) -> Result<Buffer, EngineError> {
+        let bytes = buffer::as_bytes(data);
+        let size = bytes.len() as u64;
+
+        let mut staging = Buffer::init(
+            logical_device,
+            allocator,
+            size,
+            BufferUsage::Staging,
+            MemoryLocation::CpuToGpu,
+        )?;
+        staging.write(0, bytes);
+        let mut destination = match Buffer::init(
+            logical_device,
+            allocator,
+            size,
+            usage,
+            MemoryLocation::GpuOnly,
+        ) {
+            Ok(destination) => destination,
+            Err(err) => {
+                unsafe { staging.cleanup(logical_device, allocator) };
+                return Err(err);
+            }
+        };
+
+        let mut pending = PendingUpload {
+            staging,
+            transfer_command_buffer: vk::CommandBuffer::null(),
+            graphics_command_buffer: vk::CommandBuffer::null(),
+            semaphore: vk::Semaphore::null(),
+            fence: vk::Fence::null(),
+        };
+        let transfer_ownership = self.transfer_family != self.graphics_family;
+        match self.record_and_submit(
+            logical_device,
+            queues,
+            &mut pending,
+            &destination,
+            transfer_ownership,
+        ) {
+            Ok(()) => {
+                self.pending.push(pending);
+                Ok(destination)
+            }
+            Err(err) => {
+                // part of it might have been submitted already
+                unsafe {
+                    let _ = logical_device.device_wait_idle();
+                    self.destroy_pending(logical_device, allocator, &mut pending);
+                    destination.cleanup(logical_device, allocator);
+                }
+                Err(err)
+            }
+        }
+    }
+
+    fn record_and_submit(
+        &self,
+        logical_device: &ash::Device,
+        queues: &Queues,
+        pending: &mut PendingUpload,
+        destination: &Buffer,
+        transfer_ownership: bool,
+    ) -> Result<(), EngineError> {
+        pending.transfer_command_buffer =
+            command::allocate_command_buffers(logical_device, self.transfer_pool, 1)?[0];
+        pending.fence =
+            unsafe { logical_device.create_fence(&vk::FenceCreateInfo::builder(), None) }
+                .call("create_fence")?;
+
+        // same family: a plain barrier makes the copy visible to the graphics stages.
+        // otherwise the release half of the ownership transfer, whose dst part is ignored
+        let (src_family, dst_family, dst_stage, dst_access) = if transfer_ownership {
+            (
+                self.transfer_family,
+                self.graphics_family,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::AccessFlags::empty(),
+            )
+        } else {
+            (
+                vk::QUEUE_FAMILY_IGNORED,
+                vk::QUEUE_FAMILY_IGNORED,
+                destination.usage.dst_stage(),
+                destination.usage.dst_access(),
+            )
+        };
+        let release = vk::BufferMemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(dst_access)
+            .src_queue_family_index(src_family)
+            .dst_queue_family_index(dst_family)
+            .buffer(destination.buffer)
+            .offset(0)
+            .size(vk::WHOLE_SIZE);
+
+        let command_buffer = pending.transfer_command_buffer;
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        unsafe {
+            logical_device
+                .begin_command_buffer(command_buffer, &begin_info)
+                .call("begin_command_buffer")?;
+            let region = vk::BufferCopy::builder().size(destination.size);
+            logical_device.cmd_copy_buffer(
+                command_buffer,
+                pending.staging.buffer,
+                destination.buffer,
+                &[region.build()],
+            );
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                dst_stage,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[release.build()],
+                &[],
+            );
+            logical_device
+                .end_command_buffer(command_buffer)
+                .call("end_command_buffer")?;
+        }
+
+        let transfer_command_buffers = [command_buffer];
+        if !transfer_ownership {
+            let submit_info = vk::SubmitInfo::builder().command_buffers(&transfer_command_buffers);
+            return unsafe {
+                logical_device.queue_submit(
+                    queues.transfer_queue,
+                    &[submit_info.build()],
+                    pending.fence,
+                )
+            }
+            .call("queue_submit");
+        }
+
+        pending.semaphore =
+            unsafe { logical_device.create_semaphore(&vk::SemaphoreCreateInfo::builder(), None) }
+                .call("create_semaphore")?;
+        pending.graphics_command_buffer =
+            command::allocate_command_buffers(logical_device, self.graphics_pool, 1)?[0];
+
+        // the acquire half has to match the release except for the access masks
+        let acquire = vk::BufferMemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::empty())
+            .dst_access_mask(destination.usage.dst_access())
+            .src_queue_family_index(self.transfer_family)
+            .dst_queue_family_index(self.graphics_family)
+            .buffer(destination.buffer)
+            .offset(0)
+            .size(vk::WHOLE_SIZE);
+        let command_buffer = pending.graphics_command_buffer;
+        unsafe {
+            logical_device
+                .begin_command_buffer(command_buffer, &begin_info)
+                .call("begin_command_buffer")?;
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                destination.usage.dst_stage(),
+                destination.usage.dst_stage(),
+                vk::DependencyFlags::empty(),
+                &[],
+                &[acquire.build()],
+                &[],
+            );
+            logical_device
+                .end_command_buffer(command_buffer)
+                .call("end_command_buffer")?;
+        }
+
+        let semaphores = [pending.semaphore];
+        let transfer_submit = vk::SubmitInfo::builder()
+            .command_buffers(&transfer_command_buffers)
+            .signal_semaphores(&semaphores);
+        unsafe {
+            logical_device.queue_submit(
+                queues.transfer_queue,
+                &[transfer_submit.build()],
+                vk::Fence::null(),
+            )
+        }
+        .call("queue_submit")?;
+
+        let graphics_command_buffers = [command_buffer];
+        // same stage as the acquire barrier, so the semaphore wait and the barrier form a chain
+        let wait_stages = [destination.usage.dst_stage()];
+        let graphics_submit = vk::SubmitInfo::builder()
+            .wait_semaphores(&semaphores)
+            .wait_dst_stage_mask(&wait_stages)
+            .command_buffers(&graphics_command_buffers);
+        unsafe {
+            logical_device.queue_submit(
+                queues.graphics_queue,
+                &[graphics_submit.build()],
+                pending.fence,
+            )
+        }
+        .call("queue_submit")
+    }
+
+    /// copies tightly packed `pixels` into mip 0 of a 2d `image` and blits each following mip
+    /// level from the previous one, leaving every level in `SHADER_READ_ONLY_OPTIMAL`. blits
+    /// need a graphics queue, so this runs on it without any ownership transfer. the image has
+    /// to support linear blits if `mip_levels` > 1
+    #[allow(clippy::too_many_arguments)]
+    pub fn upload_image(
+        &mut self,
+        logical_device: &ash::Device,
+        allocator: &mut Allocator,
+        queues: &Queues,
+        pixels: &[u8],
+        image: vk::Image,
+        extent: vk::Extent2D,
+        mip_levels: u32,
+    ) -> Result<(), EngineError> {
+        let mut staging = Buffer::init(
+            logical_device,
+            allocator,
+            pixels.len() as u64,
+            BufferUsage::Staging,
+            MemoryLocation::CpuToGpu,
+        )?;
+        staging.write(0, pixels);
+
+        let mut pending = PendingUpload {
+            staging,
+            transfer_command_buffer: vk::CommandBuffer::null(),
+            graphics_command_buffer: vk::CommandBuffer::null(),
+            semaphore: vk::Semaphore::null(),
+            fence: vk::Fence::null(),
+        };
+        match self.record_and_submit_image(
+            logical_device,
+            queues,
+            &mut pending,
+            image,
+            extent,
+            mip_levels,
+        ) {
+            Ok(()) => {
+                self.pending.push(pending);
+                Ok(())
+            }
+            Err(err) => {
+                unsafe {
+                    let _ = logical_device.device_wait_idle();
+                    self.destroy_pending(logical_device, allocator, &mut pending);
+                }
+                Err(err)
+            }
+        }
+    }
+
+    fn record_and_submit_image(
+        &self,
+        logical_device: &ash::Device,
+        queues: &Queues,
+        pending: &mut PendingUpload,
+        image: vk::Image,
+        extent: vk::Extent2D,
+        mip_levels: u32,
+    ) -> Result<(), EngineError> {
+        pending.graphics_command_buffer =
+            command::allocate_command_buffers(logical_device, self.graphics_pool, 1)?[0];
+        pending.fence =
+            unsafe { logical_device.create_fence(&vk::FenceCreateInfo::builder(), None) }
+                .call("create_fence")?;
+
+        let command_buffer = pending.graphics_command_buffer;
+        let barrier =
+            |base_mip_level: u32,
+             level_count: u32,
+             (old_layout, src_access): (vk::ImageLayout, vk::AccessFlags),
+             (new_layout, dst_access): (vk::ImageLayout, vk::AccessFlags)| {
+                vk::ImageMemoryBarrier::builder()
+                    .old_layout(old_layout)
+                    .new_layout(new_layout)
+                    .src_access_mask(src_access)
+                    .dst_access_mask(dst_access)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .image(image)
+                    .subresource_range(vk::ImageSubresourceRange {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        base_mip_level,
+                        level_count,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    })
+                    .build()
+            };
+        let undefined = (vk::ImageLayout::UNDEFINED, vk::AccessFlags::empty());
+        let transfer_dst = (
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::AccessFlags::TRANSFER_WRITE,
+        );
+        let transfer_src = (
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::AccessFlags::TRANSFER_READ,
+        );
+        let shader_read = (
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::AccessFlags::SHADER_READ,
+        );
+        let cmd_barrier = |src_stage, dst_stage, barrier: vk::ImageMemoryBarrier| unsafe {
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                src_stage,
+                dst_stage,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier],
+            );
+        };
+        let mip_extent = |level: u32| vk::Offset3D {
+            x: (extent.width >> level).max(1) as i32,
+            y: (extent.height >> level).max(1) as i32,
+            z: 1,
+        };
+        let layers = |mip_level| vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        unsafe {
+            logical_device
+                .begin_command_buffer(command_buffer, &begin_info)
+                .call("begin_command_buffer")?;
+        }
+        cmd_barrier(
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::TRANSFER,
+            barrier(0, mip_levels, undefined, transfer_dst),
+        );
+        let region = vk::BufferImageCopy::builder()
+            .image_subresource(layers(0))
+            .image_extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            });
+        unsafe {
+            logical_device.cmd_copy_buffer_to_image(
+                command_buffer,
+                pending.staging.buffer,
+                image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[region.build()],
+            );
+        }
+
+        // each level is blitted from the one before, which is then done and can be sampled
+        for level in 1..mip_levels {
+            cmd_barrier(
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                barrier(level - 1, 1, transfer_dst, transfer_src),
+            );
+            let blit = vk::ImageBlit::builder()
+                .src_subresource(layers(level - 1))
+                .src_offsets([vk::Offset3D::default(), mip_extent(level - 1)])
+                .dst_subresource(layers(level))
+                .dst_offsets([vk::Offset3D::default(), mip_extent(level)]);
+            unsafe {
+                logical_device.cmd_blit_image(
+                    command_buffer,
+                    image,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[blit.build()],
+                    vk::Filter::LINEAR,
+                );
+            }
+            cmd_barrier(
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                barrier(level - 1, 1, transfer_src, shader_read),
+            );
+        }
+        cmd_barrier(
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            barrier(mip_levels - 1, 1, transfer_dst, shader_read),
+        );
+        unsafe {
+            logical_device
+                .end_command_buffer(command_buffer)
+                .call("end_command_buffer")?;
+        }
+
+        let command_buffers = [command_buffer];
+        let submit_info = vk::SubmitInfo::builder().command_buffers(&command_buffers);
+        unsafe {
+            logical_device.queue_submit(
+                queues.graphics_queue,
+                &[submit_info.build()],
+                pending.fence,
+            )
+        }
+        .call("queue_submit")
+    }
+
+    /// frees the staging buffers and command buffers of finished uploads, returns how many
+    /// uploads are still in flight
+    pub fn collect(
+        &mut self,
+        logical_device: &ash::Device,
+        allocator: &mut Allocator,
+    ) -> Result<usize, EngineError> {
+        let mut index = 0;
+        while index < self.pending.len() {
+            let fence = self.pending[index].fence;
+            if unsafe { logical_device.get_fence_status(fence) }.call("get_fence_status")? {
+                let mut pending = self.pending.swap_remove(index);
+                unsafe { self.destroy_pending(logical_device, allocator, &mut pending) };
+            } else {
+                index += 1;
+            }
+        }
+        Ok(self.pending.len())
+    }
+
+    /// blocks until every upload is on the gpu
+    pub fn wait(
+        &mut self,
+        logical_device: &ash::Device,
+        allocator: &mut Allocator,
+    ) -> Result<(), EngineError> {
+        let fences: Vec<vk::Fence> = self.pending.iter().map(|pending| pending.fence).collect();
+        if !fences.is_empty() {
+            unsafe { logical_device.wait_for_fences(&fences, true, u64::MAX) }
+                .call("wait_for_fences")?;
+        }
+        self.collect(logical_device, allocator).map(|_| ())
+    }
+
+    unsafe fn destroy_pending(
+        &self,
+        logical_device: &ash::Device,
+        allocator: &mut Allocator,
+        pending: &mut PendingUpload,
+    ) {
+        pending.staging.cleanup(logical_device, allocator);
+        for (pool, command_buffer) in [
+            (self.transfer_pool, pending.transfer_command_buffer),
+            (self.graphics_pool, pending.graphics_command_buffer),
+        ] {
+            if command_buffer != vk::CommandBuffer::null() {
+                logical_device.free_command_buffers(pool, &[command_buffer]);
+            }
+        }
+        logical_device.destroy_semaphore(pending.semaphore, None);
+        logical_device.destroy_fence(pending.fence, None);
+    }
+
+    /// # Safety
+    /// the device must be idle
+    pub unsafe fn cleanup(&mut self, logical_device: &ash::Device, allocator: &mut Allocator) {
+        for mut pending in std::mem::take(&mut self.pending) {
+            self.destroy_pending(logical_device, allocator, &mut pending);
+        }
+        logical_device.destroy_command_pool(self.transfer_pool, None);
+        logical_device.destroy_command_pool(self.graphics_pool, None);
+    }
+}
@@ -0,0 +1,104 @@
+use std::mem;
+
+use ash::vk;
+
+use super::buffer::Pod;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VertexAttribute {
+    pub format: vk::Format,
+    pub offset: u32,
+}
+
+/// plain data read by the vertex input stage, either per vertex or per instance. every
+/// attribute takes one location, so a matrix has to be split into its columns
+pub trait Vertex: Pod {
+    /// in location order
+    const ATTRIBUTES: &'static [VertexAttribute];
+}
+
+/// the vertex every built-in pipeline uses, locations 0 to 3
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MeshVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+    pub color: [f32; 4],
+}
+
+// four float fields, nothing to pad
+unsafe impl Pod for MeshVertex {}
+
+impl Vertex for MeshVertex {
+    const ATTRIBUTES: &'static [VertexAttribute] = &[
+        VertexAttribute {
+            format: vk::Format::R32G32B32_SFLOAT,
+            offset: mem::offset_of!(MeshVertex, position) as u32,
+        },
+        VertexAttribute {
+            format: vk::Format::R32G32B32_SFLOAT,
+            offset: mem::offset_of!(MeshVertex, normal) as u32,
+        },
+        VertexAttribute {
+            format: vk::Format::R32G32_SFLOAT,
+            offset: mem::offset_of!(MeshVertex, uv) as u32,
+        },
+        VertexAttribute {
+            format: vk::Format::R32G32B32A32_SFLOAT,
+            offset: mem::offset_of!(MeshVertex, color) as u32,
+        },
+    ];
+}
+
+/// the binding and attribute descriptions of a pipeline, built up binding by binding:
+///
+/// ```ignore
+/// let layout = VertexLayout::default()
+///     .per_vertex::<MeshVertex>()
+///     .per_instance::<InstanceData>();
+/// ```
+///
+/// bindings are numbered in the order they are added, locations continue where the previous
+/// binding stopped
+#[derive(Debug, Clone, Default)]
+pub struct VertexLayout {
+    pub bindings: Vec<vk::VertexInputBindingDescription>,
+    pub attributes: Vec<vk::VertexInputAttributeDescription>,
+}
+
+impl VertexLayout {
+    pub fn per_vertex<V: Vertex>(self) -> Self {
+        self.binding::<V>(vk::VertexInputRate::VERTEX)
+    }
+
+    pub fn per_instance<V: Vertex>(self) -> Self {
+        self.binding::<V>(vk::VertexInputRate::INSTANCE)
+    }
+
+    fn binding<V: Vertex>(mut self, input_rate: vk::VertexInputRate) -> Self {
+        let binding = self.bindings.len() as u32;
+        self.bindings.push(vk::VertexInputBindingDescription {
+            binding,
+            stride: mem::size_of::<V>() as u32,
+            input_rate,
+        });
+        let first_location = self.attributes.len() as u32;
+        self.attributes
+            .extend(V::ATTRIBUTES.iter().enumerate().map(|(index, attribute)| {
+                vk::VertexInputAttributeDescription {
+                    location: first_location + index as u32,
+                    binding,
+                    format: attribute.format,
+                    offset: attribute.offset,
+                }
+            }));
+        self
+    }
+
+    pub fn create_info(&self) -> vk::PipelineVertexInputStateCreateInfoBuilder<'_> {
+        vk::PipelineVertexInputStateCreateInfo::builder()
+            .vertex_binding_descriptions(&self.bindings)
+            .vertex_attribute_descriptions(&self.attributes)
+    }
+}
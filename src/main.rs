@@ -1,23 +1,95 @@
 use winit::event_loop::ControlFlow;
 
-use engine::GameEngine;
+use ash::vk;
+use learning_ash::engine::{
+    config::EngineConfig, error::EngineError, headless::HeadlessEngine, vertex::MeshVertex,
+    GameEngine, DEFAULT_PIPELINE,
+};
 use winit::event::{Event, WindowEvent};
 
-mod engine;
-fn main() {
+const TRIANGLE_VERTICES: [MeshVertex; 3] = [
+    MeshVertex {
+        position: [0.0, -0.5, 0.0],
+        normal: [0.0, 0.0, 1.0],
+        uv: [0.5, 0.0],
+        color: [1.0, 0.0, 0.0, 1.0],
+    },
+    MeshVertex {
+        position: [0.5, 0.5, 0.0],
+        normal: [0.0, 0.0, 1.0],
+        uv: [1.0, 1.0],
+        color: [0.0, 1.0, 0.0, 1.0],
+    },
+    MeshVertex {
+        position: [-0.5, 0.5, 0.0],
+        normal: [0.0, 0.0, 1.0],
+        uv: [0.0, 1.0],
+        color: [0.0, 0.0, 1.0, 1.0],
+    },
+];
+const TRIANGLE_INDICES: [u16; 3] = [0, 1, 2];
+
+fn main() -> Result<(), EngineError> {
+    let mut args = std::env::args().skip(1);
+    if args.next().as_deref() == Some("--headless") {
+        let path = args.next().unwrap_or_else(|| "frame.png".to_string());
+        let mut headless_engine = HeadlessEngine::init(
+            vk::Extent2D {
+                width: 800,
+                height: 600,
+            },
+            EngineConfig::default(),
+        )?;
+        headless_engine.core.upload_mesh(
+            DEFAULT_PIPELINE,
+            &TRIANGLE_VERTICES,
+            &TRIANGLE_INDICES,
+        )?;
+        return headless_engine.save_png(&path);
+    }
+
     let event_loop = winit::event_loop::EventLoop::new();
     let window = winit::window::Window::new(&event_loop).unwrap();
-    let game_engine = GameEngine::init(window).unwrap();
+    let size = window.inner_size();
+    // kept in an option so it can be dropped before the window it renders into
+    let mut engine = GameEngine::init(
+        &window,
+        vk::Extent2D {
+            width: size.width,
+            height: size.height,
+        },
+        EngineConfig::default(),
+    )?;
+    engine
+        .core
+        .upload_mesh(DEFAULT_PIPELINE, &TRIANGLE_VERTICES, &TRIANGLE_INDICES)?;
+    let mut game_engine = Some(engine);
 
-    event_loop.run(move |event, _, control_flow| match event {
-        Event::WindowEvent {
-            event: WindowEvent::CloseRequested,
-            ..
-        } => *control_flow = ControlFlow::Exit,
-        Event::MainEventsCleared => {
-            game_engine.window.request_redraw();
-        }
+    event_loop.run(move |event, _, control_flow| {
+        let Some(engine) = game_engine.as_mut() else {
+            return;
+        };
+        match event {
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            } => *control_flow = ControlFlow::Exit,
+            Event::WindowEvent {
+                event: WindowEvent::Resized(size),
+                ..
+            } => engine.resize(size.width, size.height),
+            Event::MainEventsCleared => {
+                window.request_redraw();
+            }
+            Event::RedrawRequested(_) => {
+                if let Err(err) = engine.draw_frame() {
+                    eprintln!("{err}");
+                    *control_flow = ControlFlow::ExitWithCode(1);
+                }
+            }
+            Event::LoopDestroyed => game_engine = None,
 
-        _ => {}
+            _ => {}
+        }
     });
 }